@@ -0,0 +1,2 @@
+-- Supports keyset pagination of the feed on (published_at, id)
+CREATE INDEX blog_posts_published_at_id_idx ON blog_posts (published_at DESC, id DESC);
@@ -0,0 +1,22 @@
+-- Avatars belong to the user profile instead of being downloaded for every post
+ALTER TABLE users
+    ADD COLUMN avatar_path TEXT,
+    ADD COLUMN avatar_url TEXT;
+
+-- Keep the avatar of each author's most recent post
+UPDATE users
+SET avatar_path = latest.user_avatar_path
+FROM (
+    SELECT DISTINCT ON (user_id) user_id, user_avatar_path
+    FROM blog_posts
+    WHERE user_id IS NOT NULL AND user_avatar_path IS NOT NULL
+    ORDER BY user_id, published_at DESC
+) AS latest
+WHERE users.id = latest.user_id;
+
+-- Posts written before accounts existed have no profile to move their avatar to,
+-- so they keep their own and show it when the post has no author.
+ALTER TABLE blog_posts RENAME COLUMN user_avatar_path TO legacy_avatar_path;
+
+-- Per-post avatar files of posts with an author are no longer referenced
+UPDATE blog_posts SET legacy_avatar_path = NULL WHERE user_id IS NOT NULL;
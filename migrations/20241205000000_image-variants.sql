@@ -0,0 +1,8 @@
+-- Every rendition of a stored image, including the original itself.
+-- Images stored before this migration have no rows and are served without srcset.
+CREATE TABLE image_variants (
+    image_path TEXT NOT NULL,
+    width INTEGER NOT NULL,
+    variant_path TEXT NOT NULL,
+    PRIMARY KEY (image_path, width)
+);
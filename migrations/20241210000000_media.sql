@@ -0,0 +1,24 @@
+-- Images are stored once per content hash and shared by every post that uses them
+CREATE TABLE media (
+    id UUID PRIMARY KEY,
+    -- SHA-256 of the stored bytes, NULL for files uploaded before content addressing
+    hash TEXT UNIQUE,
+    path TEXT NOT NULL UNIQUE,
+    ref_count INTEGER NOT NULL CHECK (ref_count >= 0),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+INSERT INTO media (id, path, ref_count)
+SELECT gen_random_uuid(), image_path, COUNT(*)
+FROM blog_posts
+WHERE image_path IS NOT NULL
+GROUP BY image_path;
+
+ALTER TABLE blog_posts ADD COLUMN image_id UUID REFERENCES media (id);
+
+UPDATE blog_posts
+SET image_id = media.id
+FROM media
+WHERE media.path = blog_posts.image_path;
+
+ALTER TABLE blog_posts DROP COLUMN image_path;
@@ -0,0 +1,21 @@
+-- Work done by background workers, picked up with FOR UPDATE SKIP LOCKED
+CREATE TABLE jobs (
+    id UUID PRIMARY KEY,
+    payload JSONB NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    run_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    last_error TEXT,
+    -- Set once a job has given up, the row is kept for inspection
+    failed_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX jobs_run_at_idx ON jobs (run_at) WHERE failed_at IS NULL;
+
+-- Raw upload waiting to become the post image, the post shows a placeholder meanwhile
+ALTER TABLE blog_posts ADD COLUMN pending_image_key TEXT;
+
+-- Avatar URL waiting to be downloaded and the reason the last download failed
+ALTER TABLE users
+    ADD COLUMN avatar_pending_url TEXT,
+    ADD COLUMN avatar_error TEXT;
@@ -0,0 +1,8 @@
+-- Post text is written in Markdown, the sanitized HTML rendering is stored next to it
+ALTER TABLE blog_posts ADD COLUMN text_html TEXT;
+
+-- Existing posts keep the escaped single paragraph they were shown as so far
+UPDATE blog_posts
+SET text_html = '<p>' || replace(replace(replace(text, '&', '&amp;'), '<', '&lt;'), '>', '&gt;') || '</p>';
+
+ALTER TABLE blog_posts ALTER COLUMN text_html SET NOT NULL;
@@ -0,0 +1,6 @@
+-- Full-text search over the post text, kept up to date by PostgreSQL itself
+ALTER TABLE blog_posts
+    ADD COLUMN search_vector tsvector
+    GENERATED ALWAYS AS (to_tsvector('english', text)) STORED;
+
+CREATE INDEX blog_posts_search_vector_idx ON blog_posts USING GIN (search_vector);
@@ -0,0 +1,4 @@
+-- When a post last changed, for feed entries and conditional requests
+ALTER TABLE blog_posts ADD COLUMN updated_at TIMESTAMPTZ NOT NULL DEFAULT now();
+
+UPDATE blog_posts SET updated_at = published_at;
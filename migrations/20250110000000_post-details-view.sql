@@ -0,0 +1,44 @@
+-- Every column of a post as read by the application, joined once here instead
+-- of in each query. Recreate the view when the columns it reads change.
+CREATE VIEW post_details AS
+SELECT
+    blog_posts.id,
+    blog_posts.text,
+    blog_posts.text_html,
+    blog_posts.published_at,
+    blog_posts.updated_at,
+    media.path AS image_path,
+    blog_posts.image_id,
+    COALESCE(
+        (
+            SELECT json_agg(json_build_object('width', width, 'path', variant_path) ORDER BY width)
+            FROM image_variants
+            WHERE image_variants.image_path = media.path
+        ),
+        '[]'
+    ) AS image_variants,
+    blog_posts.pending_image_key IS NOT NULL AS image_pending,
+    blog_posts.pending_image_key,
+    ARRAY(
+        SELECT tags.name
+        FROM post_tags
+        JOIN tags ON tags.id = post_tags.tag_id
+        WHERE post_tags.post_id = blog_posts.id
+        ORDER BY tags.name
+    ) AS tags,
+    blog_posts.username,
+    COALESCE(
+        (
+            SELECT variant_path
+            FROM image_variants
+            WHERE image_variants.image_path = users.avatar_path
+            ORDER BY width
+            LIMIT 1
+        ),
+        users.avatar_path,
+        blog_posts.legacy_avatar_path
+    ) AS user_avatar_path,
+    blog_posts.user_id
+FROM blog_posts
+LEFT JOIN users ON users.id = blog_posts.user_id
+LEFT JOIN media ON media.id = blog_posts.image_id;
@@ -0,0 +1,68 @@
+use std::sync::Arc;
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+#[derive(Debug, thiserror::Error)]
+pub enum BlockingError {
+    #[error("Too many images are being processed, try again later")]
+    Saturated,
+
+    #[error("Blocking task failed: {0}")]
+    Failed(#[from] tokio::task::JoinError),
+}
+
+/// Runs CPU- and disk-bound work such as image decoding on Tokio's blocking
+/// threads, so it cannot stall the async workers serving requests. At most
+/// `max_concurrency` tasks run at a time.
+#[derive(Clone)]
+pub struct BlockingPool {
+    permits: Arc<Semaphore>,
+}
+
+impl BlockingPool {
+    pub fn new(max_concurrency: usize) -> Self {
+        Self {
+            permits: Arc::new(Semaphore::new(max_concurrency)),
+        }
+    }
+
+    /// Waits for a free slot, then runs `task`. Meant for background work that
+    /// can afford to queue.
+    pub async fn run<F, T>(&self, task: F) -> Result<T, BlockingError>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let permit = Arc::clone(&self.permits)
+            .acquire_owned()
+            .await
+            .map_err(|_| BlockingError::Saturated)?;
+        spawn(permit, task).await
+    }
+
+    /// Runs `task` only if a slot is free right away, so requests are turned
+    /// away instead of piling up while the pool is saturated.
+    pub async fn try_run<F, T>(&self, task: F) -> Result<T, BlockingError>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let permit = Arc::clone(&self.permits)
+            .try_acquire_owned()
+            .map_err(|_| BlockingError::Saturated)?;
+        spawn(permit, task).await
+    }
+}
+
+async fn spawn<F, T>(permit: OwnedSemaphorePermit, task: F) -> Result<T, BlockingError>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let result = tokio::task::spawn_blocking(move || {
+        let _permit = permit;
+        task()
+    })
+    .await?;
+    Ok(result)
+}
@@ -1,8 +1,11 @@
 use config::File;
+use ipnet::IpNet;
 use serde::Deserialize;
 use serde_aux::prelude::deserialize_number_from_string;
 use sqlx::postgres::PgConnectOptions;
-use std::path::PathBuf;
+use std::{path::PathBuf, time::Duration};
+
+use crate::domain::MediaFormat;
 
 #[derive(Clone, Deserialize)]
 pub struct Settings {
@@ -24,7 +27,182 @@ pub struct ApplicationSettings {
     pub host: String,
     #[serde(deserialize_with = "deserialize_number_from_string")]
     pub port: u16,
+    /// Address the blog is reached at from outside, e.g. `https://blog.example.com`,
+    /// used wherever a link must be absolute.
+    pub base_url: String,
     pub upload_path: PathBuf,
+    /// Upper bound in bytes for any request body, checked while it is received.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_request_size: usize,
+    /// Marks the session cookie `Secure`, so browsers only send it over HTTPS.
+    /// Turned off to sign in over plain HTTP.
+    pub secure_cookies: bool,
+    pub storage: StorageSettings,
+    pub media: MediaSettings,
+    pub fetch: FetchSettings,
+    pub jobs: JobSettings,
+    pub sweeper: SweeperSettings,
+}
+
+/// What happens to stored files that nothing references anymore.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum OrphanAction {
+    Delete,
+    /// Moved aside for inspection, see [`crate::storage::QUARANTINE_PREFIX`].
+    Quarantine,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct SweeperSettings {
+    /// Seconds between sweeps for orphaned files, `0` disables the periodic sweep.
+    pub interval_secs: u64,
+    /// Files younger than this are left alone, as a request or job may still
+    /// be about to reference them.
+    pub grace_period_secs: u64,
+    pub action: OrphanAction,
+}
+
+impl SweeperSettings {
+    pub fn interval(&self) -> Option<Duration> {
+        (self.interval_secs > 0).then(|| Duration::from_secs(self.interval_secs))
+    }
+
+    pub fn grace_period(&self) -> Duration {
+        Duration::from_secs(self.grace_period_secs)
+    }
+}
+
+#[derive(Clone, Deserialize)]
+pub struct JobSettings {
+    /// Number of background workers, `0` leaves queued jobs untouched.
+    pub workers: usize,
+    pub poll_interval_ms: u64,
+    /// Attempts before a job is marked as failed.
+    pub max_attempts: i32,
+    /// Delay before the first retry, doubled for every further attempt.
+    pub retry_base_delay_ms: u64,
+    /// How long a claimed job is left to its worker before another worker may
+    /// pick it up, e.g. because the first one died. Must outlast any job.
+    pub lease_secs: u64,
+}
+
+impl JobSettings {
+    pub fn poll_interval(&self) -> Duration {
+        Duration::from_millis(self.poll_interval_ms)
+    }
+
+    pub fn lease(&self) -> Duration {
+        Duration::from_secs(self.lease_secs)
+    }
+
+    /// Exponential backoff after `attempts` failed attempts, capped at an hour.
+    pub fn retry_delay(&self, attempts: i32) -> Duration {
+        let factor = 2u64.saturating_pow(attempts.saturating_sub(1).max(0) as u32);
+        Duration::from_millis(self.retry_base_delay_ms.saturating_mul(factor))
+            .min(Duration::from_secs(60 * 60))
+    }
+}
+
+/// Rules for downloading user-supplied URLs such as avatars. Loopback,
+/// private and link-local addresses are always blocked unless allowed here.
+#[derive(Clone, Deserialize)]
+pub struct FetchSettings {
+    pub max_redirects: usize,
+    pub connect_timeout_ms: u64,
+    /// Longest wait for the next bytes of a response, so a slow host cannot
+    /// hold a job worker.
+    pub read_timeout_ms: u64,
+    /// Longest a download may take as a whole, redirects and body included, so
+    /// a host trickling bytes cannot hold a job worker either. Must stay below
+    /// `jobs.lease_secs`, or the job is claimed again while still running.
+    pub timeout_ms: u64,
+    /// Networks exempt from the built-in blocked ranges.
+    #[serde(default)]
+    pub allowed_networks: Vec<IpNet>,
+    /// Additional networks to block, taking precedence over `allowed_networks`.
+    #[serde(default)]
+    pub denied_networks: Vec<IpNet>,
+    /// Host names blocked together with their subdomains, e.g. `internal.example.com`.
+    #[serde(default)]
+    pub denied_hosts: Vec<String>,
+}
+
+impl FetchSettings {
+    pub fn connect_timeout(&self) -> Duration {
+        Duration::from_millis(self.connect_timeout_ms)
+    }
+
+    pub fn read_timeout(&self) -> Duration {
+        Duration::from_millis(self.read_timeout_ms)
+    }
+
+    pub fn timeout(&self) -> Duration {
+        Duration::from_millis(self.timeout_ms)
+    }
+}
+
+/// Backend holding uploaded files, chosen by the `backend` key.
+#[derive(Clone, Deserialize)]
+#[serde(tag = "backend", rename_all = "lowercase")]
+pub enum StorageSettings {
+    /// Files are kept in `upload_path`.
+    Local,
+    S3(S3Settings),
+}
+
+#[derive(Clone, Deserialize)]
+pub struct S3Settings {
+    /// Base URL of the service, e.g. `http://localhost:9000` for MinIO.
+    pub endpoint: String,
+    pub region: String,
+    pub bucket: String,
+    pub access_key: String,
+    pub secret_key: String,
+    /// Addresses the bucket as `endpoint/bucket` instead of a subdomain, as MinIO expects.
+    #[serde(default)]
+    pub path_style: bool,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct MediaSettings {
+    /// Formats accepted for uploaded images and downloaded avatars,
+    /// detected from the file contents.
+    pub allowed_formats: Vec<MediaFormat>,
+    /// Format every stored image is transcoded to.
+    pub output_format: MediaFormat,
+    /// Upper bound in bytes for an uploaded post image.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_image_size: usize,
+    /// Upper bound in bytes for a downloaded avatar.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_avatar_size: usize,
+    /// Widths in pixels of the scaled-down copies generated for post images.
+    pub image_widths: Vec<u32>,
+    /// Side in pixels of the square crop generated for avatars.
+    pub avatar_size: u32,
+    /// Upper bound for the width times height of an image, checked before it
+    /// is decoded, as a small but highly compressed file can expand into a
+    /// bitmap that does not fit in memory.
+    pub max_image_pixels: u64,
+    /// Images decoded or encoded at the same time. Uploads arriving while all
+    /// slots are taken are answered with `503 Service Unavailable`.
+    pub max_concurrency: usize,
+}
+
+impl MediaSettings {
+    pub fn allows(&self, format: MediaFormat) -> bool {
+        self.allowed_formats.contains(&format)
+    }
+
+    /// Value for the `accept` attribute of file inputs.
+    pub fn accept_attribute(&self) -> String {
+        self.allowed_formats
+            .iter()
+            .map(|format| format.mime_type())
+            .collect::<Vec<_>>()
+            .join(",")
+    }
 }
 
 impl DatabaseSettings {
@@ -1,77 +1,480 @@
+pub mod images;
+pub mod media;
+pub mod search;
+pub mod sessions;
+pub mod tags;
+pub mod users;
+
 use chrono::{DateTime, Utc};
 use image::ImageFormat;
 use once_cell::sync::Lazy;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
-use sqlx::{Postgres, Transaction};
+use sqlx::{types::Json, PgExecutor, Postgres, Transaction};
+use utoipa::ToSchema;
 use uuid::Uuid;
 
+use crate::markdown::{plain_text, render_markdown};
+use crate::pagination::{Cursor, Page, PageQuery, Paginated};
+use images::ImageVariant;
+use users::User;
+
 pub const MAX_TEXT_LENGTH: u64 = 10000;
 pub const MIN_TEXT_LENGTH: u64 = 10;
-pub const ALLOWED_IMAGE_TYPE: ImageFormat = ImageFormat::Png;
+pub const TITLE_LENGTH: usize = 80;
+pub const EXCERPT_LENGTH: usize = 200;
+
+/// Image formats that can be accepted for upload and used for stored files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MediaFormat {
+    Png,
+    Jpeg,
+    Webp,
+    Gif,
+}
+
+impl MediaFormat {
+    /// Detects the format from the leading magic bytes of `data`.
+    pub fn detect(data: &[u8]) -> Option<Self> {
+        match image::guess_format(data).ok()? {
+            ImageFormat::Png => Some(Self::Png),
+            ImageFormat::Jpeg => Some(Self::Jpeg),
+            ImageFormat::WebP => Some(Self::Webp),
+            ImageFormat::Gif => Some(Self::Gif),
+            _ => None,
+        }
+    }
+
+    pub fn image_format(self) -> ImageFormat {
+        match self {
+            Self::Png => ImageFormat::Png,
+            Self::Jpeg => ImageFormat::Jpeg,
+            Self::Webp => ImageFormat::WebP,
+            Self::Gif => ImageFormat::Gif,
+        }
+    }
+
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension {
+            "png" => Some(Self::Png),
+            "jpg" => Some(Self::Jpeg),
+            "webp" => Some(Self::Webp),
+            "gif" => Some(Self::Gif),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Jpeg => "jpg",
+            Self::Webp => "webp",
+            Self::Gif => "gif",
+        }
+    }
+
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            Self::Png => "image/png",
+            Self::Jpeg => "image/jpeg",
+            Self::Webp => "image/webp",
+            Self::Gif => "image/gif",
+        }
+    }
+}
+
+impl std::fmt::Display for MediaFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Png => "PNG",
+            Self::Jpeg => "JPEG",
+            Self::Webp => "WebP",
+            Self::Gif => "GIF",
+        };
+        f.write_str(name)
+    }
+}
 
 pub static USERNAME_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^[a-zA-Z0-9_-]{2,50}$").unwrap());
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct BlogPost {
     pub id: Uuid,
+    /// Markdown source as written by the author.
     pub text: String,
+    /// Sanitized HTML rendered from [`Self::text`].
+    pub text_html: String,
+    /// Tag names in alphabetical order.
+    pub tags: Vec<String>,
     pub published_at: DateTime<Utc>,
+    /// Last change to the text, tags or image, equal to `published_at` until then.
+    pub updated_at: DateTime<Utc>,
     pub image_path: Option<String>,
+    /// Media row holding the image, shared with other posts using the same image.
+    #[serde(skip)]
+    pub image_id: Option<Uuid>,
+    /// Renditions of the image ordered by width, empty for images stored before variants existed.
+    #[schema(value_type = Vec<ImageVariant>)]
+    pub image_variants: Json<Vec<ImageVariant>>,
+    /// Whether an uploaded image is still being processed, the post shows its
+    /// previous image or a placeholder until it is done.
+    pub image_pending: bool,
+    /// Storage key of the raw upload the pending image is made from.
+    #[serde(skip)]
+    pub pending_image_key: Option<String>,
     pub username: String,
     pub user_avatar_path: Option<String>,
+    pub user_id: Option<Uuid>,
+}
+
+impl Paginated for BlogPost {
+    fn cursor(&self) -> Cursor {
+        Cursor {
+            published_at: self.published_at,
+            id: self.id,
+        }
+    }
+}
+
+impl BlogPost {
+    /// Short single-line title derived from the first line of the post text,
+    /// without Markdown markup.
+    pub fn title(&self) -> String {
+        let text = plain_text(&self.text);
+        let first_line = text
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty())
+            .unwrap_or_default();
+        if first_line.chars().count() <= TITLE_LENGTH {
+            return first_line.to_string();
+        }
+
+        let truncated: String = first_line.chars().take(TITLE_LENGTH).collect();
+        format!("{}…", truncated.trim_end())
+    }
+
+    /// Short summary of the whole post text on a single line, without
+    /// Markdown markup, as shown by link previews.
+    pub fn excerpt(&self) -> String {
+        let text = plain_text(&self.text)
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        if text.chars().count() <= EXCERPT_LENGTH {
+            return text;
+        }
+
+        let truncated: String = text.chars().take(EXCERPT_LENGTH).collect();
+        format!("{}…", truncated.trim_end())
+    }
+
+    /// `srcset` value listing every rendition of the image, if any were recorded.
+    pub fn image_srcset(&self, upload_path: &str) -> Option<String> {
+        if self.image_variants.is_empty() {
+            return None;
+        }
+
+        let candidates: Vec<String> = self
+            .image_variants
+            .iter()
+            .map(|variant| format!("{}/{} {}w", upload_path, variant.path, variant.width))
+            .collect();
+        Some(candidates.join(", "))
+    }
 }
 
 #[tracing::instrument(name = "Saving post to database", skip(tx))]
 pub async fn save_post(
     tx: &mut Transaction<'_, Postgres>,
     text: &str,
-    username: &str,
-    image_path: Option<&str>,
-    avatar_path: Option<&str>,
-) -> Result<(), sqlx::Error> {
+    author: &User,
+    pending_image_key: Option<&str>,
+) -> Result<Uuid, sqlx::Error> {
     let id = Uuid::new_v4();
     sqlx::query!(
         r#"
         INSERT INTO blog_posts (
             id,
             text,
+            text_html,
             username,
-            image_path,
-            user_avatar_path
+            user_id,
+            pending_image_key
         )
-        VALUES ($1, $2, $3, $4, $5)
+        VALUES ($1, $2, $3, $4, $5, $6)
         "#,
         id,
         text,
-        username,
-        image_path,
-        avatar_path,
+        render_markdown(text),
+        author.username,
+        author.id,
+        pending_image_key,
     )
     .execute(&mut **tx)
     .await?;
 
-    Ok(())
+    Ok(id)
+}
+
+/// Restricts a listing to matching posts, every post by default.
+#[derive(Debug, Default)]
+pub struct PostFilter {
+    /// Only posts carrying this tag, in its normalized form.
+    pub tag: Option<String>,
+    /// Only posts written by the user with this id.
+    pub user_id: Option<Uuid>,
+}
+
+/// Which posts [`select_posts`] reads. Conditions left `None` match every post.
+#[derive(Debug, Default)]
+struct PostSelection<'a> {
+    ids: Option<&'a [Uuid]>,
+    after: Option<Cursor>,
+    before: Option<Cursor>,
+    tag: Option<&'a str>,
+    user_id: Option<Uuid>,
+    limit: Option<i64>,
 }
 
-#[tracing::instrument(name = "Getting all posts from database", skip(pool))]
-pub async fn get_all_posts(pool: &sqlx::PgPool) -> Result<Vec<BlogPost>, sqlx::Error> {
-    let posts = sqlx::query_as!(
+/// Reads posts from the `post_details` view, the one query listing its
+/// columns. Posts come newest first, or oldest first when reading `after` a
+/// cursor.
+async fn select_posts<'e>(
+    executor: impl PgExecutor<'e>,
+    selection: &PostSelection<'_>,
+) -> Result<Vec<BlogPost>, sqlx::Error> {
+    sqlx::query_as!(
         BlogPost,
         r#"
-        SELECT 
-            id,
-            text,
-            published_at,
+        SELECT
+            id AS "id!",
+            text AS "text!",
+            text_html AS "text_html!",
+            published_at AS "published_at!",
+            updated_at AS "updated_at!",
             image_path,
-            username,
-            user_avatar_path
+            image_id,
+            image_variants AS "image_variants!: Json<Vec<ImageVariant>>",
+            image_pending AS "image_pending!",
+            pending_image_key,
+            tags AS "tags!",
+            username AS "username!",
+            user_avatar_path,
+            user_id
+        FROM post_details
+        WHERE ($1::uuid[] IS NULL OR id = ANY($1))
+            AND ($2::timestamptz IS NULL OR (published_at, id) > ($2, $3::uuid))
+            AND ($4::timestamptz IS NULL OR (published_at, id) < ($4, $5::uuid))
+            AND ($6::text IS NULL OR EXISTS (
+                SELECT 1
+                FROM post_tags
+                JOIN tags ON tags.id = post_tags.tag_id
+                WHERE post_tags.post_id = post_details.id AND tags.name = $6
+            ))
+            AND ($7::uuid IS NULL OR user_id = $7)
+        ORDER BY
+            CASE WHEN $8 THEN published_at END ASC,
+            CASE WHEN $8 THEN id END ASC,
+            published_at DESC,
+            id DESC
+        LIMIT $9
+        "#,
+        selection.ids,
+        selection.after.map(|cursor| cursor.published_at),
+        selection.after.map(|cursor| cursor.id),
+        selection.before.map(|cursor| cursor.published_at),
+        selection.before.map(|cursor| cursor.id),
+        selection.tag,
+        selection.user_id,
+        selection.after.is_some(),
+        selection.limit,
+    )
+    .fetch_all(executor)
+    .await
+}
+
+#[tracing::instrument(name = "Getting page of posts from database", skip(pool))]
+pub async fn get_posts_page(
+    pool: &sqlx::PgPool,
+    query: &PageQuery,
+    filter: &PostFilter,
+) -> Result<Page<BlogPost>, sqlx::Error> {
+    let selection = PostSelection {
+        after: query.after,
+        // `after` takes precedence.
+        before: query.before.filter(|_| query.after.is_none()),
+        tag: filter.tag.as_deref(),
+        user_id: filter.user_id,
+        limit: Some(query.fetch_limit()),
+        ..PostSelection::default()
+    };
+    let posts = select_posts(pool, &selection).await?;
+
+    Ok(Page::from_rows(posts, query))
+}
+
+#[tracing::instrument(name = "Getting post by id from database", skip(pool))]
+pub async fn get_post_by_id(
+    pool: &sqlx::PgPool,
+    id: Uuid,
+) -> Result<Option<BlogPost>, sqlx::Error> {
+    let selection = PostSelection {
+        ids: Some(&[id]),
+        ..PostSelection::default()
+    };
+    let post = select_posts(pool, &selection).await?.pop();
+
+    Ok(post)
+}
+
+/// Posts with the given ids, newest first.
+#[tracing::instrument(name = "Getting posts by ids from database", skip(pool))]
+pub async fn get_posts_by_ids(
+    pool: &sqlx::PgPool,
+    ids: &[Uuid],
+) -> Result<Vec<BlogPost>, sqlx::Error> {
+    let selection = PostSelection {
+        ids: Some(ids),
+        ..PostSelection::default()
+    };
+
+    select_posts(pool, &selection).await
+}
+
+/// Locks the row of a post for the rest of the transaction and reads it. The
+/// view cannot be locked through its outer joins, so the row is locked first.
+#[tracing::instrument(name = "Locking post for update", skip(tx))]
+pub async fn get_post_for_update(
+    tx: &mut Transaction<'_, Postgres>,
+    id: Uuid,
+) -> Result<Option<BlogPost>, sqlx::Error> {
+    sqlx::query!("SELECT id FROM blog_posts WHERE id = $1 FOR UPDATE", id)
+        .fetch_optional(&mut **tx)
+        .await?;
+
+    let selection = PostSelection {
+        ids: Some(&[id]),
+        ..PostSelection::default()
+    };
+    let post = select_posts(&mut **tx, &selection).await?.pop();
+
+    Ok(post)
+}
+
+#[tracing::instrument(name = "Updating post in database", skip(tx))]
+pub async fn update_post(
+    tx: &mut Transaction<'_, Postgres>,
+    id: Uuid,
+    text: &str,
+    image_id: Option<Uuid>,
+    pending_image_key: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE blog_posts
+        SET
+            text = $2,
+            text_html = $3,
+            updated_at = now(),
+            image_id = $4,
+            pending_image_key = $5
+        WHERE id = $1
+        "#,
+        id,
+        text,
+        render_markdown(text),
+        image_id,
+        pending_image_key,
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+/// Locks the post and returns the key of its pending upload, `None` if the
+/// post no longer exists.
+#[tracing::instrument(name = "Locking pending post image", skip(tx))]
+pub async fn get_pending_image_for_update(
+    tx: &mut Transaction<'_, Postgres>,
+    id: Uuid,
+) -> Result<Option<Option<String>>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT pending_image_key
         FROM blog_posts
-        ORDER BY published_at DESC
+        WHERE id = $1
+        FOR UPDATE
+        "#,
+        id,
+    )
+    .fetch_optional(&mut **tx)
+    .await?;
+
+    Ok(row.map(|row| row.pending_image_key))
+}
+
+/// Replaces the placeholder or previous image of a post with its processed
+/// image. Returns the media of the previous image, if any.
+#[tracing::instrument(name = "Setting processed post image", skip(tx))]
+pub async fn set_post_image(
+    tx: &mut Transaction<'_, Postgres>,
+    id: Uuid,
+    image_id: Uuid,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let previous = sqlx::query_scalar!(
+        r#"
+        UPDATE blog_posts
+        SET image_id = $2, pending_image_key = NULL, updated_at = now()
+        FROM (SELECT image_id FROM blog_posts WHERE id = $1) AS previous
+        WHERE blog_posts.id = $1
+        RETURNING previous.image_id
         "#,
+        id,
+        image_id,
     )
-    .fetch_all(pool)
+    .fetch_optional(&mut **tx)
+    .await?;
+
+    Ok(previous.flatten())
+}
+
+/// Drops the placeholder of a post whose upload could not be processed, unless
+/// another upload has replaced it in the meantime. A previous image stays.
+#[tracing::instrument(name = "Clearing pending post image", skip(tx))]
+pub async fn clear_pending_image(
+    tx: &mut Transaction<'_, Postgres>,
+    id: Uuid,
+    pending_image_key: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE blog_posts
+        SET pending_image_key = NULL, updated_at = now()
+        WHERE id = $1 AND pending_image_key = $2
+        "#,
+        id,
+        pending_image_key,
+    )
+    .execute(&mut **tx)
     .await?;
 
-    Ok(posts)
+    Ok(())
+}
+
+#[tracing::instrument(name = "Deleting post from database", skip(tx))]
+pub async fn delete_post(tx: &mut Transaction<'_, Postgres>, id: Uuid) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        DELETE FROM blog_posts
+        WHERE id = $1
+        "#,
+        id,
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
 }
@@ -0,0 +1,64 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{Postgres, Transaction};
+use utoipa::ToSchema;
+
+/// A stored rendition of an uploaded image, the original included.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ImageVariant {
+    pub width: i32,
+    pub path: String,
+}
+
+#[tracing::instrument(name = "Saving image variants to database", skip(tx))]
+pub async fn save_image_variants(
+    tx: &mut Transaction<'_, Postgres>,
+    image_path: &str,
+    variants: &[ImageVariant],
+) -> Result<(), sqlx::Error> {
+    let widths: Vec<i32> = variants.iter().map(|variant| variant.width).collect();
+    let paths: Vec<String> = variants
+        .iter()
+        .map(|variant| variant.path.clone())
+        .collect();
+
+    sqlx::query!(
+        r#"
+        INSERT INTO image_variants (image_path, width, variant_path)
+        SELECT $1, width, variant_path
+        FROM UNNEST($2::int4[], $3::text[]) AS variants (width, variant_path)
+        "#,
+        image_path,
+        &widths,
+        &paths,
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+/// Forgets the variants of `image_path` and returns the files that held them,
+/// not counting the original.
+#[tracing::instrument(name = "Deleting image variants from database", skip(tx))]
+pub async fn delete_image_variants(
+    tx: &mut Transaction<'_, Postgres>,
+    image_path: &str,
+) -> Result<Vec<String>, sqlx::Error> {
+    let paths = sqlx::query_scalar!(
+        r#"
+        WITH deleted AS (
+            DELETE FROM image_variants
+            WHERE image_path = $1
+            RETURNING variant_path
+        )
+        SELECT variant_path AS "variant_path!"
+        FROM deleted
+        WHERE variant_path <> $1
+        "#,
+        image_path,
+    )
+    .fetch_all(&mut **tx)
+    .await?;
+
+    Ok(paths)
+}
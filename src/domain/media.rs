@@ -0,0 +1,138 @@
+use sqlx::{Postgres, Transaction};
+use uuid::Uuid;
+
+/// A stored image shared by every post referencing it.
+#[derive(Debug, Clone)]
+pub struct Media {
+    pub id: Uuid,
+    pub path: String,
+}
+
+/// Length of a hex encoded SHA-256 hash.
+const HASH_LENGTH: usize = 64;
+
+/// The hash a content-addressed file is named after, i.e. `<hash>.<ext>` or a
+/// variant `<hash>_<suffix>.<ext>`. `None` for every other file.
+pub fn content_hash(key: &str) -> Option<&str> {
+    let hash = key.get(..HASH_LENGTH)?;
+    let rest = &key[HASH_LENGTH..];
+    let is_hex = hash
+        .bytes()
+        .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b));
+    (is_hex && (rest.starts_with('.') || rest.starts_with('_'))).then_some(hash)
+}
+
+/// Serializes storing and removing the files of the media with `hash` until
+/// the transaction ends. Files are written while the lock is held, so they are
+/// never removed between being stored and their row being committed.
+#[tracing::instrument(name = "Locking media", skip(tx))]
+pub async fn lock_media(tx: &mut Transaction<'_, Postgres>, hash: &str) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "SELECT pg_advisory_xact_lock(hashtextextended($1, 0))",
+        hash
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+/// Whether a media row exists for `hash`, i.e. its files are still in use.
+#[tracing::instrument(name = "Checking media", skip(tx))]
+pub async fn media_exists(
+    tx: &mut Transaction<'_, Postgres>,
+    hash: &str,
+) -> Result<bool, sqlx::Error> {
+    let exists = sqlx::query_scalar!(
+        r#"SELECT EXISTS (SELECT 1 FROM media WHERE hash = $1) AS "exists!""#,
+        hash,
+    )
+    .fetch_one(&mut **tx)
+    .await?;
+
+    Ok(exists)
+}
+
+/// Whether the file under `key` may be removed. A content-addressed file is
+/// kept while its media exists, and the media stays locked until the
+/// transaction ends so it cannot be stored again before the file is gone.
+/// Every other file is left to the caller to decide.
+pub async fn lock_removable_file(
+    tx: &mut Transaction<'_, Postgres>,
+    key: &str,
+) -> Result<bool, sqlx::Error> {
+    let Some(hash) = content_hash(key) else {
+        return Ok(true);
+    };
+
+    lock_media(tx, hash).await?;
+    Ok(!media_exists(tx, hash).await?)
+}
+
+/// Takes a reference to the media with `hash`, creating it under `path` if it
+/// does not exist yet. Returns whether the row was created, in which case the
+/// caller has to store the file before the transaction commits.
+#[tracing::instrument(name = "Acquiring media", skip(tx))]
+pub async fn acquire_media(
+    tx: &mut Transaction<'_, Postgres>,
+    hash: &str,
+    path: &str,
+) -> Result<(Media, bool), sqlx::Error> {
+    lock_media(tx, hash).await?;
+
+    let row = sqlx::query!(
+        r#"
+        INSERT INTO media (id, hash, path, ref_count)
+        VALUES ($1, $2, $3, 1)
+        ON CONFLICT (hash) DO UPDATE SET ref_count = media.ref_count + 1
+        RETURNING id, path, (xmax = 0) AS "created!"
+        "#,
+        Uuid::new_v4(),
+        hash,
+        path,
+    )
+    .fetch_one(&mut **tx)
+    .await?;
+
+    let media = Media {
+        id: row.id,
+        path: row.path,
+    };
+    Ok((media, row.created))
+}
+
+/// Drops a reference to the media. When it was the last one the row is
+/// deleted and its path returned so the file can be queued for removal.
+#[tracing::instrument(name = "Releasing media", skip(tx))]
+pub async fn release_media(
+    tx: &mut Transaction<'_, Postgres>,
+    id: Uuid,
+) -> Result<Option<String>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        UPDATE media
+        SET ref_count = ref_count - 1
+        WHERE id = $1
+        RETURNING path, ref_count
+        "#,
+        id,
+    )
+    .fetch_one(&mut **tx)
+    .await?;
+
+    if row.ref_count > 0 {
+        return Ok(None);
+    }
+
+    sqlx::query!(
+        r#"
+        DELETE FROM media
+        WHERE id = $1
+        "#,
+        id,
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(Some(row.path))
+}
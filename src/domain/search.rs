@@ -0,0 +1,163 @@
+use chrono::{DateTime, Days, NaiveDate, Utc};
+use serde::Serialize;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use super::{get_posts_by_ids, users::get_user_by_username, BlogPost};
+use crate::pagination::{DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE};
+
+pub const MAX_QUERY_LENGTH: usize = 200;
+
+/// Private-use characters PostgreSQL puts around matches in snippets. They are
+/// turned into `<mark>` only after the rest of the snippet has been escaped.
+const MATCH_START: char = '\u{E000}';
+const MATCH_END: char = '\u{E001}';
+
+/// What to search for. Dates are whole UTC days, both ends included.
+#[derive(Debug, Default)]
+pub struct SearchCriteria {
+    pub query: String,
+    pub username: Option<String>,
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+impl SearchCriteria {
+    pub fn limit(&self) -> i64 {
+        self.limit
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE)
+    }
+
+    pub fn offset(&self) -> i64 {
+        self.offset.unwrap_or(0).max(0)
+    }
+
+    fn published_after(&self) -> Option<DateTime<Utc>> {
+        self.from.map(start_of_day)
+    }
+
+    fn published_before(&self) -> Option<DateTime<Utc>> {
+        self.to
+            .and_then(|to| to.checked_add_days(Days::new(1)))
+            .map(start_of_day)
+    }
+}
+
+fn start_of_day(date: NaiveDate) -> DateTime<Utc> {
+    date.and_time(Default::default()).and_utc()
+}
+
+/// A post matching a search, best matches first.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SearchResult {
+    #[serde(flatten)]
+    pub post: BlogPost,
+    pub rank: f32,
+    /// Excerpts of the text around the matches as HTML, matches wrapped in `<mark>`.
+    pub snippet: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SearchResults {
+    pub results: Vec<SearchResult>,
+    /// Offset of the next page of results, if there is one.
+    pub next_offset: Option<i64>,
+}
+
+/// Searches post texts with `websearch_to_tsquery` syntax: words, `"quoted phrases"`,
+/// `or` and `-excluded` words. Posts are matched to a username through the
+/// account, so nothing is found for an unknown user.
+#[tracing::instrument(name = "Searching posts", skip(pool))]
+pub async fn search_posts(
+    pool: &sqlx::PgPool,
+    criteria: &SearchCriteria,
+) -> Result<SearchResults, sqlx::Error> {
+    let user_id = match &criteria.username {
+        Some(username) => match get_user_by_username(pool, username).await? {
+            Some(user) => Some(user.id),
+            None => {
+                return Ok(SearchResults {
+                    results: Vec::new(),
+                    next_offset: None,
+                })
+            }
+        },
+        None => None,
+    };
+    let headline_options = format!(
+        "StartSel={}, StopSel={}, MaxFragments=2, MaxWords=30, MinWords=10, FragmentDelimiter=\" … \"",
+        MATCH_START, MATCH_END
+    );
+
+    let mut matches = sqlx::query!(
+        r#"
+        SELECT
+            blog_posts.id,
+            ts_rank(blog_posts.search_vector, query) AS "rank!",
+            ts_headline('english', blog_posts.text, query, $5) AS "snippet!"
+        FROM blog_posts, websearch_to_tsquery('english', $1) AS query
+        WHERE blog_posts.search_vector @@ query
+            AND ($2::uuid IS NULL OR blog_posts.user_id = $2)
+            AND ($3::timestamptz IS NULL OR blog_posts.published_at >= $3)
+            AND ($4::timestamptz IS NULL OR blog_posts.published_at < $4)
+        ORDER BY 2 DESC, blog_posts.published_at DESC, blog_posts.id DESC
+        LIMIT $6
+        OFFSET $7
+        "#,
+        criteria.query,
+        user_id,
+        criteria.published_after(),
+        criteria.published_before(),
+        headline_options,
+        criteria.limit() + 1,
+        criteria.offset(),
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let next_offset =
+        (matches.len() as i64 > criteria.limit()).then(|| criteria.offset() + criteria.limit());
+    matches.truncate(criteria.limit() as usize);
+
+    let ids: Vec<Uuid> = matches.iter().map(|m| m.id).collect();
+    let mut posts = get_posts_by_ids(pool, &ids).await?;
+
+    // Posts deleted in between are left out.
+    let results = matches
+        .into_iter()
+        .filter_map(|m| {
+            let index = posts.iter().position(|post| post.id == m.id)?;
+            Some(SearchResult {
+                post: posts.swap_remove(index),
+                rank: m.rank,
+                snippet: highlight_snippet(&m.snippet),
+            })
+        })
+        .collect();
+
+    Ok(SearchResults {
+        results,
+        next_offset,
+    })
+}
+
+/// Escapes a headline from PostgreSQL and marks the matches in it.
+fn highlight_snippet(headline: &str) -> String {
+    let mut html = String::with_capacity(headline.len());
+    for c in headline.chars() {
+        match c {
+            MATCH_START => html.push_str("<mark>"),
+            MATCH_END => html.push_str("</mark>"),
+            '&' => html.push_str("&amp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' => html.push_str("&quot;"),
+            '\'' => html.push_str("&#x27;"),
+            c => html.push(c),
+        }
+    }
+    html
+}
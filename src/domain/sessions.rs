@@ -0,0 +1,72 @@
+use chrono::{TimeDelta, Utc};
+use uuid::Uuid;
+
+use super::users::User;
+
+pub const SESSION_COOKIE: &str = "session_id";
+pub const SESSION_LIFETIME_DAYS: i64 = 7;
+
+#[tracing::instrument(name = "Creating session in database", skip(pool))]
+pub async fn create_session(pool: &sqlx::PgPool, user_id: Uuid) -> Result<Uuid, sqlx::Error> {
+    let id = Uuid::new_v4();
+    let expires_at = Utc::now() + TimeDelta::days(SESSION_LIFETIME_DAYS);
+
+    sqlx::query!(
+        r#"
+        DELETE FROM sessions
+        WHERE expires_at <= NOW()
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO sessions (id, user_id, expires_at)
+        VALUES ($1, $2, $3)
+        "#,
+        id,
+        user_id,
+        expires_at,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(id)
+}
+
+#[tracing::instrument(name = "Getting session user from database", skip(pool, session_id))]
+pub async fn get_session_user(
+    pool: &sqlx::PgPool,
+    session_id: Uuid,
+) -> Result<Option<User>, sqlx::Error> {
+    let user = sqlx::query_as!(
+        User,
+        r#"
+        SELECT users.id, users.username
+        FROM sessions
+        JOIN users ON users.id = sessions.user_id
+        WHERE sessions.id = $1 AND sessions.expires_at > NOW()
+        "#,
+        session_id,
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(user)
+}
+
+#[tracing::instrument(name = "Deleting session from database", skip(pool, session_id))]
+pub async fn delete_session(pool: &sqlx::PgPool, session_id: Uuid) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        DELETE FROM sessions
+        WHERE id = $1
+        "#,
+        session_id,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,99 @@
+use std::collections::BTreeSet;
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use sqlx::{Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::markdown::extract_hashtags;
+
+pub const MAX_TAGS: u64 = 10;
+pub const MAX_TAG_LENGTH: usize = 50;
+
+static TAG_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^[\w-]+$").unwrap());
+
+/// Turns user input such as `#Rust` into the stored form `rust`, `None` if it
+/// is not a valid tag.
+pub fn normalize_tag(input: &str) -> Option<String> {
+    let tag = input.trim();
+    let tag = tag.strip_prefix('#').unwrap_or(tag).to_lowercase();
+    (TAG_RE.is_match(&tag) && tag.chars().count() <= MAX_TAG_LENGTH).then_some(tag)
+}
+
+/// Parses the tags form field, a list separated by commas or whitespace.
+pub fn parse_tags(input: &str) -> Result<Vec<String>, String> {
+    normalize_tags(
+        input
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|tag| !tag.is_empty()),
+    )
+}
+
+/// Normalizes each of the tags, failing on the first invalid one. Tags that
+/// are the same once normalized, e.g. `Rust` and `#rust`, are kept once, so
+/// they count once toward [`MAX_TAGS`].
+pub fn normalize_tags<'a>(tags: impl IntoIterator<Item = &'a str>) -> Result<Vec<String>, String> {
+    let mut normalized = Vec::new();
+    for tag in tags {
+        let tag = normalize_tag(tag).ok_or_else(|| format!("Invalid tag: {}", tag))?;
+        if !normalized.contains(&tag) {
+            normalized.push(tag);
+        }
+    }
+    Ok(normalized)
+}
+
+/// Tags found as `#hashtags` in Markdown text. Hashtags that are not valid
+/// tags, e.g. too long, are ignored.
+pub fn hashtags(text: &str) -> BTreeSet<String> {
+    extract_hashtags(text)
+        .iter()
+        .filter_map(|hashtag| normalize_tag(hashtag))
+        .collect()
+}
+
+/// Tags of a post: the given ones together with the hashtags of its text,
+/// sorted and without duplicates.
+pub fn collect_tags(text: &str, explicit: &[String]) -> Vec<String> {
+    let mut tags = hashtags(text);
+    tags.extend(explicit.iter().cloned());
+    tags.into_iter().collect()
+}
+
+/// Replaces the tags of a post, creating tags that do not exist yet.
+#[tracing::instrument(name = "Saving post tags to database", skip(tx))]
+pub async fn set_post_tags(
+    tx: &mut Transaction<'_, Postgres>,
+    post_id: Uuid,
+    tags: &[String],
+) -> Result<(), sqlx::Error> {
+    let ids: Vec<Uuid> = tags.iter().map(|_| Uuid::new_v4()).collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO tags (id, name)
+        SELECT * FROM UNNEST($1::uuid[], $2::text[])
+        ON CONFLICT (name) DO NOTHING
+        "#,
+        &ids,
+        tags,
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    sqlx::query!("DELETE FROM post_tags WHERE post_id = $1", post_id)
+        .execute(&mut **tx)
+        .await?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO post_tags (post_id, tag_id)
+        SELECT $1, id FROM tags WHERE name = ANY($2)
+        "#,
+        post_id,
+        tags,
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,266 @@
+use argon2::{
+    password_hash::SaltString, Algorithm, Argon2, Params, PasswordHash, PasswordHasher,
+    PasswordVerifier, Version,
+};
+use sqlx::{Postgres, Transaction};
+use uuid::Uuid;
+
+pub const MIN_PASSWORD_LENGTH: u64 = 8;
+pub const MAX_PASSWORD_LENGTH: u64 = 128;
+
+/// Hash verified when the username does not exist, so that failed logins take
+/// the same time regardless of whether the account is known.
+const FALLBACK_PASSWORD_HASH: &str = "$argon2id$v=19$m=15000,t=2,p=1$\
+    +oSBIIlW7uj9/BiBSBrGSg$\
+    YnJc8vdZ2kgZmRPhQRiE3/I35j2oYIMa4EVgZJBcDJ8";
+
+#[derive(Debug, Clone)]
+pub struct User {
+    pub id: Uuid,
+    pub username: String,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum PasswordError {
+    #[error("Invalid credentials")]
+    InvalidCredentials,
+
+    #[error("Failed to hash password: {0}")]
+    Hashing(argon2::password_hash::Error),
+}
+
+fn hasher() -> Argon2<'static> {
+    Argon2::new(
+        Algorithm::Argon2id,
+        Version::V0x13,
+        Params::new(15000, 2, 1, None).expect("Invalid Argon2 parameters"),
+    )
+}
+
+pub fn compute_password_hash(password: &str) -> Result<String, PasswordError> {
+    let salt = SaltString::generate(&mut rand::thread_rng());
+    let hash = hasher()
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(PasswordError::Hashing)?;
+
+    Ok(hash.to_string())
+}
+
+/// Verifies `password` against `expected_hash`, or against a fallback hash when the
+/// account does not exist.
+pub fn verify_password_hash(
+    expected_hash: Option<&str>,
+    password: &str,
+) -> Result<(), PasswordError> {
+    let known_user = expected_hash.is_some();
+    let expected_hash = PasswordHash::new(expected_hash.unwrap_or(FALLBACK_PASSWORD_HASH))
+        .map_err(PasswordError::Hashing)?;
+
+    hasher()
+        .verify_password(password.as_bytes(), &expected_hash)
+        .map_err(|_| PasswordError::InvalidCredentials)?;
+
+    if !known_user {
+        return Err(PasswordError::InvalidCredentials);
+    }
+
+    Ok(())
+}
+
+#[tracing::instrument(name = "Saving user to database", skip(pool, password_hash))]
+pub async fn insert_user(
+    pool: &sqlx::PgPool,
+    username: &str,
+    password_hash: &str,
+) -> Result<User, sqlx::Error> {
+    let id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO users (id, username, password_hash)
+        VALUES ($1, $2, $3)
+        "#,
+        id,
+        username,
+        password_hash,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(User {
+        id,
+        username: username.to_string(),
+    })
+}
+
+/// Returns the user together with their stored password hash.
+#[tracing::instrument(name = "Getting user credentials from database", skip(pool))]
+pub async fn get_user_credentials(
+    pool: &sqlx::PgPool,
+    username: &str,
+) -> Result<Option<(User, String)>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT id, username, password_hash
+        FROM users
+        WHERE username = $1
+        "#,
+        username,
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|row| {
+        (
+            User {
+                id: row.id,
+                username: row.username,
+            },
+            row.password_hash,
+        )
+    }))
+}
+
+#[tracing::instrument(name = "Getting user by name from database", skip(pool))]
+pub async fn get_user_by_username(
+    pool: &sqlx::PgPool,
+    username: &str,
+) -> Result<Option<User>, sqlx::Error> {
+    let user = sqlx::query_as!(
+        User,
+        "SELECT id, username FROM users WHERE username = $1",
+        username,
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(user)
+}
+
+/// Avatar stored on a user profile along with the URL it was downloaded from.
+#[derive(Debug, Default)]
+pub struct Avatar {
+    pub path: Option<String>,
+    pub url: Option<String>,
+    /// URL waiting to be downloaded, the current avatar stays until it is.
+    pub pending_url: Option<String>,
+    /// Why the last requested avatar could not be downloaded.
+    pub error: Option<String>,
+}
+
+#[tracing::instrument(name = "Locking user avatar for update", skip(tx))]
+pub async fn get_avatar_for_update(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+) -> Result<Avatar, sqlx::Error> {
+    let avatar = sqlx::query_as!(
+        Avatar,
+        r#"
+        SELECT
+            avatar_path AS path,
+            avatar_url AS url,
+            avatar_pending_url AS pending_url,
+            avatar_error AS error
+        FROM users
+        WHERE id = $1
+        FOR UPDATE
+        "#,
+        user_id,
+    )
+    .fetch_one(&mut **tx)
+    .await?;
+
+    Ok(avatar)
+}
+
+#[tracing::instrument(name = "Getting user avatar from database", skip(pool))]
+pub async fn get_avatar(pool: &sqlx::PgPool, user_id: Uuid) -> Result<Avatar, sqlx::Error> {
+    let avatar = sqlx::query_as!(
+        Avatar,
+        r#"
+        SELECT
+            avatar_path AS path,
+            avatar_url AS url,
+            avatar_pending_url AS pending_url,
+            avatar_error AS error
+        FROM users
+        WHERE id = $1
+        "#,
+        user_id,
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(avatar)
+}
+
+#[tracing::instrument(name = "Saving user avatar to database", skip(tx))]
+pub async fn set_avatar(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    avatar: &Avatar,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET
+            avatar_path = $2,
+            avatar_url = $3,
+            avatar_pending_url = NULL,
+            avatar_error = NULL
+        WHERE id = $1
+        "#,
+        user_id,
+        avatar.path,
+        avatar.url,
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+/// Records `url` as the avatar to download next, superseding any earlier request.
+#[tracing::instrument(name = "Requesting avatar download", skip(tx))]
+pub async fn request_avatar(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    url: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET avatar_pending_url = $2, avatar_error = NULL
+        WHERE id = $1
+        "#,
+        user_id,
+        url,
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+/// Gives up on downloading `url`, unless another avatar was requested since.
+#[tracing::instrument(name = "Recording failed avatar download", skip(tx))]
+pub async fn fail_avatar_request(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    url: &str,
+    error: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET avatar_pending_url = NULL, avatar_error = $3
+        WHERE id = $1 AND avatar_pending_url = $2
+        "#,
+        user_id,
+        url,
+        error,
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,265 @@
+use std::path::Path;
+
+use atom_syndication as atom;
+use chrono::{DateTime, Utc};
+use rss::extension::dublincore::DublinCoreExtension;
+use serde::Serialize;
+
+use crate::{
+    domain::{BlogPost, MediaFormat},
+    startup::UPLOADS_ROUTE,
+};
+
+/// What a feed is about and where it lives. URLs are absolute, built from the
+/// configured base URL, as feed readers resolve nothing against the page they
+/// found the feed on.
+#[derive(Debug)]
+pub struct FeedInfo {
+    pub title: String,
+    pub description: String,
+    /// URL of the feed document itself.
+    pub feed_url: String,
+    /// URL of the HTML page listing the same posts.
+    pub html_url: String,
+    /// Prefix for links to posts and uploads.
+    pub base_url: String,
+}
+
+impl FeedInfo {
+    fn post_url(&self, post: &BlogPost) -> String {
+        format!("{}/posts/{}", self.base_url, post.id)
+    }
+
+    fn upload_url(&self, key: &str) -> String {
+        format!("{}{}/{}", self.base_url, UPLOADS_ROUTE, key)
+    }
+}
+
+/// Identifier of a post that stays the same wherever the blog is hosted.
+fn post_urn(post: &BlogPost) -> String {
+    format!("urn:uuid:{}", post.id)
+}
+
+fn image_mime_type(key: &str) -> &'static str {
+    Path::new(key)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .and_then(MediaFormat::from_extension)
+        .map_or("application/octet-stream", MediaFormat::mime_type)
+}
+
+/// When the newest change to any of the posts was made.
+pub fn last_modified(posts: &[BlogPost]) -> Option<DateTime<Utc>> {
+    posts.iter().map(|post| post.updated_at).max()
+}
+
+/// Renders the posts as an RSS 2.0 document.
+pub fn rss_feed(info: &FeedInfo, posts: &[BlogPost]) -> String {
+    let items = posts
+        .iter()
+        .map(|post| rss::Item {
+            title: Some(post.title()),
+            link: Some(info.post_url(post)),
+            description: Some(post.text_html.clone()),
+            guid: Some(rss::Guid {
+                value: post_urn(post),
+                permalink: false,
+            }),
+            pub_date: Some(post.published_at.to_rfc2822()),
+            categories: post
+                .tags
+                .iter()
+                .map(|tag| rss::Category {
+                    name: tag.clone(),
+                    domain: None,
+                })
+                .collect(),
+            // The size of stored images is not recorded, and zero is what the
+            // spec suggests for an unknown length.
+            enclosure: post.image_path.as_ref().map(|key| rss::Enclosure {
+                url: info.upload_url(key),
+                length: "0".to_string(),
+                mime_type: image_mime_type(key).to_string(),
+            }),
+            // <author> would have to be an email address.
+            dublin_core_ext: Some(DublinCoreExtension {
+                creators: vec![post.username.clone()],
+                ..DublinCoreExtension::default()
+            }),
+            ..rss::Item::default()
+        })
+        .collect();
+
+    let channel = rss::Channel {
+        title: info.title.clone(),
+        link: info.html_url.clone(),
+        description: info.description.clone(),
+        last_build_date: last_modified(posts).map(|modified| modified.to_rfc2822()),
+        items,
+        ..rss::Channel::default()
+    };
+    channel.to_string()
+}
+
+/// Renders the posts as an Atom 1.0 document.
+pub fn atom_feed(info: &FeedInfo, posts: &[BlogPost]) -> String {
+    let entries = posts
+        .iter()
+        .map(|post| {
+            let mut links = vec![atom::Link {
+                href: info.post_url(post),
+                rel: "alternate".to_string(),
+                mime_type: Some("text/html".to_string()),
+                ..atom::Link::default()
+            }];
+            if let Some(key) = &post.image_path {
+                links.push(atom::Link {
+                    href: info.upload_url(key),
+                    rel: "enclosure".to_string(),
+                    mime_type: Some(image_mime_type(key).to_string()),
+                    ..atom::Link::default()
+                });
+            }
+
+            atom::Entry {
+                title: atom::Text::plain(post.title()),
+                id: post_urn(post),
+                updated: post.updated_at.fixed_offset(),
+                published: Some(post.published_at.fixed_offset()),
+                authors: vec![atom::Person {
+                    name: post.username.clone(),
+                    ..atom::Person::default()
+                }],
+                categories: post
+                    .tags
+                    .iter()
+                    .map(|tag| atom::Category {
+                        term: tag.clone(),
+                        ..atom::Category::default()
+                    })
+                    .collect(),
+                links,
+                content: Some(atom::Content {
+                    value: Some(post.text_html.clone()),
+                    content_type: Some("html".to_string()),
+                    ..atom::Content::default()
+                }),
+                ..atom::Entry::default()
+            }
+        })
+        .collect();
+
+    let feed = atom::Feed {
+        title: atom::Text::plain(info.title.clone()),
+        subtitle: Some(atom::Text::plain(info.description.clone())),
+        id: info.feed_url.clone(),
+        updated: last_modified(posts)
+            .unwrap_or(DateTime::UNIX_EPOCH)
+            .fixed_offset(),
+        links: vec![
+            atom::Link {
+                href: info.feed_url.clone(),
+                rel: "self".to_string(),
+                mime_type: Some("application/atom+xml".to_string()),
+                ..atom::Link::default()
+            },
+            atom::Link {
+                href: info.html_url.clone(),
+                rel: "alternate".to_string(),
+                mime_type: Some("text/html".to_string()),
+                ..atom::Link::default()
+            },
+        ],
+        entries,
+        ..atom::Feed::default()
+    };
+    feed.to_string()
+}
+
+/// Version URL identifying a [JSON Feed](https://www.jsonfeed.org/version/1.1/) document.
+pub const JSON_FEED_VERSION: &str = "https://jsonfeed.org/version/1.1";
+
+#[derive(Debug, Serialize)]
+struct JsonFeed<'a> {
+    version: &'static str,
+    title: &'a str,
+    description: &'a str,
+    home_page_url: &'a str,
+    feed_url: &'a str,
+    items: Vec<JsonFeedItem>,
+}
+
+#[derive(Debug, Serialize)]
+struct JsonFeedItem {
+    id: String,
+    url: String,
+    title: String,
+    content_html: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    image: Option<String>,
+    date_published: DateTime<Utc>,
+    date_modified: DateTime<Utc>,
+    authors: Vec<JsonFeedAuthor>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    attachments: Vec<JsonFeedAttachment>,
+}
+
+#[derive(Debug, Serialize)]
+struct JsonFeedAuthor {
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    avatar: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct JsonFeedAttachment {
+    url: String,
+    mime_type: &'static str,
+}
+
+/// Renders the posts as a JSON Feed 1.1 document.
+pub fn json_feed(info: &FeedInfo, posts: &[BlogPost]) -> String {
+    let items = posts
+        .iter()
+        .map(|post| {
+            let image = post.image_path.as_ref().map(|key| info.upload_url(key));
+            JsonFeedItem {
+                id: post_urn(post),
+                url: info.post_url(post),
+                title: post.title(),
+                content_html: post.text_html.clone(),
+                attachments: post
+                    .image_path
+                    .iter()
+                    .map(|key| JsonFeedAttachment {
+                        url: info.upload_url(key),
+                        mime_type: image_mime_type(key),
+                    })
+                    .collect(),
+                image,
+                date_published: post.published_at,
+                date_modified: post.updated_at,
+                authors: vec![JsonFeedAuthor {
+                    name: post.username.clone(),
+                    avatar: post
+                        .user_avatar_path
+                        .as_ref()
+                        .map(|key| info.upload_url(key)),
+                }],
+                tags: post.tags.clone(),
+            }
+        })
+        .collect();
+
+    let feed = JsonFeed {
+        version: JSON_FEED_VERSION,
+        title: &info.title,
+        description: &info.description,
+        home_page_url: &info.html_url,
+        feed_url: &info.feed_url,
+        items,
+    };
+    serde_json::to_string(&feed).expect("JSON Feed documents always serialize")
+}
@@ -0,0 +1,236 @@
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use ipnet::IpNet;
+use once_cell::sync::Lazy;
+use reqwest::{
+    dns::{Addrs, Name, Resolve, Resolving},
+    header,
+    redirect::Policy,
+    Client, Response, Url,
+};
+use url::Host;
+
+use crate::configuration::FetchSettings;
+
+/// Ranges that are never reachable from user-supplied URLs unless explicitly
+/// allowed: loopback, private, link-local, shared, multicast, discard and reserved
+/// space, as well as IPv6 ranges embedding IPv4 addresses such as IPv4-compatible
+/// addresses, NAT64, 6to4 and Teredo.
+static BLOCKED_NETWORKS: Lazy<Vec<IpNet>> = Lazy::new(|| {
+    [
+        "0.0.0.0/8",
+        "10.0.0.0/8",
+        "100.64.0.0/10",
+        "127.0.0.0/8",
+        "169.254.0.0/16",
+        "172.16.0.0/12",
+        "192.0.0.0/24",
+        "192.168.0.0/16",
+        "198.18.0.0/15",
+        "224.0.0.0/4",
+        "240.0.0.0/4",
+        "::/96",
+        "::1/128",
+        "64:ff9b::/96",
+        "100::/64",
+        "2001::/32",
+        "2002::/16",
+        "fc00::/7",
+        "fe80::/10",
+        "ff00::/8",
+    ]
+    .iter()
+    .map(|network| network.parse().unwrap())
+    .collect()
+});
+
+#[derive(Debug, thiserror::Error)]
+pub enum FetchError {
+    #[error("{0}")]
+    Blocked(String),
+
+    #[error("Invalid URL: {0}")]
+    InvalidUrl(String),
+
+    #[error("Stopped after {0} redirects")]
+    TooManyRedirects(usize),
+
+    #[error("Failed to resolve {0}: {1}")]
+    Resolve(String, std::io::Error),
+
+    #[error("{0} does not resolve to any address")]
+    NoAddresses(String),
+
+    #[error(transparent)]
+    Request(#[from] reqwest::Error),
+}
+
+/// HTTP client for URLs supplied by users. Every hop is resolved and checked
+/// against the address rules before connecting, and the connection is pinned
+/// to the checked addresses so a second DNS answer cannot point elsewhere.
+#[derive(Clone)]
+pub struct GuardedFetcher {
+    settings: FetchSettings,
+}
+
+impl GuardedFetcher {
+    pub fn new(settings: FetchSettings) -> Self {
+        Self { settings }
+    }
+
+    /// Longest a whole fetch may take, reading the body included.
+    pub fn timeout(&self) -> Duration {
+        self.settings.timeout()
+    }
+
+    /// Checks `url` against the address rules without connecting, so requests
+    /// for blocked addresses can be rejected before they are queued. Redirects
+    /// are still checked when the URL is actually fetched.
+    pub async fn check(&self, url: &str) -> Result<(), FetchError> {
+        let url = Url::parse(url).map_err(|e| FetchError::InvalidUrl(e.to_string()))?;
+        self.resolve_checked(&url).await.map(|_| ())
+    }
+
+    /// Sends a `GET` to `url`, following up to `max_redirects` redirects.
+    #[tracing::instrument(name = "Fetching user supplied URL", skip(self))]
+    pub async fn get(&self, url: &str) -> Result<Response, FetchError> {
+        let mut url = Url::parse(url).map_err(|e| FetchError::InvalidUrl(e.to_string()))?;
+
+        let resolver = Arc::new(PinnedResolver::default());
+        // A proxy would connect to the host on its own, bypassing the checked addresses.
+        let client = Client::builder()
+            .redirect(Policy::none())
+            .no_proxy()
+            .connect_timeout(self.settings.connect_timeout())
+            .read_timeout(self.settings.read_timeout())
+            .dns_resolver(Arc::clone(&resolver))
+            .build()?;
+
+        for _ in 0..=self.settings.max_redirects {
+            if let (Some(domain), addresses) = self.resolve_checked(&url).await? {
+                resolver.pin(domain, addresses);
+            }
+            let response = client.get(url.clone()).send().await?;
+            if !response.status().is_redirection() {
+                return Ok(response);
+            }
+
+            let location = response
+                .headers()
+                .get(header::LOCATION)
+                .and_then(|location| location.to_str().ok())
+                .ok_or_else(|| FetchError::InvalidUrl("Redirect without location".to_string()))?;
+            url = url
+                .join(location)
+                .map_err(|e| FetchError::InvalidUrl(e.to_string()))?;
+        }
+
+        Err(FetchError::TooManyRedirects(self.settings.max_redirects))
+    }
+
+    /// Resolves the host of `url` and checks every address it resolves to,
+    /// returning the domain, if the host is not an address itself, with the
+    /// addresses it must be reached at.
+    async fn resolve_checked(
+        &self,
+        url: &Url,
+    ) -> Result<(Option<String>, Vec<SocketAddr>), FetchError> {
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(FetchError::Blocked(format!(
+                "Scheme {} is not allowed",
+                url.scheme()
+            )));
+        }
+
+        let port = url
+            .port_or_known_default()
+            .ok_or_else(|| FetchError::InvalidUrl(url.to_string()))?;
+        let (domain, addresses) = match url.host() {
+            Some(Host::Ipv4(ip)) => (None, vec![SocketAddr::new(ip.into(), port)]),
+            Some(Host::Ipv6(ip)) => (None, vec![SocketAddr::new(ip.into(), port)]),
+            Some(Host::Domain(domain)) => {
+                if self.is_denied_host(domain) {
+                    return Err(FetchError::Blocked(format!(
+                        "Host {} is not allowed",
+                        domain
+                    )));
+                }
+                let addresses: Vec<SocketAddr> = tokio::net::lookup_host((domain, port))
+                    .await
+                    .map_err(|e| FetchError::Resolve(domain.to_string(), e))?
+                    .collect();
+                // The client would otherwise fall back to resolving the host itself.
+                if addresses.is_empty() {
+                    return Err(FetchError::NoAddresses(domain.to_string()));
+                }
+                (Some(domain.to_string()), addresses)
+            }
+            None => return Err(FetchError::InvalidUrl(url.to_string())),
+        };
+
+        // A host is only trusted if every address it resolves to is.
+        if let Some(address) = addresses
+            .iter()
+            .find(|address| !self.is_allowed(address.ip()))
+        {
+            return Err(FetchError::Blocked(format!(
+                "Address {} is not allowed",
+                address.ip()
+            )));
+        }
+
+        Ok((domain, addresses))
+    }
+
+    fn is_denied_host(&self, domain: &str) -> bool {
+        let domain = domain.trim_end_matches('.').to_ascii_lowercase();
+        self.settings
+            .denied_hosts
+            .iter()
+            .any(|denied| domain == *denied || domain.ends_with(&format!(".{}", denied)))
+    }
+
+    fn is_allowed(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        let contains = |networks: &[IpNet]| networks.iter().any(|network| network.contains(&ip));
+
+        if contains(&self.settings.denied_networks) {
+            return false;
+        }
+        if contains(&self.settings.allowed_networks) {
+            return true;
+        }
+        !contains(&BLOCKED_NETWORKS)
+    }
+}
+
+/// Resolver of the client used for one fetch, answering only with the
+/// addresses a host was checked to resolve to. Hosts are pinned again on every
+/// hop, so one client serves all redirects of a fetch.
+#[derive(Default)]
+struct PinnedResolver {
+    addresses: Mutex<HashMap<String, Vec<SocketAddr>>>,
+}
+
+impl PinnedResolver {
+    fn pin(&self, domain: String, addresses: Vec<SocketAddr>) {
+        self.addresses.lock().unwrap().insert(domain, addresses);
+    }
+}
+
+impl Resolve for PinnedResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let addresses = self.addresses.lock().unwrap().get(name.as_str()).cloned();
+        Box::pin(async move {
+            // The client never falls back to resolving a host on its own.
+            let addresses = addresses
+                .ok_or_else(|| format!("{} was not checked before connecting", name.as_str()))?;
+            Ok(Box::new(addresses.into_iter()) as Addrs)
+        })
+    }
+}
@@ -0,0 +1,302 @@
+use std::{sync::Arc, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use sqlx::{types::Json, PgPool, Postgres, Transaction};
+use tracing::{error, warn};
+use uuid::Uuid;
+
+use crate::{
+    configuration::JobSettings,
+    domain::{clear_pending_image, media::lock_removable_file, users::fail_avatar_request},
+    routes::{
+        errors::CreatePostError,
+        posts::{process_post_image, CleanupGuard},
+        profile::download_requested_avatar,
+    },
+    startup::AppState,
+};
+
+/// Work done by the background workers instead of the request that caused it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Job {
+    /// Turns a raw upload into the image of a post.
+    ProcessPostImage { post_id: Uuid, upload_key: String },
+    /// Downloads the avatar requested by a user.
+    DownloadAvatar { user_id: Uuid, url: String },
+    /// Removes stored files that are no longer referenced.
+    RemoveFiles { keys: Vec<String> },
+}
+
+#[tracing::instrument(name = "Enqueueing job", skip(tx))]
+pub async fn enqueue(tx: &mut Transaction<'_, Postgres>, job: &Job) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO jobs (id, payload)
+        VALUES ($1, $2)
+        "#,
+        Uuid::new_v4(),
+        Json(job) as _,
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+/// Queues the removal of files that are only unreferenced once the
+/// transaction commits. Content-addressed files are kept if their media is
+/// stored again before the job runs.
+pub async fn enqueue_file_removal(
+    tx: &mut Transaction<'_, Postgres>,
+    keys: Vec<String>,
+) -> Result<(), sqlx::Error> {
+    if keys.is_empty() {
+        return Ok(());
+    }
+    enqueue(tx, &Job::RemoveFiles { keys }).await
+}
+
+/// Spawns the configured number of workers, each polling the queue until the
+/// runtime shuts down.
+pub fn start_workers(state: &Arc<AppState>, settings: &JobSettings) {
+    for _ in 0..settings.workers {
+        tokio::spawn(run_worker(Arc::clone(state), settings.clone()));
+    }
+}
+
+enum ExecutionOutcome {
+    JobCompleted,
+    EmptyQueue,
+}
+
+async fn run_worker(state: Arc<AppState>, settings: JobSettings) {
+    loop {
+        match try_execute_job(&state, &settings).await {
+            Ok(ExecutionOutcome::JobCompleted) => {}
+            Ok(ExecutionOutcome::EmptyQueue) => tokio::time::sleep(settings.poll_interval()).await,
+            Err(e) => {
+                error!("Failed to execute job: {}", e);
+                tokio::time::sleep(settings.poll_interval()).await;
+            }
+        }
+    }
+}
+
+struct QueuedJob {
+    id: Uuid,
+    payload: Json<Job>,
+    attempts: i32,
+}
+
+/// Claims the next due job and runs it. No transaction is held while the job
+/// downloads or processes anything; its changes are committed together with
+/// its removal from the queue once done.
+#[tracing::instrument(name = "Executing job", skip_all, fields(job_id, kind))]
+async fn try_execute_job(
+    state: &AppState,
+    settings: &JobSettings,
+) -> Result<ExecutionOutcome, sqlx::Error> {
+    let Some(job) = claim(&state.connection_pool, settings.lease()).await? else {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    };
+    tracing::Span::current()
+        .record("job_id", tracing::field::display(job.id))
+        .record("kind", tracing::field::debug(&job.payload.0));
+
+    let mut cleanup_guard = CleanupGuard::new(&state.storage);
+    let e = match complete(state, &job, &mut cleanup_guard).await {
+        Ok(()) => {
+            cleanup_guard.dismiss();
+            return Ok(ExecutionOutcome::JobCompleted);
+        }
+        Err(e) => e,
+    };
+
+    // Content-addressed files the run stored may be stored again by another
+    // job meanwhile, so they are only removed by a job that checks first. They
+    // are taken before anything else can fail, leaving them to the sweeper
+    // rather than to an unchecked removal by the guard.
+    let mut stale_files = cleanup_guard.take_keys();
+    let mut tx = state.connection_pool.begin().await?;
+    let attempts = job.attempts + 1;
+    if e.is_transient() && attempts < settings.max_attempts {
+        let delay = settings.retry_delay(attempts);
+        warn!("Job failed, retrying in {:?}: {}", delay, e);
+        schedule_retry(&mut tx, job.id, attempts, delay, &e.to_string()).await?;
+    } else {
+        error!("Job failed after {} attempts: {}", attempts, e);
+        stale_files.extend(abandon(&mut tx, &job.payload, &e.to_string()).await?);
+        mark_failed(&mut tx, job.id, attempts, &e.to_string()).await?;
+    }
+    enqueue_file_removal(&mut tx, stale_files).await?;
+    tx.commit().await?;
+
+    Ok(ExecutionOutcome::JobCompleted)
+}
+
+/// Runs the job and commits its changes, removing it from the queue and
+/// queueing the removal of the files it made stale.
+async fn complete(
+    state: &AppState,
+    job: &QueuedJob,
+    cleanup_guard: &mut CleanupGuard,
+) -> Result<(), CreatePostError> {
+    let (mut tx, stale_files) = execute(state, &job.payload, cleanup_guard).await?;
+    delete_job(&mut tx, job.id).await?;
+    enqueue_file_removal(&mut tx, stale_files).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Runs the job, returning the transaction holding its changes and the files
+/// to remove once it commits. Handlers only open the transaction after the
+/// slow work is done, so rows are locked just long enough to write the result.
+async fn execute(
+    state: &AppState,
+    job: &Job,
+    cleanup_guard: &mut CleanupGuard,
+) -> Result<(Transaction<'static, Postgres>, Vec<String>), CreatePostError> {
+    match job {
+        Job::ProcessPostImage {
+            post_id,
+            upload_key,
+        } => process_post_image(state, *post_id, upload_key, cleanup_guard).await,
+        Job::DownloadAvatar { user_id, url } => {
+            download_requested_avatar(state, *user_id, url, cleanup_guard).await
+        }
+        Job::RemoveFiles { keys } => {
+            let mut tx = state.connection_pool.begin().await?;
+            remove_files(state, &mut tx, keys).await?;
+            Ok((tx, Vec::new()))
+        }
+    }
+}
+
+/// Removes the files, skipping content-addressed ones whose media was stored
+/// again since they were released.
+async fn remove_files(
+    state: &AppState,
+    tx: &mut Transaction<'_, Postgres>,
+    keys: &[String],
+) -> Result<(), CreatePostError> {
+    for key in keys {
+        if lock_removable_file(tx, key).await? {
+            state.storage.delete(key).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Resolves whatever waits on a job that will not be retried, returning the
+/// files to remove.
+async fn abandon(
+    tx: &mut Transaction<'_, Postgres>,
+    job: &Job,
+    error: &str,
+) -> Result<Vec<String>, sqlx::Error> {
+    match job {
+        Job::ProcessPostImage {
+            post_id,
+            upload_key,
+        } => {
+            clear_pending_image(tx, *post_id, upload_key).await?;
+            Ok(vec![upload_key.clone()])
+        }
+        Job::DownloadAvatar { user_id, url } => {
+            fail_avatar_request(tx, *user_id, url, error).await?;
+            Ok(Vec::new())
+        }
+        Job::RemoveFiles { .. } => Ok(Vec::new()),
+    }
+}
+
+/// Takes the next due job off the queue for the length of the lease. The row
+/// is only locked while it is claimed, and the job becomes due again should
+/// its worker die before completing it.
+async fn claim(pool: &PgPool, lease: Duration) -> Result<Option<QueuedJob>, sqlx::Error> {
+    let job = sqlx::query_as!(
+        QueuedJob,
+        r#"
+        UPDATE jobs
+        SET run_at = now() + make_interval(secs => $1)
+        WHERE id = (
+            SELECT id
+            FROM jobs
+            WHERE failed_at IS NULL AND run_at <= now()
+            ORDER BY run_at
+            FOR UPDATE SKIP LOCKED
+            LIMIT 1
+        )
+        RETURNING id, payload AS "payload: Json<Job>", attempts
+        "#,
+        lease.as_secs_f64(),
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(job)
+}
+
+async fn delete_job(tx: &mut Transaction<'_, Postgres>, id: Uuid) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        DELETE FROM jobs
+        WHERE id = $1
+        "#,
+        id,
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+async fn schedule_retry(
+    tx: &mut Transaction<'_, Postgres>,
+    id: Uuid,
+    attempts: i32,
+    delay: Duration,
+    error: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE jobs
+        SET
+            attempts = $2,
+            run_at = now() + make_interval(secs => $3),
+            last_error = $4
+        WHERE id = $1
+        "#,
+        id,
+        attempts,
+        delay.as_secs_f64(),
+        error,
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+async fn mark_failed(
+    tx: &mut Transaction<'_, Postgres>,
+    id: Uuid,
+    attempts: i32,
+    error: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE jobs
+        SET attempts = $2, last_error = $3, failed_at = now()
+        WHERE id = $1
+        "#,
+        id,
+        attempts,
+        error,
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
@@ -1,6 +1,14 @@
+pub mod blocking;
 pub mod configuration;
 pub mod domain;
+pub mod feeds;
+pub mod fetcher;
+pub mod jobs;
+pub mod markdown;
+pub mod pagination;
 pub mod routes;
 pub mod startup;
+pub mod storage;
+pub mod sweeper;
 pub mod telemetry;
 pub mod templates;
@@ -1,5 +1,6 @@
 use jetbrains_web_app::configuration;
 use jetbrains_web_app::startup::Appliaction;
+use jetbrains_web_app::sweeper::sweep_once;
 use jetbrains_web_app::telemetry::initialize_tracing;
 
 #[tokio::main]
@@ -7,9 +8,27 @@ async fn main() {
     initialize_tracing("trace").expect("Failed to initialize application tracing.");
     let configuration = configuration::get_configuration().expect("Failed to read configuration.");
 
-    let application = Appliaction::build(&configuration)
-        .await
-        .expect("Failed to build application.");
+    match std::env::args().nth(1).as_deref() {
+        None | Some("serve") => {
+            let application = Appliaction::build(&configuration)
+                .await
+                .expect("Failed to build application.");
 
-    application.run_until_stopped().await;
+            application.run_until_stopped().await;
+        }
+        Some("sweep-orphans") => {
+            let report = sweep_once(&configuration)
+                .await
+                .expect("Failed to sweep orphaned files.");
+            println!(
+                "Swept {} orphaned files out of {}",
+                report.orphans.len(),
+                report.scanned
+            );
+        }
+        Some(command) => {
+            eprintln!("Unknown command {command}, expected serve or sweep-orphans");
+            std::process::exit(2);
+        }
+    }
 }
@@ -0,0 +1,165 @@
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    io::{self, Write},
+};
+
+use comrak::{
+    adapters::SyntaxHighlighterAdapter, html, markdown_to_html_with_plugins, nodes::NodeValue,
+    parse_document, Arena, Options, Plugins,
+};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use syntect::{
+    html::{ClassStyle, ClassedHTMLGenerator},
+    parsing::SyntaxSet,
+    util::LinesWithEndings,
+};
+
+/// Prefix of the classes given to highlighted code, styled by the page stylesheet.
+pub const HIGHLIGHT_CLASS_PREFIX: &str = "hl-";
+
+/// Options for CommonMark with the GitHub tables, strikethrough and autolink
+/// extensions. Single line breaks are kept, as authors expect from a text box.
+static OPTIONS: Lazy<Options<'static>> = Lazy::new(|| {
+    let mut options = Options::default();
+    options.extension.table = true;
+    options.extension.strikethrough = true;
+    options.extension.autolink = true;
+    options.render.hardbreaks = true;
+    // Raw HTML is passed through to the sanitizer, which keeps the harmless tags.
+    options.render.unsafe_ = true;
+    options
+});
+
+/// A `#` starting a word, followed by a letter or underscore. Anchors in URLs
+/// and character references like `&#39;` are not hashtags.
+static HASHTAG_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?:^|[^\w&/#])#([^\W\d][\w-]*)").unwrap());
+
+static SYNTAXES: Lazy<SyntaxSet> = Lazy::new(SyntaxSet::load_defaults_newlines);
+
+static SANITIZER: Lazy<ammonia::Builder<'static>> = Lazy::new(|| {
+    let mut builder = ammonia::Builder::default();
+    builder
+        .link_rel(Some("noopener noreferrer nofollow"))
+        .url_schemes(HashSet::from(["http", "https", "mailto"]))
+        .add_tag_attributes("code", ["class"])
+        .add_tag_attributes("span", ["class"])
+        .attribute_filter(|_, attribute, value| {
+            if attribute != "class" {
+                return Some(Cow::Borrowed(value));
+            }
+            // Only the classes of highlighted code survive, so posts cannot
+            // borrow the styling of the page around them.
+            let classes: Vec<&str> = value
+                .split_whitespace()
+                .filter(|class| {
+                    class.starts_with(HIGHLIGHT_CLASS_PREFIX) || class.starts_with("language-")
+                })
+                .collect();
+            (!classes.is_empty()).then(|| Cow::Owned(classes.join(" ")))
+        });
+    builder
+});
+
+/// Highlights fenced code blocks by their language hint, marking tokens with
+/// classes named after their syntax scopes (`hl-keyword`, `hl-string`, ...).
+/// Blocks without a known language are left plain.
+struct Highlighter;
+
+impl Highlighter {
+    fn highlight(code: &str, lang: &str) -> Option<String> {
+        let syntax = SYNTAXES.find_syntax_by_token(lang)?;
+        let mut generator = ClassedHTMLGenerator::new_with_class_style(
+            syntax,
+            &SYNTAXES,
+            ClassStyle::SpacedPrefixed {
+                prefix: HIGHLIGHT_CLASS_PREFIX,
+            },
+        );
+        for line in LinesWithEndings::from(code) {
+            generator
+                .parse_html_for_line_which_includes_newline(line)
+                .ok()?;
+        }
+        Some(generator.finalize())
+    }
+}
+
+impl SyntaxHighlighterAdapter for Highlighter {
+    fn write_highlighted(
+        &self,
+        output: &mut dyn Write,
+        lang: Option<&str>,
+        code: &str,
+    ) -> io::Result<()> {
+        match lang.and_then(|lang| Self::highlight(code, lang)) {
+            Some(highlighted) => output.write_all(highlighted.as_bytes()),
+            None => html::escape(output, code.as_bytes()),
+        }
+    }
+
+    fn write_pre_tag(
+        &self,
+        output: &mut dyn Write,
+        attributes: HashMap<String, String>,
+    ) -> io::Result<()> {
+        html::write_opening_tag(output, "pre", attributes)
+    }
+
+    fn write_code_tag(
+        &self,
+        output: &mut dyn Write,
+        attributes: HashMap<String, String>,
+    ) -> io::Result<()> {
+        html::write_opening_tag(output, "code", attributes)
+    }
+}
+
+/// Renders Markdown post text into HTML that is safe to embed in a page.
+pub fn render_markdown(source: &str) -> String {
+    let mut plugins = Plugins::default();
+    plugins.render.codefence_syntax_highlighter = Some(&Highlighter);
+
+    let html = markdown_to_html_with_plugins(source, &OPTIONS, &plugins);
+    SANITIZER.clean(&html).to_string()
+}
+
+/// The text of Markdown without its markup, one line per line break or block.
+pub fn plain_text(source: &str) -> String {
+    let arena = Arena::new();
+    let root = parse_document(&arena, source, &OPTIONS);
+
+    let mut text = String::new();
+    for node in root.descendants() {
+        match &node.data.borrow().value {
+            NodeValue::Text(literal) => text.push_str(literal),
+            NodeValue::Code(code) => text.push_str(&code.literal),
+            NodeValue::CodeBlock(block) => text.push_str(&block.literal),
+            NodeValue::SoftBreak | NodeValue::LineBreak => text.push('\n'),
+            value if value.block() && !text.is_empty() && !text.ends_with('\n') => text.push('\n'),
+            _ => {}
+        }
+    }
+    text
+}
+
+/// Names of the `#hashtags` in the prose of Markdown text, without the `#`.
+/// Code spans and blocks are skipped, so `#include` in a snippet is no tag.
+pub fn extract_hashtags(source: &str) -> Vec<String> {
+    let arena = Arena::new();
+    let root = parse_document(&arena, source, &OPTIONS);
+
+    let mut hashtags = Vec::new();
+    for node in root.descendants() {
+        if let NodeValue::Text(text) = &node.data.borrow().value {
+            hashtags.extend(
+                HASHTAG_RE
+                    .captures_iter(text)
+                    .map(|captures| captures[1].trim_end_matches('-').to_string()),
+            );
+        }
+    }
+    hashtags
+}
@@ -0,0 +1,138 @@
+use std::{fmt, str::FromStr};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+pub const DEFAULT_PAGE_SIZE: i64 = 20;
+pub const MAX_PAGE_SIZE: i64 = 100;
+
+/// Position of an item in a listing ordered by `(published_at, id)`, newest first.
+///
+/// Rendered as `<published_at in microseconds>_<id>` so it can be passed around in
+/// query strings without further encoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cursor {
+    pub published_at: DateTime<Utc>,
+    pub id: Uuid,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Invalid pagination cursor")]
+pub struct InvalidCursor;
+
+impl fmt::Display for Cursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}_{}", self.published_at.timestamp_micros(), self.id)
+    }
+}
+
+impl FromStr for Cursor {
+    type Err = InvalidCursor;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (micros, id) = s.split_once('_').ok_or(InvalidCursor)?;
+        let micros = micros.parse::<i64>().map_err(|_| InvalidCursor)?;
+
+        Ok(Self {
+            published_at: DateTime::from_timestamp_micros(micros).ok_or(InvalidCursor)?,
+            id: Uuid::parse_str(id).map_err(|_| InvalidCursor)?,
+        })
+    }
+}
+
+impl Serialize for Cursor {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Cursor {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        value.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Items that can be paginated by [`Cursor`].
+pub trait Paginated {
+    fn cursor(&self) -> Cursor;
+}
+
+/// Pagination parameters accepted by listings: `?before=`, `?after=` and `?limit=`.
+///
+/// `before` walks towards older items and `after` towards newer ones. When both are
+/// given, `after` takes precedence.
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PageQuery {
+    /// Cursor of the oldest item seen, to list older items.
+    #[param(value_type = Option<String>)]
+    pub before: Option<Cursor>,
+    /// Cursor of the newest item seen, to list newer items.
+    #[param(value_type = Option<String>)]
+    pub after: Option<Cursor>,
+    /// Number of items per page, 20 by default and at most 100.
+    pub limit: Option<i64>,
+}
+
+impl PageQuery {
+    pub fn limit(&self) -> i64 {
+        self.limit
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE)
+    }
+
+    /// Number of rows to fetch; one more than the limit tells whether another page exists.
+    pub fn fetch_limit(&self) -> i64 {
+        self.limit() + 1
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Cursor for `?before=`, if there are older items.
+    #[schema(value_type = Option<String>)]
+    pub older: Option<Cursor>,
+    /// Cursor for `?after=`, if there are newer items.
+    #[schema(value_type = Option<String>)]
+    pub newer: Option<Cursor>,
+}
+
+impl<T: Paginated> Page<T> {
+    /// Builds a page from up to [`PageQuery::fetch_limit`] rows. Rows must be ordered
+    /// newest first, or oldest first when the query walks forward with `after`.
+    pub fn from_rows(mut rows: Vec<T>, query: &PageQuery) -> Self {
+        let has_more = rows.len() as i64 > query.limit();
+        rows.truncate(query.limit() as usize);
+
+        let (has_older, has_newer) = if query.after.is_some() {
+            rows.reverse();
+            (true, has_more)
+        } else {
+            (has_more, query.before.is_some())
+        };
+
+        Self {
+            older: rows.last().filter(|_| has_older).map(Paginated::cursor),
+            newer: rows.first().filter(|_| has_newer).map(Paginated::cursor),
+            items: rows,
+        }
+    }
+}
+
+impl<T> Page<T> {
+    /// Query string leading to the page of older items, if there is one.
+    pub fn older_query(&self, limit: i64) -> Option<String> {
+        self.older
+            .map(|cursor| format!("before={}&limit={}", cursor, limit))
+    }
+
+    /// Query string leading to the page of newer items, if there is one.
+    pub fn newer_query(&self, limit: i64) -> Option<String> {
+        self.newer
+            .map(|cursor| format!("after={}&limit={}", cursor, limit))
+    }
+}
@@ -0,0 +1,224 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{rejection::JsonRejection, State},
+    response::{IntoResponse, Response},
+    Json,
+};
+use hyper::{header, StatusCode};
+use serde::Deserialize;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::{
+    domain::{get_post_by_id, get_posts_page, tags::normalize_tags, BlogPost, PostFilter},
+    pagination::{Page, PageQuery},
+    startup::AppState,
+};
+
+use super::{
+    caller::Caller,
+    errors::{CreatePostError, GetPostError},
+    extract::{Path, Query},
+    posts::{apply_post_update, delete_post_of, insert_post, invalid_tags, PostForm, UpdateMode},
+};
+
+/// Prefix of the versioned JSON API.
+pub const API_V1_ROUTE: &str = "/api/v1";
+
+/// Body of `POST` and `PUT` requests, the post as a whole.
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct PostRequest {
+    /// Markdown text of 10 to 10,000 characters.
+    pub text: String,
+    /// Explicit tags merged with the hashtags of the text.
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// Body of `PATCH` requests, missing fields are left as they are.
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct PostPatchRequest {
+    /// Markdown text of 10 to 10,000 characters.
+    pub text: Option<String>,
+    /// Explicit tags, replacing those given before.
+    pub tags: Option<Vec<String>>,
+}
+
+/// Unwraps a JSON body, reporting the ones that cannot be read as malformed.
+fn json_body<T>(
+    state: &AppState,
+    body: Result<Json<T>, JsonRejection>,
+) -> Result<T, CreatePostError> {
+    match body {
+        Ok(Json(body)) => Ok(body),
+        Err(rejection) if rejection.status() == StatusCode::PAYLOAD_TOO_LARGE => {
+            Err(CreatePostError::RequestTooLarge(state.max_request_size))
+        }
+        Err(rejection) => Err(CreatePostError::MalformedRequest(rejection.body_text())),
+    }
+}
+
+fn post_tags(tags: Vec<String>) -> Result<Vec<String>, CreatePostError> {
+    normalize_tags(tags.iter().map(String::as_str)).map_err(invalid_tags)
+}
+
+/// Reads a post back after it was written.
+async fn fetch_post(state: &AppState, id: Uuid) -> Result<BlogPost, CreatePostError> {
+    get_post_by_id(&state.connection_pool, id)
+        .await?
+        .ok_or(CreatePostError::NotFound)
+}
+
+/// Lists posts, newest first.
+#[utoipa::path(
+    get,
+    path = "/api/v1/posts",
+    tag = "api",
+    params(PageQuery),
+    responses(
+        (status = 200, description = "A page of posts", body = Page<BlogPost>),
+        GetPostError,
+    )
+)]
+#[tracing::instrument(name = "Listing posts through the API", skip(state))]
+pub async fn api_list_posts(
+    State(state): State<Arc<AppState>>,
+    Query(page_query): Query<PageQuery>,
+) -> Result<Json<Page<BlogPost>>, GetPostError> {
+    let page = get_posts_page(&state.connection_pool, &page_query, &PostFilter::default()).await?;
+    Ok(Json(page))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/posts/{id}",
+    tag = "api",
+    params(("id" = Uuid, Path, description = "Id of the post")),
+    responses((status = 200, description = "The post", body = BlogPost), GetPostError)
+)]
+#[tracing::instrument(name = "Getting a post through the API", skip(state))]
+pub async fn api_get_post(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<BlogPost>, GetPostError> {
+    let post = get_post_by_id(&state.connection_pool, id)
+        .await?
+        .ok_or(GetPostError::NotFound)?;
+    Ok(Json(post))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/posts",
+    tag = "api",
+    request_body = PostRequest,
+    responses(
+        (status = 201, description = "Post created", body = BlogPost, headers(
+            ("Location" = String, description = "URL of the new post"),
+        )),
+        CreatePostError,
+    ),
+    security(("session" = []))
+)]
+#[tracing::instrument(name = "Creating a post through the API", skip(state, body))]
+pub async fn api_create_post(
+    State(state): State<Arc<AppState>>,
+    caller: Caller,
+    body: Result<Json<PostRequest>, JsonRejection>,
+) -> Result<Response, CreatePostError> {
+    let body = json_body(&state, body)?;
+    let form = PostForm {
+        text: Some(body.text),
+        tags: Some(post_tags(body.tags)?),
+        image: None,
+    };
+
+    let id = insert_post(&state, caller, form).await?;
+    let post = fetch_post(&state, id).await?;
+
+    let location = format!("{}/posts/{}", API_V1_ROUTE, id);
+    Ok((
+        StatusCode::CREATED,
+        [(header::LOCATION, location)],
+        Json(post),
+    )
+        .into_response())
+}
+
+/// Replaces the text and tags of a post. Images are not part of the JSON
+/// representation, so the image of the post is kept.
+#[utoipa::path(
+    put,
+    path = "/api/v1/posts/{id}",
+    tag = "api",
+    params(("id" = Uuid, Path, description = "Id of the post")),
+    request_body = PostRequest,
+    responses((status = 200, description = "The updated post", body = BlogPost), CreatePostError),
+    security(("session" = []))
+)]
+#[tracing::instrument(name = "Replacing a post through the API", skip(state, body))]
+pub async fn api_replace_post(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+    caller: Caller,
+    body: Result<Json<PostRequest>, JsonRejection>,
+) -> Result<Json<BlogPost>, CreatePostError> {
+    let body = json_body(&state, body)?;
+    let form = PostForm {
+        text: Some(body.text),
+        tags: Some(post_tags(body.tags)?),
+        image: None,
+    };
+
+    apply_post_update(&state, id, &caller, form, UpdateMode::Merge).await?;
+    Ok(Json(fetch_post(&state, id).await?))
+}
+
+/// Changes the fields of a post present in the body.
+#[utoipa::path(
+    patch,
+    path = "/api/v1/posts/{id}",
+    tag = "api",
+    params(("id" = Uuid, Path, description = "Id of the post")),
+    request_body = PostPatchRequest,
+    responses((status = 200, description = "The updated post", body = BlogPost), CreatePostError),
+    security(("session" = []))
+)]
+#[tracing::instrument(name = "Patching a post through the API", skip(state, body))]
+pub async fn api_patch_post(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+    caller: Caller,
+    body: Result<Json<PostPatchRequest>, JsonRejection>,
+) -> Result<Json<BlogPost>, CreatePostError> {
+    let body = json_body(&state, body)?;
+    let form = PostForm {
+        text: body.text,
+        tags: body.tags.map(post_tags).transpose()?,
+        image: None,
+    };
+
+    apply_post_update(&state, id, &caller, form, UpdateMode::Merge).await?;
+    Ok(Json(fetch_post(&state, id).await?))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/posts/{id}",
+    tag = "api",
+    params(("id" = Uuid, Path, description = "Id of the post")),
+    responses((status = 204, description = "Post deleted"), CreatePostError),
+    security(("session" = []))
+)]
+#[tracing::instrument(name = "Deleting a post through the API", skip(state))]
+pub async fn api_delete_post(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+    caller: Caller,
+) -> Result<StatusCode, CreatePostError> {
+    delete_post_of(&state, id, &caller).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,200 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::State,
+    response::{IntoResponse, Redirect},
+};
+use axum_extra::extract::{
+    cookie::{Cookie, SameSite},
+    CookieJar,
+};
+use serde::Deserialize;
+use time::Duration;
+use utoipa::ToSchema;
+use validator::Validate;
+
+use crate::{
+    domain::{
+        sessions::{create_session, delete_session, SESSION_COOKIE, SESSION_LIFETIME_DAYS},
+        users::{
+            compute_password_hash, get_user_credentials, insert_user, verify_password_hash,
+            PasswordError, User, MAX_PASSWORD_LENGTH, MIN_PASSWORD_LENGTH,
+        },
+        USERNAME_RE,
+    },
+    startup::AppState,
+    templates::{LoginTemplate, RegisterTemplate},
+};
+
+use super::{
+    caller::Caller,
+    errors::{field_violations, AuthError},
+    extract::Form,
+};
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CredentialsForm {
+    /// 2 to 50 letters, digits, `_` or `-`.
+    username: String,
+    password: String,
+}
+
+#[derive(Debug, Validate)]
+struct NewUserData<'a> {
+    #[validate(length(
+        min = 2,
+        max = 50,
+        message = "Username must be between 2 and 50 characters"
+    ))]
+    #[validate(regex(path = *USERNAME_RE, message = "Username contains invalid characters"))]
+    username: &'a str,
+
+    #[validate(length(
+        min = "MIN_PASSWORD_LENGTH",
+        max = "MAX_PASSWORD_LENGTH",
+        message = "Password must be between 8 and 128 characters"
+    ))]
+    password: &'a str,
+}
+
+/// Registration page.
+#[utoipa::path(
+    get,
+    path = "/register",
+    tag = "auth",
+    responses((status = 200, description = "The registration form", body = String, content_type = "text/html"))
+)]
+#[tracing::instrument]
+pub async fn register_form() -> RegisterTemplate {
+    RegisterTemplate
+}
+
+/// Sign-in page.
+#[utoipa::path(
+    get,
+    path = "/login",
+    tag = "auth",
+    responses((status = 200, description = "The sign-in form", body = String, content_type = "text/html"))
+)]
+#[tracing::instrument]
+pub async fn login_form() -> LoginTemplate {
+    LoginTemplate
+}
+
+/// Registers a user and signs them in.
+#[utoipa::path(
+    post,
+    path = "/register",
+    tag = "auth",
+    request_body(content = CredentialsForm, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 303, description = "Signed in, the session cookie is set", headers(
+            ("Set-Cookie" = String, description = "The `session_id` cookie"),
+        )),
+        AuthError,
+    )
+)]
+#[tracing::instrument(name = "Registering a new user", skip(state, jar, form), fields(username = %form.username))]
+pub async fn register(
+    State(state): State<Arc<AppState>>,
+    jar: CookieJar,
+    Form(form): Form<CredentialsForm>,
+) -> Result<impl IntoResponse, AuthError> {
+    NewUserData {
+        username: &form.username,
+        password: &form.password,
+    }
+    .validate()
+    .map_err(|e| AuthError::ValidationError(field_violations(&e)))?;
+
+    let password_hash = tokio::task::spawn_blocking(move || compute_password_hash(&form.password))
+        .await
+        .map_err(|_| AuthError::InternalError)?
+        .map_err(|_| AuthError::InternalError)?;
+
+    let user = insert_user(&state.connection_pool, &form.username, &password_hash)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(ref db_error) if db_error.is_unique_violation() => {
+                AuthError::UsernameTaken
+            }
+            e => AuthError::DatabaseError(e),
+        })?;
+
+    start_session(&state, jar, &user).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/login",
+    tag = "auth",
+    request_body(content = CredentialsForm, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 303, description = "Signed in, the session cookie is set", headers(
+            ("Set-Cookie" = String, description = "The `session_id` cookie"),
+        )),
+        AuthError,
+    )
+)]
+#[tracing::instrument(name = "Logging in", skip(state, jar, form), fields(username = %form.username))]
+pub async fn login(
+    State(state): State<Arc<AppState>>,
+    jar: CookieJar,
+    Form(form): Form<CredentialsForm>,
+) -> Result<impl IntoResponse, AuthError> {
+    let credentials = get_user_credentials(&state.connection_pool, &form.username).await?;
+    let (user, password_hash) = match credentials {
+        Some((user, password_hash)) => (Some(user), Some(password_hash)),
+        None => (None, None),
+    };
+
+    tokio::task::spawn_blocking(move || {
+        verify_password_hash(password_hash.as_deref(), &form.password)
+    })
+    .await
+    .map_err(|_| AuthError::InternalError)?
+    .map_err(|e| match e {
+        PasswordError::InvalidCredentials => AuthError::InvalidCredentials,
+        PasswordError::Hashing(_) => AuthError::InternalError,
+    })?;
+
+    let user = user.ok_or(AuthError::InvalidCredentials)?;
+    start_session(&state, jar, &user).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/logout",
+    tag = "auth",
+    responses((status = 303, description = "Signed out, the session cookie is removed"), AuthError)
+)]
+#[tracing::instrument(name = "Logging out", skip(state, jar))]
+pub async fn logout(
+    State(state): State<Arc<AppState>>,
+    jar: CookieJar,
+    caller: Option<Caller>,
+) -> Result<impl IntoResponse, AuthError> {
+    if let Some(caller) = caller {
+        delete_session(&state.connection_pool, caller.session_id).await?;
+    }
+
+    let jar = jar.remove(Cookie::build(SESSION_COOKIE).path("/"));
+    Ok((jar, Redirect::to("/home")))
+}
+
+async fn start_session(
+    state: &AppState,
+    jar: CookieJar,
+    user: &User,
+) -> Result<(CookieJar, Redirect), AuthError> {
+    let session_id = create_session(&state.connection_pool, user.id).await?;
+
+    let cookie = Cookie::build((SESSION_COOKIE, session_id.to_string()))
+        .path("/")
+        .http_only(true)
+        .secure(state.secure_cookies)
+        .same_site(SameSite::Lax)
+        .max_age(Duration::days(SESSION_LIFETIME_DAYS));
+
+    Ok((jar.add(cookie), Redirect::to("/home")))
+}
@@ -0,0 +1,45 @@
+use std::sync::Arc;
+
+use axum::{async_trait, extract::FromRequestParts, http::request::Parts};
+use axum_extra::extract::CookieJar;
+use uuid::Uuid;
+
+use crate::{
+    domain::sessions::{get_session_user, SESSION_COOKIE},
+    startup::AppState,
+};
+
+use super::errors::CreatePostError;
+
+/// Signed-in user performing the request, resolved from the session cookie.
+#[derive(Debug)]
+pub struct Caller {
+    pub user_id: Uuid,
+    pub username: String,
+    pub session_id: Uuid,
+}
+
+#[async_trait]
+impl FromRequestParts<Arc<AppState>> for Caller {
+    type Rejection = CreatePostError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        let session_id = CookieJar::from_headers(&parts.headers)
+            .get(SESSION_COOKIE)
+            .and_then(|cookie| Uuid::parse_str(cookie.value()).ok())
+            .ok_or(CreatePostError::Unauthorized)?;
+
+        let user = get_session_user(&state.connection_pool, session_id)
+            .await?
+            .ok_or(CreatePostError::Unauthorized)?;
+
+        Ok(Self {
+            user_id: user.id,
+            username: user.username,
+            session_id,
+        })
+    }
+}
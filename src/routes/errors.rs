@@ -1,18 +1,33 @@
 use axum::{
+    extract::{
+        multipart::MultipartRejection,
+        rejection::{FormRejection, PathRejection, QueryRejection},
+    },
     response::{IntoResponse, Response},
     Json,
 };
-use hyper::StatusCode;
+use std::collections::BTreeMap;
+
+use hyper::{header, StatusCode};
 use serde::Serialize;
-use tracing::error;
+use utoipa::{
+    openapi::{Content, Ref, RefOr, Response as ApiResponse, ResponseBuilder},
+    IntoResponses, ToSchema,
+};
+use validator::ValidationErrors;
+
+use crate::blocking::BlockingError;
 
 #[derive(Debug, thiserror::Error)]
 pub enum CreatePostError {
-    #[error("Invalid file type. Supported types is only PNG")]
-    InvalidFileType,
+    #[error("Invalid file type. Supported types are {0}")]
+    InvalidFileType(String),
+
+    #[error("File too large. Maximum size is {}", display_size(*.0))]
+    FileTooLarge(usize),
 
-    #[error("File too large. Maximum size is 5MB")]
-    FileTooLarge,
+    #[error("Request too large. Maximum size is {}", display_size(*.0))]
+    RequestTooLarge(usize),
 
     #[error("Database error: {0}")]
     DatabaseError(#[from] sqlx::Error),
@@ -20,47 +35,579 @@ pub enum CreatePostError {
     #[error("IO error: {0}")]
     IoError(#[from] std::io::Error),
 
+    #[error("Storage error: {0}")]
+    StorageError(#[from] crate::storage::StorageError),
+
     #[error("Failed to download avatar: {0}")]
     AvatarDownloadError(String),
 
-    #[error("Invalid input: {0}")]
-    ValidationError(String),
+    #[error("Avatar URL is not allowed: {0}")]
+    AvatarUrlBlocked(String),
+
+    #[error("Invalid input: {}", display_violations(.0))]
+    ValidationError(Vec<FieldViolation>),
+
+    #[error("Malformed request: {0}")]
+    MalformedRequest(String),
 
     #[error("Image processing error: {0}")]
     ImageError(#[from] image::ImageError),
 
+    #[error(transparent)]
+    BlockingError(#[from] BlockingError),
+
+    #[error("Post not found")]
+    NotFound,
+
+    #[error("You must be signed in to do this")]
+    Unauthorized,
+
+    #[error("Only the author of the post can modify it")]
+    Forbidden,
+
     #[error("Internal server error")]
     InternalError,
 }
 
-#[derive(Debug, Serialize)]
-struct ErrorResponse {
-    status_code: u16,
+#[derive(Debug, thiserror::Error)]
+pub enum GetPostError {
+    #[error("Post not found")]
+    NotFound,
+
+    #[error("Tag not found")]
+    TagNotFound,
+
+    #[error("User not found")]
+    UserNotFound,
+
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] sqlx::Error),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SearchError {
+    #[error("Invalid search: {0}")]
+    InvalidQuery(String),
+
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] sqlx::Error),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum AuthError {
+    #[error("Invalid username or password")]
+    InvalidCredentials,
+
+    #[error("Username is already taken")]
+    UsernameTaken,
+
+    #[error("Invalid input: {}", display_violations(.0))]
+    ValidationError(Vec<FieldViolation>),
+
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] sqlx::Error),
+
+    #[error("Internal server error")]
+    InternalError,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum FileError {
+    #[error("File not found")]
+    NotFound,
+
+    #[error("Failed to read file: {0}")]
+    ReadFailed(String),
+}
+
+/// A request that an extractor could not read, such as a path segment that is
+/// not a valid id or a form without a multipart boundary.
+#[derive(Debug, thiserror::Error)]
+#[error("Malformed request: {message}")]
+pub struct RejectionError {
+    status: StatusCode,
     message: String,
 }
 
+/// A request no route answers.
+#[derive(Debug, thiserror::Error)]
+pub enum RouteError {
+    #[error("No resource at this path")]
+    NotFound,
+
+    #[error("Method not allowed for this path")]
+    MethodNotAllowed,
+}
+
+/// Fallback of the router for paths without a route.
+pub async fn route_not_found() -> RouteError {
+    RouteError::NotFound
+}
+
+/// Fallback of the router for methods a path is not served with.
+pub async fn method_not_allowed() -> RouteError {
+    RouteError::MethodNotAllowed
+}
+
+/// A rule broken by a submitted field.
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct FieldViolation {
+    /// Name of the field in the form or JSON body.
+    pub field: String,
+    /// Name of the broken rule, e.g. `length` or `required`.
+    pub code: String,
+    /// What is wrong, meant to be shown to people.
+    pub message: String,
+}
+
+impl FieldViolation {
+    pub fn new(field: &str, code: &str, message: impl Into<String>) -> Self {
+        Self {
+            field: field.to_string(),
+            code: code.to_string(),
+            message: message.into(),
+        }
+    }
+}
+
+/// Lists every rule broken by a struct checked with [`validator::Validate`],
+/// ordered by field.
+pub fn field_violations(errors: &ValidationErrors) -> Vec<FieldViolation> {
+    let mut violations: Vec<FieldViolation> = errors
+        .field_errors()
+        .into_iter()
+        .flat_map(|(field, errors)| {
+            errors.iter().map(move |error| FieldViolation {
+                field: field.to_string(),
+                code: error.code.to_string(),
+                message: error
+                    .message
+                    .as_ref()
+                    .map_or_else(|| error.to_string(), ToString::to_string),
+            })
+        })
+        .collect();
+    violations.sort_by(|a, b| a.field.cmp(&b.field));
+    violations
+}
+
+fn display_violations(violations: &[FieldViolation]) -> String {
+    violations
+        .iter()
+        .map(|violation| violation.message.as_str())
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// Formats a byte count the way limits are written in the configuration.
+fn display_size(bytes: usize) -> String {
+    const KB: usize = 1024;
+    const MB: usize = 1024 * KB;
+
+    match bytes {
+        b if b >= MB && b % MB == 0 => format!("{}MB", b / MB),
+        b if b >= KB && b % KB == 0 => format!("{}KB", b / KB),
+        b => format!("{} bytes", b),
+    }
+}
+
+/// Media type of error answers.
+pub const PROBLEM_JSON: &str = "application/problem+json";
+
+/// Body of every error answer, problem details as in RFC 9457.
+#[derive(Debug, Serialize, ToSchema)]
+pub(super) struct ProblemDetails {
+    /// Always `about:blank`, problems are told apart by their `code`.
+    #[serde(rename = "type")]
+    problem_type: &'static str,
+    /// Reason phrase of the status code.
+    title: &'static str,
+    status: u16,
+    /// What went wrong in this case, meant to be shown to people.
+    detail: String,
+    /// Stable machine-readable name of the problem, e.g. `validation_failed`.
+    code: &'static str,
+    /// The rejected fields of a `validation_failed` problem.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    errors: Vec<FieldViolation>,
+}
+
+/// An error answered with [`ProblemDetails`], its `Display` output being the
+/// `detail`.
+pub trait Problem: std::fmt::Display {
+    fn status_code(&self) -> StatusCode;
+
+    /// Stable name of the problem for clients to tell errors apart.
+    fn code(&self) -> &'static str;
+
+    /// The rejected fields of a `validation_failed` problem.
+    fn violations(&self) -> &[FieldViolation] {
+        &[]
+    }
+}
+
+impl ProblemDetails {
+    /// Answers with the problem. The `detail` of server errors may name
+    /// internal causes such as database or storage errors, so it is only
+    /// logged and the client gets a generic one.
+    fn into_response(problem: &impl Problem) -> Response {
+        let (status, code, detail) = (problem.status_code(), problem.code(), problem.to_string());
+        let detail = if status.is_server_error() {
+            tracing::error!("{} ({}): {}", status, code, detail);
+            match status {
+                StatusCode::SERVICE_UNAVAILABLE => "The server is busy, try again later",
+                _ => "Internal server error",
+            }
+            .to_string()
+        } else {
+            tracing::debug!("{} ({}): {}", status, code, detail);
+            detail
+        };
+        let problem = ProblemDetails {
+            problem_type: "about:blank",
+            title: status.canonical_reason().unwrap_or("Error"),
+            status: status.as_u16(),
+            detail,
+            code,
+            errors: problem.violations().to_vec(),
+        };
+        (
+            status,
+            [(header::CONTENT_TYPE, PROBLEM_JSON)],
+            Json(problem),
+        )
+            .into_response()
+    }
+}
+
+/// Documents the error answers of a handler, each with a [`ProblemDetails`] body.
+fn error_responses(responses: &[(StatusCode, &str)]) -> BTreeMap<String, RefOr<ApiResponse>> {
+    responses
+        .iter()
+        .map(|(status, description)| {
+            let schema = Ref::from_schema_name(ProblemDetails::name());
+            let response = ResponseBuilder::new()
+                .description(*description)
+                .content(PROBLEM_JSON, Content::new(Some(schema)))
+                .build();
+            (status.as_str().to_string(), response.into())
+        })
+        .collect()
+}
+
 impl CreatePostError {
+    /// A single field breaking a rule.
+    pub fn invalid_field(field: &str, code: &str, message: impl Into<String>) -> Self {
+        Self::ValidationError(vec![FieldViolation::new(field, code, message)])
+    }
+
+    /// Whether the same work may succeed when retried later.
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            Self::DatabaseError(_)
+                | Self::IoError(_)
+                | Self::StorageError(_)
+                | Self::AvatarDownloadError(_)
+                | Self::BlockingError(BlockingError::Saturated)
+        )
+    }
+}
+
+impl Problem for CreatePostError {
     fn status_code(&self) -> StatusCode {
         match self {
-            Self::InvalidFileType
-            | Self::FileTooLarge
+            Self::InvalidFileType(_)
             | Self::ValidationError(_)
-            | Self::AvatarDownloadError(_) => StatusCode::BAD_REQUEST,
+            | Self::MalformedRequest(_)
+            | Self::AvatarDownloadError(_)
+            | Self::AvatarUrlBlocked(_) => StatusCode::BAD_REQUEST,
+            Self::FileTooLarge(_) | Self::RequestTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::NotFound => StatusCode::NOT_FOUND,
+            Self::Unauthorized => StatusCode::UNAUTHORIZED,
+            Self::Forbidden => StatusCode::FORBIDDEN,
+            Self::BlockingError(BlockingError::Saturated) => StatusCode::SERVICE_UNAVAILABLE,
             Self::DatabaseError(_)
+            | Self::BlockingError(BlockingError::Failed(_))
             | Self::IoError(_)
+            | Self::StorageError(_)
             | Self::ImageError(_)
             | Self::InternalError => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
+
+    fn code(&self) -> &'static str {
+        match self {
+            Self::InvalidFileType(_) => "invalid_file_type",
+            Self::FileTooLarge(_) => "file_too_large",
+            Self::RequestTooLarge(_) => "request_too_large",
+            Self::DatabaseError(_) => "database_error",
+            Self::IoError(_) => "io_error",
+            Self::StorageError(_) => "storage_error",
+            Self::AvatarDownloadError(_) => "avatar_download_failed",
+            Self::AvatarUrlBlocked(_) => "avatar_url_blocked",
+            Self::ValidationError(_) => "validation_failed",
+            Self::MalformedRequest(_) => "malformed_request",
+            Self::ImageError(_) => "image_processing_failed",
+            Self::BlockingError(BlockingError::Saturated) => "server_busy",
+            Self::BlockingError(BlockingError::Failed(_)) => "processing_failed",
+            Self::NotFound => "post_not_found",
+            Self::Unauthorized => "unauthorized",
+            Self::Forbidden => "forbidden",
+            Self::InternalError => "internal_error",
+        }
+    }
+
+    fn violations(&self) -> &[FieldViolation] {
+        match self {
+            Self::ValidationError(violations) => violations,
+            _ => &[],
+        }
+    }
+}
+
+impl IntoResponses for CreatePostError {
+    fn responses() -> BTreeMap<String, RefOr<ApiResponse>> {
+        error_responses(&[
+            (
+                StatusCode::BAD_REQUEST,
+                "Invalid text, tags or image, a malformed request or an avatar that cannot be downloaded",
+            ),
+            (StatusCode::UNAUTHORIZED, "Not signed in"),
+            (StatusCode::FORBIDDEN, "The post belongs to another user"),
+            (StatusCode::NOT_FOUND, "Post not found"),
+            (StatusCode::PAYLOAD_TOO_LARGE, "Image or request too large"),
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "Too many images are being processed, try again later",
+            ),
+            (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error"),
+        ])
+    }
 }
 
 impl IntoResponse for CreatePostError {
     fn into_response(self) -> Response {
-        let error_response = ErrorResponse {
-            status_code: self.status_code().as_u16(),
-            message: self.to_string(),
-        };
-        tracing::error!("{:?}", error_response);
-        (self.status_code(), Json(error_response)).into_response()
+        ProblemDetails::into_response(&self)
+    }
+}
+
+impl Problem for GetPostError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::NotFound | Self::TagNotFound | Self::UserNotFound => StatusCode::NOT_FOUND,
+            Self::DatabaseError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn code(&self) -> &'static str {
+        match self {
+            Self::NotFound => "post_not_found",
+            Self::TagNotFound => "tag_not_found",
+            Self::UserNotFound => "user_not_found",
+            Self::DatabaseError(_) => "database_error",
+        }
+    }
+}
+
+impl IntoResponses for GetPostError {
+    fn responses() -> BTreeMap<String, RefOr<ApiResponse>> {
+        error_responses(&[
+            (StatusCode::NOT_FOUND, "Post, tag or user not found"),
+            (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error"),
+        ])
+    }
+}
+
+impl IntoResponse for GetPostError {
+    fn into_response(self) -> Response {
+        ProblemDetails::into_response(&self)
+    }
+}
+
+impl Problem for SearchError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::InvalidQuery(_) => StatusCode::BAD_REQUEST,
+            Self::DatabaseError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn code(&self) -> &'static str {
+        match self {
+            Self::InvalidQuery(_) => "invalid_search",
+            Self::DatabaseError(_) => "database_error",
+        }
+    }
+}
+
+impl IntoResponses for SearchError {
+    fn responses() -> BTreeMap<String, RefOr<ApiResponse>> {
+        error_responses(&[
+            (
+                StatusCode::BAD_REQUEST,
+                "Invalid search query or date range",
+            ),
+            (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error"),
+        ])
+    }
+}
+
+impl IntoResponse for SearchError {
+    fn into_response(self) -> Response {
+        ProblemDetails::into_response(&self)
+    }
+}
+
+impl Problem for AuthError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::InvalidCredentials => StatusCode::UNAUTHORIZED,
+            Self::UsernameTaken => StatusCode::CONFLICT,
+            Self::ValidationError(_) => StatusCode::BAD_REQUEST,
+            Self::DatabaseError(_) | Self::InternalError => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn code(&self) -> &'static str {
+        match self {
+            Self::InvalidCredentials => "invalid_credentials",
+            Self::UsernameTaken => "username_taken",
+            Self::ValidationError(_) => "validation_failed",
+            Self::DatabaseError(_) => "database_error",
+            Self::InternalError => "internal_error",
+        }
+    }
+
+    fn violations(&self) -> &[FieldViolation] {
+        match self {
+            Self::ValidationError(violations) => violations,
+            _ => &[],
+        }
+    }
+}
+
+impl IntoResponses for AuthError {
+    fn responses() -> BTreeMap<String, RefOr<ApiResponse>> {
+        error_responses(&[
+            (StatusCode::BAD_REQUEST, "Invalid username or password"),
+            (StatusCode::UNAUTHORIZED, "Wrong username or password"),
+            (StatusCode::CONFLICT, "Username is already taken"),
+            (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error"),
+        ])
+    }
+}
+
+impl IntoResponse for AuthError {
+    fn into_response(self) -> Response {
+        ProblemDetails::into_response(&self)
+    }
+}
+
+impl Problem for RejectionError {
+    fn status_code(&self) -> StatusCode {
+        self.status
+    }
+
+    fn code(&self) -> &'static str {
+        match self.status {
+            StatusCode::PAYLOAD_TOO_LARGE => "request_too_large",
+            _ => "malformed_request",
+        }
+    }
+}
+
+impl From<PathRejection> for RejectionError {
+    fn from(rejection: PathRejection) -> Self {
+        Self {
+            status: rejection.status(),
+            message: rejection.body_text(),
+        }
+    }
+}
+
+impl From<QueryRejection> for RejectionError {
+    fn from(rejection: QueryRejection) -> Self {
+        Self {
+            status: rejection.status(),
+            message: rejection.body_text(),
+        }
+    }
+}
+
+impl From<FormRejection> for RejectionError {
+    fn from(rejection: FormRejection) -> Self {
+        Self {
+            status: rejection.status(),
+            message: rejection.body_text(),
+        }
+    }
+}
+
+impl From<MultipartRejection> for RejectionError {
+    fn from(rejection: MultipartRejection) -> Self {
+        Self {
+            status: rejection.status(),
+            message: rejection.body_text(),
+        }
+    }
+}
+
+impl IntoResponse for RejectionError {
+    fn into_response(self) -> Response {
+        ProblemDetails::into_response(&self)
+    }
+}
+
+impl Problem for RouteError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::NotFound => StatusCode::NOT_FOUND,
+            Self::MethodNotAllowed => StatusCode::METHOD_NOT_ALLOWED,
+        }
+    }
+
+    fn code(&self) -> &'static str {
+        match self {
+            Self::NotFound => "route_not_found",
+            Self::MethodNotAllowed => "method_not_allowed",
+        }
+    }
+}
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> Response {
+        ProblemDetails::into_response(&self)
+    }
+}
+
+impl Problem for FileError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::NotFound => StatusCode::NOT_FOUND,
+            Self::ReadFailed(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn code(&self) -> &'static str {
+        match self {
+            Self::NotFound => "file_not_found",
+            Self::ReadFailed(_) => "file_read_failed",
+        }
+    }
+}
+
+impl IntoResponses for FileError {
+    fn responses() -> BTreeMap<String, RefOr<ApiResponse>> {
+        error_responses(&[
+            (StatusCode::NOT_FOUND, "File not found"),
+            (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error"),
+        ])
+    }
+}
+
+impl IntoResponse for FileError {
+    fn into_response(self) -> Response {
+        ProblemDetails::into_response(&self)
     }
 }
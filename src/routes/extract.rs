@@ -0,0 +1,22 @@
+//! Extractors of axum that answer requests they cannot read with problem
+//! details, like every other error.
+
+use axum::extract::{FromRequest, FromRequestParts};
+
+use super::errors::RejectionError;
+
+#[derive(FromRequestParts)]
+#[from_request(via(axum::extract::Path), rejection(RejectionError))]
+pub struct Path<T>(pub T);
+
+#[derive(FromRequestParts)]
+#[from_request(via(axum::extract::Query), rejection(RejectionError))]
+pub struct Query<T>(pub T);
+
+#[derive(FromRequest)]
+#[from_request(via(axum::extract::Form), rejection(RejectionError))]
+pub struct Form<T>(pub T);
+
+#[derive(FromRequest)]
+#[from_request(rejection(RejectionError))]
+pub struct Multipart(pub axum::extract::Multipart);
@@ -0,0 +1,311 @@
+use std::{collections::HashMap, sync::Arc};
+
+use askama_axum::{IntoResponse, Response};
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, State},
+};
+use chrono::{DateTime, SubsecRound, Utc};
+use hyper::{header, http::request::Parts, HeaderMap, StatusCode};
+use sha2::{Digest, Sha256};
+use utoipa::{
+    openapi::{
+        self,
+        path::{HttpMethod, OperationBuilder, ParameterBuilder, ParameterIn},
+        schema::Type,
+        Content, HeaderBuilder, Object, Required, Response as ApiResponse, ResponseBuilder,
+    },
+    IntoResponses, Modify,
+};
+
+use crate::{
+    domain::{
+        get_posts_page, tags::normalize_tag, users::get_user_by_username, BlogPost, PostFilter,
+    },
+    feeds::{
+        atom_feed as render_atom, json_feed as render_json, last_modified, rss_feed as render_rss,
+        FeedInfo,
+    },
+    pagination::PageQuery,
+    startup::AppState,
+};
+
+use super::{
+    errors::{GetPostError, RejectionError},
+    extract::Path,
+};
+
+/// A format feeds are served in.
+pub trait FeedKind: Send + 'static {
+    /// Name of the format in the API documentation.
+    const NAME: &'static str;
+    const FILE_NAME: &'static str;
+    const MEDIA_TYPE: &'static str;
+
+    fn render(info: &FeedInfo, posts: &[BlogPost]) -> String;
+}
+
+pub struct Rss;
+
+impl FeedKind for Rss {
+    const NAME: &'static str = "RSS";
+    const FILE_NAME: &'static str = "feed.rss";
+    const MEDIA_TYPE: &'static str = "application/rss+xml";
+
+    fn render(info: &FeedInfo, posts: &[BlogPost]) -> String {
+        render_rss(info, posts)
+    }
+}
+
+pub struct Atom;
+
+impl FeedKind for Atom {
+    const NAME: &'static str = "Atom";
+    const FILE_NAME: &'static str = "feed.atom";
+    const MEDIA_TYPE: &'static str = "application/atom+xml";
+
+    fn render(info: &FeedInfo, posts: &[BlogPost]) -> String {
+        render_atom(info, posts)
+    }
+}
+
+pub struct Json;
+
+impl FeedKind for Json {
+    const NAME: &'static str = "JSON";
+    const FILE_NAME: &'static str = "feed.json";
+    const MEDIA_TYPE: &'static str = "application/feed+json";
+
+    fn render(info: &FeedInfo, posts: &[BlogPost]) -> String {
+        render_json(info, posts)
+    }
+}
+
+/// Which posts a feed holds, read from the parameters of the route in
+/// [`FEED_ROUTES`] that matched.
+#[derive(Debug)]
+pub enum FeedScope {
+    All,
+    User(String),
+    Tag(String),
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for FeedScope {
+    type Rejection = RejectionError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Path(mut params) =
+            Path::<HashMap<String, String>>::from_request_parts(parts, state).await?;
+        let scope = if let Some(username) = params.remove("username") {
+            Self::User(username)
+        } else if let Some(tag) = params.remove("tag") {
+            Self::Tag(tag)
+        } else {
+            Self::All
+        };
+        Ok(scope)
+    }
+}
+
+/// Where the feeds of some posts are served, in every [`FeedKind`].
+pub struct FeedRoute {
+    /// Path the file names of the feeds are appended to, with parameters
+    /// written as `:name`.
+    prefix: &'static str,
+    /// Name and description of the path parameter.
+    parameter: Option<(&'static str, &'static str)>,
+    /// Prepended to the operation IDs in the API documentation.
+    operation_prefix: &'static str,
+    /// The posts held, as described in the API documentation.
+    posts: &'static str,
+}
+
+pub const FEED_ROUTES: [FeedRoute; 3] = [
+    FeedRoute {
+        prefix: "",
+        parameter: None,
+        operation_prefix: "",
+        posts: "the latest posts",
+    },
+    FeedRoute {
+        prefix: "/users/:username",
+        parameter: Some(("username", "Name of the user")),
+        operation_prefix: "user_",
+        posts: "the latest posts of a user",
+    },
+    FeedRoute {
+        prefix: "/tags/:tag",
+        parameter: Some(("tag", "Name of the tag")),
+        operation_prefix: "tag_",
+        posts: "the latest posts with a tag",
+    },
+];
+
+impl FeedRoute {
+    /// Path of the feed in format `K`, with parameters written as `:name`.
+    pub fn path<K: FeedKind>(&self) -> String {
+        format!("{}/{}", self.prefix, K::FILE_NAME)
+    }
+
+    fn document<K: FeedKind>(&self, openapi: &mut openapi::OpenApi) {
+        let string = || Object::with_type(Type::String);
+        let header = |description| {
+            HeaderBuilder::new()
+                .schema(string())
+                .description(Some(description))
+                .build()
+        };
+        let feed = ResponseBuilder::new()
+            .description("The feed")
+            .content(K::MEDIA_TYPE, Content::new(Some(string())))
+            .header("ETag", header("Hash of the feed"))
+            .header("Last-Modified", header("Last change to a post in the feed"));
+
+        let mut operation = OperationBuilder::new()
+            .tag("feeds")
+            .summary(Some(format!("{} feed of {}.", K::NAME, self.posts)))
+            .operation_id(Some(format!(
+                "{}{}_feed",
+                self.operation_prefix,
+                K::NAME.to_lowercase()
+            )))
+            .response("200", feed)
+            .response(
+                "304",
+                ApiResponse::new("The client's copy is still current"),
+            );
+        for (status, response) in GetPostError::responses() {
+            operation = operation.response(status, response);
+        }
+        let mut path = self.path::<K>();
+        if let Some((name, description)) = self.parameter {
+            operation = operation.parameter(
+                ParameterBuilder::new()
+                    .name(name)
+                    .parameter_in(ParameterIn::Path)
+                    .required(Required::True)
+                    .description(Some(description))
+                    .schema(Some(string())),
+            );
+            path = path.replace(&format!(":{}", name), &format!("{{{}}}", name));
+        }
+
+        openapi
+            .paths
+            .add_path_operation(path, vec![HttpMethod::Get], operation);
+    }
+}
+
+/// Documents the operations of [`FEED_ROUTES`], which share one handler
+/// and so cannot be listed with `#[utoipa::path]`.
+pub struct FeedDocs;
+
+impl Modify for FeedDocs {
+    fn modify(&self, openapi: &mut openapi::OpenApi) {
+        for route in &FEED_ROUTES {
+            route.document::<Rss>(openapi);
+            route.document::<Atom>(openapi);
+            route.document::<Json>(openapi);
+        }
+    }
+}
+
+/// Feed in format `K` of the posts of the matched route in [`FEED_ROUTES`].
+#[tracing::instrument(name = "Rendering feed", skip(state, headers))]
+pub async fn feed<K: FeedKind>(
+    State(state): State<Arc<AppState>>,
+    scope: FeedScope,
+    headers: HeaderMap,
+) -> Result<Response, GetPostError> {
+    let base_url = &state.base_url;
+    // Users have no page of their own, their posts are listed on the home page.
+    let (filter, title, feed_path, html_path) = match scope {
+        FeedScope::All => (
+            PostFilter::default(),
+            "Blog Posts".to_string(),
+            String::new(),
+            "/home".to_string(),
+        ),
+        FeedScope::User(username) => {
+            let user = get_user_by_username(&state.connection_pool, &username)
+                .await?
+                .ok_or(GetPostError::UserNotFound)?;
+            let title = format!("Posts by {}", user.username);
+            let feed_path = format!("/users/{}", user.username);
+            let filter = PostFilter {
+                user_id: Some(user.id),
+                ..PostFilter::default()
+            };
+            (filter, title, feed_path, "/home".to_string())
+        }
+        FeedScope::Tag(tag) => {
+            let tag = normalize_tag(&tag).ok_or(GetPostError::TagNotFound)?;
+            let title = format!("Posts tagged #{}", tag);
+            let path = format!("/tags/{}", tag);
+            let filter = PostFilter {
+                tag: Some(tag),
+                ..PostFilter::default()
+            };
+            (filter, title, path.clone(), path)
+        }
+    };
+
+    let page = get_posts_page(&state.connection_pool, &PageQuery::default(), &filter).await?;
+
+    let info = FeedInfo {
+        description: format!("The latest {}", title.to_lowercase()),
+        title,
+        feed_url: format!("{}{}/{}", base_url, feed_path, K::FILE_NAME),
+        html_url: format!("{}{}", base_url, html_path),
+        base_url: base_url.clone(),
+    };
+    let body = K::render(&info, &page.items);
+
+    Ok(conditional_response(
+        &headers,
+        &format!("{}; charset=utf-8", K::MEDIA_TYPE),
+        body,
+        last_modified(&page.items),
+    ))
+}
+
+/// Answers with `304 Not Modified` when the client's copy is still current.
+/// The `ETag` is a hash of the body, so it also changes when a post is
+/// deleted, which the `Last-Modified` date cannot show. As RFC 9110 requires,
+/// `If-Modified-Since` is only consulted without `If-None-Match`.
+fn conditional_response(
+    headers: &HeaderMap,
+    content_type: &str,
+    body: String,
+    last_modified: Option<DateTime<Utc>>,
+) -> Response {
+    let etag = format!("\"{:x}\"", Sha256::digest(body.as_bytes()));
+    let last_modified = last_modified.map(|modified| modified.trunc_subsecs(0));
+
+    let header = |name| headers.get(name).and_then(|value| value.to_str().ok());
+    let not_modified = match header(header::IF_NONE_MATCH) {
+        Some(if_none_match) => if_none_match
+            .split(',')
+            .map(str::trim)
+            .any(|candidate| candidate == "*" || candidate.trim_start_matches("W/") == etag),
+        None => header(header::IF_MODIFIED_SINCE)
+            .and_then(|since| DateTime::parse_from_rfc2822(since).ok())
+            .zip(last_modified)
+            .is_some_and(|(since, modified)| modified <= since),
+    };
+
+    let mut response = if not_modified {
+        StatusCode::NOT_MODIFIED.into_response()
+    } else {
+        ([(header::CONTENT_TYPE, content_type)], body).into_response()
+    };
+
+    let response_headers = response.headers_mut();
+    response_headers.insert(header::ETAG, etag.parse().unwrap());
+    if let Some(modified) = last_modified {
+        let http_date = modified.format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+        response_headers.insert(header::LAST_MODIFIED, http_date.parse().unwrap());
+    }
+    response
+}
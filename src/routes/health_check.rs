@@ -1,3 +1,10 @@
+/// Answers as long as the application is running.
+#[utoipa::path(
+    get,
+    path = "/health_check",
+    tag = "health",
+    responses((status = 200, description = "The application is running", body = String, content_type = "text/plain"))
+)]
 #[tracing::instrument]
 pub async fn handle_get() -> axum::response::Response {
     return axum::response::IntoResponse::into_response((
@@ -2,19 +2,42 @@ use std::sync::Arc;
 
 use askama_axum::{IntoResponse, Response};
 use axum::extract::State;
-use hyper::StatusCode;
 
-use crate::{domain::get_all_posts, startup::AppState, templates::HomeTemplate};
+use crate::{
+    domain::{get_posts_page, PostFilter},
+    pagination::PageQuery,
+    startup::{AppState, UPLOADS_ROUTE},
+    templates::HomeTemplate,
+};
 
+use super::{caller::Caller, errors::GetPostError, extract::Query};
+
+/// Home page listing the newest posts, with the form to write one when signed in.
+#[utoipa::path(
+    get,
+    path = "/home",
+    tag = "posts",
+    params(PageQuery),
+    responses(
+        (status = 200, description = "The home page", body = String, content_type = "text/html"),
+        GetPostError,
+    )
+)]
 #[tracing::instrument(skip(state))]
-pub async fn home(State(state): State<Arc<AppState>>) -> Result<Response, StatusCode> {
-    let posts = get_all_posts(&state.connection_pool)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+pub async fn home(
+    State(state): State<Arc<AppState>>,
+    caller: Option<Caller>,
+    Query(page_query): Query<PageQuery>,
+) -> Result<Response, GetPostError> {
+    let page = get_posts_page(&state.connection_pool, &page_query, &PostFilter::default()).await?;
 
     let template = HomeTemplate {
-        posts,
-        upload_path: state.upload_path.to_str().unwrap().to_string(),
+        current_user: caller.map(|caller| caller.username),
+        older_query: page.older_query(page_query.limit()),
+        newer_query: page.newer_query(page_query.limit()),
+        posts: page.items,
+        upload_path: UPLOADS_ROUTE.to_string(),
+        accepted_image_types: state.media.accept_attribute(),
     };
 
     Ok(template.into_response())
@@ -1,4 +1,14 @@
+pub mod api;
+pub mod auth;
+pub mod caller;
 pub mod errors;
+pub mod extract;
+pub mod feeds;
 pub mod health_check;
 pub mod home;
+pub mod openapi;
 pub mod posts;
+pub mod profile;
+pub mod search;
+pub mod tags;
+pub mod uploads;
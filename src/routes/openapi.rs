@@ -0,0 +1,136 @@
+use std::sync::Arc;
+
+use axum::{
+    response::{IntoResponse, Redirect, Response},
+    Json,
+};
+use hyper::header;
+use once_cell::sync::Lazy;
+use utoipa::{
+    openapi::{
+        self,
+        security::{ApiKey, ApiKeyValue, SecurityScheme},
+    },
+    Modify, OpenApi,
+};
+use utoipa_swagger_ui::Config;
+
+use crate::domain::sessions::SESSION_COOKIE;
+
+use super::{
+    api, auth,
+    errors::{FieldViolation, FileError, ProblemDetails},
+    extract::Path,
+    feeds::FeedDocs,
+    health_check, home, posts, profile, search, tags, uploads,
+};
+
+/// Route of the OpenAPI document.
+pub const OPENAPI_ROUTE: &str = "/api/openapi.json";
+
+/// Route of the documentation page rendered from the OpenAPI document.
+pub const DOCS_ROUTE: &str = "/api/docs";
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Blog",
+        description = "Posts with Markdown text, tags and images. Changes require the \
+                       session cookie set by `/register` or `/login`."
+    ),
+    paths(
+        health_check::handle_get,
+        home::home,
+        auth::register_form,
+        auth::register,
+        auth::login_form,
+        auth::login,
+        auth::logout,
+        profile::profile,
+        profile::update_avatar,
+        posts::list_posts,
+        posts::create_post,
+        posts::get_post,
+        posts::replace_post,
+        posts::patch_post,
+        posts::remove_post,
+        posts::submit_post_edit,
+        posts::submit_post_delete,
+        tags::posts_by_tag,
+        search::search,
+        api::api_list_posts,
+        api::api_create_post,
+        api::api_get_post,
+        api::api_replace_post,
+        api::api_patch_post,
+        api::api_delete_post,
+        uploads::serve_upload,
+    ),
+    components(schemas(ProblemDetails, FieldViolation)),
+    modifiers(&SessionCookie, &FeedDocs),
+    tags(
+        (name = "auth", description = "Registration and sessions"),
+        (name = "posts", description = "Posts as used by the web pages, written with forms"),
+        (name = "api", description = "Versioned JSON API for posts"),
+        (name = "profile", description = "Profile and avatar of the signed-in user"),
+        (name = "feeds", description = "RSS, Atom and JSON feeds of the latest posts"),
+        (name = "uploads", description = "Stored images and avatars"),
+        (name = "health", description = "Monitoring"),
+    )
+)]
+pub struct ApiDoc;
+
+/// Declares the session cookie the operations refer to as `session`.
+struct SessionCookie;
+
+impl Modify for SessionCookie {
+    fn modify(&self, openapi: &mut openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "session",
+            SecurityScheme::ApiKey(ApiKey::Cookie(ApiKeyValue::new(SESSION_COOKIE))),
+        );
+    }
+}
+
+static OPENAPI: Lazy<openapi::OpenApi> = Lazy::new(|| {
+    let mut document = ApiDoc::openapi();
+    // Taken from the package, which names no license.
+    document.info.license = None;
+    document
+});
+
+static DOCS_CONFIG: Lazy<Arc<Config<'static>>> =
+    Lazy::new(|| Arc::new(Config::from(OPENAPI_ROUTE)));
+
+#[tracing::instrument]
+pub async fn openapi_json() -> Json<&'static openapi::OpenApi> {
+    Json(&OPENAPI)
+}
+
+/// The docs page links its assets relative to the directory it is served from.
+pub async fn docs_redirect() -> Redirect {
+    Redirect::to(&format!("{}/", DOCS_ROUTE))
+}
+
+pub async fn docs_index() -> Result<Response, FileError> {
+    docs_file("")
+}
+
+/// Serves Swagger UI from the copy built into the binary, so the docs page
+/// loads nothing from other hosts.
+pub async fn docs_asset(Path(path): Path<String>) -> Result<Response, FileError> {
+    docs_file(&path)
+}
+
+fn docs_file(path: &str) -> Result<Response, FileError> {
+    let file = utoipa_swagger_ui::serve(path, Arc::clone(&DOCS_CONFIG))
+        .map_err(|e| FileError::ReadFailed(format!("{}: {}", path, e)))?
+        .ok_or(FileError::NotFound)?;
+
+    Ok((
+        [(header::CONTENT_TYPE, file.content_type)],
+        file.bytes.into_owned(),
+    )
+        .into_response())
+}
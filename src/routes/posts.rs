@@ -1,26 +1,53 @@
-use std::{
-    fs::File,
-    io::BufWriter,
-    path::{Path, PathBuf},
-    sync::Arc,
-};
+use std::{io::Cursor, path::Path, sync::Arc};
 
 use crate::{
-    domain::{save_post, ALLOWED_IMAGE_TYPE, MAX_TEXT_LENGTH, MIN_TEXT_LENGTH, USERNAME_RE},
-    startup::AppState,
+    configuration::MediaSettings,
+    domain::{
+        delete_post, get_pending_image_for_update, get_post_by_id, get_post_for_update,
+        get_posts_page,
+        images::{delete_image_variants, save_image_variants, ImageVariant},
+        media::{acquire_media, release_media, Media},
+        save_post, set_post_image,
+        tags::{collect_tags, hashtags, parse_tags, set_post_tags, MAX_TAGS},
+        update_post,
+        users::User,
+        BlogPost, MediaFormat, PostFilter, MAX_TEXT_LENGTH, MIN_TEXT_LENGTH,
+    },
+    fetcher::FetchError,
+    jobs::{enqueue, enqueue_file_removal, Job},
+    pagination::{Page, PageQuery},
+    startup::{AppState, UPLOADS_ROUTE},
+    storage::{Storage, INCOMING_PREFIX},
+    templates::PostTemplate,
 };
 use axum::{
-    extract::{Multipart, State},
-    response::IntoResponse,
+    extract::{
+        multipart::{Field, MultipartError},
+        State,
+    },
+    response::{IntoResponse, Response},
+    Json,
+};
+use hyper::{header, HeaderMap, StatusCode};
+use image::{
+    error::{LimitError, LimitErrorKind},
+    imageops::FilterType,
+    DynamicImage, ImageDecoder, ImageError, ImageReader,
 };
-use hyper::{header, StatusCode};
-use image::guess_format;
-use reqwest::Client;
+use sha2::{Digest, Sha256};
+use sqlx::{Postgres, Transaction};
+use tempfile::{NamedTempFile, TempPath};
+use tokio::io::AsyncWriteExt;
 use tracing::warn;
+use utoipa::ToSchema;
 use uuid::Uuid;
 use validator::Validate;
 
-use super::errors::CreatePostError;
+use super::{
+    caller::Caller,
+    errors::{field_violations, CreatePostError, GetPostError},
+    extract::{Multipart, Path as UrlPath, Query},
+};
 
 #[derive(Debug, Validate)]
 struct NewPostData {
@@ -30,128 +57,439 @@ struct NewPostData {
         message = "Text must be between 10 and 10,000 characters"
     ))]
     text: String,
-
-    #[validate(length(
-        min = 2,
-        max = 50,
-        message = "Username must be between 2 and 50 characters"
-    ))]
-    #[validate(regex(path = *USERNAME_RE, message = "Username contains invalid characters"))]
-    username: String,
-
-    #[validate(url)]
-    #[validate(custom(function = "Self::validate_image_url"))]
-    user_avatar_url: Option<String>,
-    image_data: Option<Vec<u8>>,
+    /// Explicit tags, limited on their own as the hashtags of the text are
+    /// already limited by its length.
+    #[validate(length(max = "MAX_TAGS", message = "At most 10 tags are allowed per post"))]
+    tags: Vec<String>,
+    /// Upload spooled to a temporary file, removed once the request is done.
+    image: Option<TempPath>,
 }
 
-impl NewPostData {
-    fn validate_image_url(url: &str) -> Result<(), validator::ValidationError> {
-        if !url.ends_with(".png") {
-            return Err(validator::ValidationError::new(
-                "Avatar URL must point to a PNG image",
-            ));
-        }
-        Ok(())
+/// Bytes from the start of an upload inspected to detect its format.
+const SNIFF_LENGTH: usize = 32;
+
+/// Checks the magic bytes of `data` against the configured allow-list.
+fn check_image_format(media: &MediaSettings, data: &[u8]) -> Result<(), CreatePostError> {
+    match MediaFormat::detect(data) {
+        Some(format) if media.allows(format) => Ok(()),
+        _ => Err(CreatePostError::InvalidFileType(
+            media
+                .allowed_formats
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", "),
+        )),
     }
 }
 
-struct CleanupGuard {
-    paths: Vec<PathBuf>,
+/// Removes stored files unless dismissed. Dropping the guard removes them in
+/// the background.
+pub(crate) struct CleanupGuard {
+    storage: Arc<dyn Storage>,
+    keys: Vec<String>,
 }
 
 impl CleanupGuard {
-    fn new() -> Self {
-        Self { paths: Vec::new() }
+    pub(crate) fn new(storage: &Arc<dyn Storage>) -> Self {
+        Self {
+            storage: Arc::clone(storage),
+            keys: Vec::new(),
+        }
+    }
+
+    pub(crate) fn add(&mut self, key: String) {
+        self.keys.push(key);
     }
 
-    fn add(&mut self, path: PathBuf) {
-        self.paths.push(path);
+    pub(crate) fn dismiss(mut self) {
+        self.keys.clear();
     }
 
-    fn dismiss(mut self) {
-        self.paths.clear();
+    /// Hands the files over to the caller instead of removing them.
+    pub(crate) fn take_keys(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.keys)
     }
 }
 
 impl Drop for CleanupGuard {
     fn drop(&mut self) {
-        for path in self.paths.iter() {
-            if let Err(e) = std::fs::remove_file(path) {
-                warn!("Failed to clean up file {}: {}", path.display(), e);
-            }
+        if self.keys.is_empty() {
+            return;
         }
+
+        let storage = Arc::clone(&self.storage);
+        let keys = std::mem::take(&mut self.keys);
+        tokio::spawn(async move { remove_files(storage.as_ref(), keys).await });
     }
 }
 
+async fn remove_files(storage: &dyn Storage, keys: Vec<String>) {
+    for key in keys {
+        if let Err(e) = storage.delete(&key).await {
+            warn!("Failed to clean up file {}: {}", key, e);
+        }
+    }
+}
+
+/// Multipart form of `POST /posts`, `PUT /posts/{id}` and `PATCH /posts/{id}`.
+/// Only describes the form for the API documentation, see [`PostForm`].
+#[derive(ToSchema)]
+#[allow(dead_code)]
+pub(super) struct PostUpload {
+    /// Markdown text of 10 to 10,000 characters, optional for `PATCH`.
+    text: String,
+    /// Tags separated by commas or whitespace, added to the `#hashtags` of the text.
+    tags: Option<String>,
+    /// PNG, JPEG, WebP or GIF image.
+    #[schema(value_type = Option<String>, format = Binary)]
+    image: Option<Vec<u8>>,
+}
+
+/// Creates a post. The image is processed in the background, the post shows a
+/// placeholder until then.
+#[utoipa::path(
+    post,
+    path = "/posts",
+    tag = "posts",
+    request_body(content = PostUpload, content_type = "multipart/form-data"),
+    responses(
+        (status = 303, description = "Post created, redirects to `/home`"),
+        CreatePostError,
+    ),
+    security(("session" = []))
+)]
 #[tracing::instrument(name = "Creating a new post", skip(state, multipart))]
 pub async fn create_post(
     State(state): State<Arc<AppState>>,
-    mut multipart: Multipart,
+    caller: Caller,
+    Multipart(mut multipart): Multipart,
 ) -> Result<impl IntoResponse, CreatePostError> {
-    let mut cleanup_guard = CleanupGuard::new();
-    let (text, username, user_avatar_url, image_data) =
-        process_multipart_fields(&mut multipart).await?;
+    let form = process_multipart_fields(&state, &mut multipart).await?;
+    insert_post(&state, caller, form).await?;
+
+    Ok((
+        StatusCode::SEE_OTHER,
+        [(header::LOCATION, "/home")],
+        "Redirecting to /home",
+    ))
+}
+
+/// Validates and saves a new post written by `caller`, queueing its image for
+/// processing. Returns the id of the post.
+pub(super) async fn insert_post(
+    state: &AppState,
+    caller: Caller,
+    form: PostForm,
+) -> Result<Uuid, CreatePostError> {
+    let mut cleanup_guard = CleanupGuard::new(&state.storage);
 
+    let text = form
+        .text
+        .ok_or_else(|| CreatePostError::invalid_field("text", "required", "Text is required"))?;
     let post_data = NewPostData {
-        text: text
-            .ok_or_else(|| CreatePostError::ValidationError("Text is required".to_string()))?,
-        username: username
-            .ok_or_else(|| CreatePostError::ValidationError("Username is required".to_string()))?,
-        user_avatar_url,
-        image_data,
+        text,
+        tags: form.tags.unwrap_or_default(),
+        image: form.image,
     };
 
     post_data
         .validate()
-        .map_err(|e| CreatePostError::ValidationError(e.to_string()))?;
+        .map_err(|e| CreatePostError::ValidationError(field_violations(&e)))?;
+    let tags = collect_tags(&post_data.text, &post_data.tags);
+
+    // Stored before the transaction begins, so no connection is held while
+    // the upload is written.
+    let upload_key = if let Some(image) = post_data.image {
+        Some(store_upload(state, &image, &mut cleanup_guard).await?)
+    } else {
+        None
+    };
 
     let mut tx = state
         .connection_pool
         .begin()
         .await
-        .map_err(|e| CreatePostError::DatabaseError(e))?;
+        .map_err(CreatePostError::DatabaseError)?;
+
+    let author = User {
+        id: caller.user_id,
+        username: caller.username,
+    };
 
-    let image_path = if let Some(image_data) = post_data.image_data {
-        let file_name = format!("{}.png", Uuid::new_v4());
-        let file_path = state.upload_path.join(&file_name);
+    let post_id = save_post(&mut tx, &post_data.text, &author, upload_key.as_deref())
+        .await
+        .map_err(CreatePostError::DatabaseError)?;
+    set_post_tags(&mut tx, post_id, &tags).await?;
 
-        save_image(&image_data, &file_path).await?;
-        cleanup_guard.add(file_path);
+    if let Some(upload_key) = upload_key {
+        enqueue(
+            &mut tx,
+            &Job::ProcessPostImage {
+                post_id,
+                upload_key,
+            },
+        )
+        .await?;
+    }
 
-        Some(file_name)
-    } else {
-        None
+    tx.commit().await.map_err(CreatePostError::DatabaseError)?;
+
+    cleanup_guard.dismiss();
+
+    Ok(post_id)
+}
+
+/// How fields missing from an update form are treated.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) enum UpdateMode {
+    /// `PUT`: the form describes the whole post, missing media is removed.
+    Replace,
+    /// `PATCH`: only the fields present in the form are changed.
+    Merge,
+}
+
+/// Replaces a post. Missing tags leave only the `#hashtags` of the text and a
+/// missing image removes the image.
+#[utoipa::path(
+    put,
+    path = "/posts/{id}",
+    tag = "posts",
+    params(("id" = Uuid, Path, description = "Id of the post")),
+    request_body(content = PostUpload, content_type = "multipart/form-data"),
+    responses(
+        (status = 303, description = "Post replaced, redirects to the post"),
+        CreatePostError,
+    ),
+    security(("session" = []))
+)]
+#[tracing::instrument(name = "Replacing a post", skip(state, multipart))]
+pub async fn replace_post(
+    State(state): State<Arc<AppState>>,
+    UrlPath(id): UrlPath<Uuid>,
+    caller: Caller,
+    Multipart(mut multipart): Multipart,
+) -> Result<impl IntoResponse, CreatePostError> {
+    let form = process_multipart_fields(&state, &mut multipart).await?;
+    apply_post_update(&state, id, &caller, form, UpdateMode::Replace).await?;
+    Ok(redirect_to_post(id))
+}
+
+/// Changes the fields of a post present in the form.
+#[utoipa::path(
+    patch,
+    path = "/posts/{id}",
+    tag = "posts",
+    params(("id" = Uuid, Path, description = "Id of the post")),
+    request_body(content = PostUpload, content_type = "multipart/form-data"),
+    responses(
+        (status = 303, description = "Post changed, redirects to the post"),
+        CreatePostError,
+    ),
+    security(("session" = []))
+)]
+#[tracing::instrument(name = "Patching a post", skip(state, multipart))]
+pub async fn patch_post(
+    State(state): State<Arc<AppState>>,
+    UrlPath(id): UrlPath<Uuid>,
+    caller: Caller,
+    Multipart(mut multipart): Multipart,
+) -> Result<impl IntoResponse, CreatePostError> {
+    let form = process_multipart_fields(&state, &mut multipart).await?;
+    apply_post_update(&state, id, &caller, form, UpdateMode::Merge).await?;
+    Ok(redirect_to_post(id))
+}
+
+fn redirect_to_post(id: Uuid) -> impl IntoResponse {
+    let location = format!("/posts/{}", id);
+    (
+        StatusCode::SEE_OTHER,
+        [(header::LOCATION, location.clone())],
+        format!("Redirecting to {}", location),
+    )
+}
+
+/// Changes a post of `caller`, validating the result like a new post.
+pub(super) async fn apply_post_update(
+    state: &AppState,
+    id: Uuid,
+    caller: &Caller,
+    form: PostForm,
+    mode: UpdateMode,
+) -> Result<(), CreatePostError> {
+    let mut cleanup_guard = CleanupGuard::new(&state.storage);
+
+    // Stored before the post is locked, so other writers to the post, its
+    // image job included, are not held up while the upload is written. The
+    // guard removes it again should the update be refused.
+    let new_upload = match &form.image {
+        Some(image) => Some(store_upload(state, image, &mut cleanup_guard).await?),
+        None => None,
     };
 
-    let avatar_path = if let Some(url) = post_data.user_avatar_url {
-        let file_name = format!("avatar_{}.png", Uuid::new_v4());
-        let file_path = state.upload_path.join(&file_name);
+    let mut tx = state
+        .connection_pool
+        .begin()
+        .await
+        .map_err(CreatePostError::DatabaseError)?;
 
-        download_and_save_avatar(&state.http_client, &url, &file_path).await?;
-        cleanup_guard.add(file_path.clone());
+    let post = get_post_for_update(&mut tx, id)
+        .await?
+        .ok_or(CreatePostError::NotFound)?;
 
-        Some(file_name)
-    } else {
-        None
+    if post.user_id != Some(caller.user_id) {
+        return Err(CreatePostError::Forbidden);
+    }
+
+    let text = match (form.text, mode) {
+        (Some(text), _) => text,
+        (None, UpdateMode::Merge) => post.text.clone(),
+        (None, UpdateMode::Replace) => {
+            return Err(CreatePostError::invalid_field(
+                "text",
+                "required",
+                "Text is required",
+            ))
+        }
     };
 
-    save_post(
+    // Without a tags field, PUT keeps only the hashtags while PATCH also
+    // keeps the tags that were given explicitly before.
+    let explicit_tags = match (form.tags, mode) {
+        (Some(tags), _) => tags,
+        (None, UpdateMode::Replace) => Vec::new(),
+        (None, UpdateMode::Merge) => {
+            let previous_hashtags = hashtags(&post.text);
+            post.tags
+                .iter()
+                .filter(|tag| !previous_hashtags.contains(*tag))
+                .cloned()
+                .collect()
+        }
+    };
+
+    let post_data = NewPostData {
+        text,
+        tags: explicit_tags,
+        image: form.image,
+    };
+
+    post_data
+        .validate()
+        .map_err(|e| CreatePostError::ValidationError(field_violations(&e)))?;
+    let tags = collect_tags(&post_data.text, &post_data.tags);
+
+    let mut replaced_image = None;
+
+    let (image_id, upload_key) = match (&new_upload, mode) {
+        // The current image stays until the job swaps in the new one.
+        (Some(upload_key), _) => (post.image_id, Some(upload_key.clone())),
+        (None, UpdateMode::Replace) => {
+            replaced_image = post.image_id;
+            (None, None)
+        }
+        (None, UpdateMode::Merge) => (post.image_id, post.pending_image_key),
+    };
+
+    // A superseded upload is left to its job, which notices and discards it.
+    update_post(
         &mut tx,
+        id,
         &post_data.text,
-        &post_data.username,
-        image_path.as_deref(),
-        avatar_path.as_deref(),
+        image_id,
+        upload_key.as_deref(),
     )
     .await
-    .map_err(|e| CreatePostError::DatabaseError(e))?;
+    .map_err(CreatePostError::DatabaseError)?;
+    set_post_tags(&mut tx, id, &tags).await?;
 
-    tx.commit()
-        .await
-        .map_err(|e| CreatePostError::DatabaseError(e))?;
+    if let Some(upload_key) = new_upload {
+        enqueue(
+            &mut tx,
+            &Job::ProcessPostImage {
+                post_id: id,
+                upload_key,
+            },
+        )
+        .await?;
+    }
+
+    let replaced_files = release_post_image(&mut tx, replaced_image).await?;
+    enqueue_file_removal(&mut tx, replaced_files).await?;
+
+    tx.commit().await.map_err(CreatePostError::DatabaseError)?;
 
     cleanup_guard.dismiss();
 
+    Ok(())
+}
+
+/// Deletes a post together with the files only it used.
+#[utoipa::path(
+    delete,
+    path = "/posts/{id}",
+    tag = "posts",
+    params(("id" = Uuid, Path, description = "Id of the post")),
+    responses((status = 204, description = "Post deleted"), CreatePostError),
+    security(("session" = []))
+)]
+#[tracing::instrument(name = "Deleting a post", skip(state))]
+pub async fn remove_post(
+    State(state): State<Arc<AppState>>,
+    UrlPath(id): UrlPath<Uuid>,
+    caller: Caller,
+) -> Result<impl IntoResponse, CreatePostError> {
+    delete_post_of(&state, id, &caller).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Changes the fields of a post present in the edit form of its page. Forms
+/// cannot send `PATCH`, so the page posts here instead.
+#[utoipa::path(
+    post,
+    path = "/posts/{id}/edit",
+    tag = "posts",
+    params(("id" = Uuid, Path, description = "Id of the post")),
+    request_body(content = PostUpload, content_type = "multipart/form-data"),
+    responses(
+        (status = 303, description = "Post changed, redirects to the post"),
+        CreatePostError,
+    ),
+    security(("session" = []))
+)]
+#[tracing::instrument(name = "Editing a post", skip(state, multipart))]
+pub async fn submit_post_edit(
+    State(state): State<Arc<AppState>>,
+    UrlPath(id): UrlPath<Uuid>,
+    caller: Caller,
+    Multipart(mut multipart): Multipart,
+) -> Result<impl IntoResponse, CreatePostError> {
+    let form = process_multipart_fields(&state, &mut multipart).await?;
+    apply_post_update(&state, id, &caller, form, UpdateMode::Merge).await?;
+    Ok(redirect_to_post(id))
+}
+
+/// Deletes a post from the delete button of its page. Forms cannot send
+/// `DELETE`, so the page posts here instead.
+#[utoipa::path(
+    post,
+    path = "/posts/{id}/delete",
+    tag = "posts",
+    params(("id" = Uuid, Path, description = "Id of the post")),
+    responses(
+        (status = 303, description = "Post deleted, redirects to `/home`"),
+        CreatePostError,
+    ),
+    security(("session" = []))
+)]
+#[tracing::instrument(name = "Deleting a post from its page", skip(state))]
+pub async fn submit_post_delete(
+    State(state): State<Arc<AppState>>,
+    UrlPath(id): UrlPath<Uuid>,
+    caller: Caller,
+) -> Result<impl IntoResponse, CreatePostError> {
+    delete_post_of(&state, id, &caller).await?;
+
     Ok((
         StatusCode::SEE_OTHER,
         [(header::LOCATION, "/home")],
@@ -159,117 +497,690 @@ pub async fn create_post(
     ))
 }
 
-#[tracing::instrument(name = "Saving image to disk", skip(data))]
-async fn save_image(data: &[u8], path: &Path) -> Result<(), CreatePostError> {
-    let img = image::load_from_memory(data)?;
+/// Deletes a post of `caller` together with the files only it used.
+pub(super) async fn delete_post_of(
+    state: &AppState,
+    id: Uuid,
+    caller: &Caller,
+) -> Result<(), CreatePostError> {
+    let mut tx = state
+        .connection_pool
+        .begin()
+        .await
+        .map_err(CreatePostError::DatabaseError)?;
+
+    let post = get_post_for_update(&mut tx, id)
+        .await?
+        .ok_or(CreatePostError::NotFound)?;
+
+    if post.user_id != Some(caller.user_id) {
+        return Err(CreatePostError::Forbidden);
+    }
+
+    delete_post(&mut tx, id)
+        .await
+        .map_err(CreatePostError::DatabaseError)?;
+    let stale_files = release_post_image(&mut tx, post.image_id).await?;
+    enqueue_file_removal(&mut tx, stale_files).await?;
 
-    let mut buffer = BufWriter::new(File::create(path).map_err(|e| CreatePostError::IoError(e))?);
-    img.write_to(&mut buffer, image::ImageFormat::Png)?;
+    tx.commit().await.map_err(CreatePostError::DatabaseError)?;
 
     Ok(())
 }
 
-#[tracing::instrument(name = "Downloading and saving avatar")]
-async fn download_and_save_avatar(
-    client: &Client,
-    url: &str,
+/// Stores an upload as is, to be turned into a post image by a background job.
+async fn store_upload(
+    state: &AppState,
     path: &Path,
-) -> Result<(), CreatePostError> {
-    let response = client
-        .get(url)
-        .send()
+    cleanup_guard: &mut CleanupGuard,
+) -> Result<String, CreatePostError> {
+    let key = format!("{}{}", INCOMING_PREFIX, Uuid::new_v4());
+    cleanup_guard.add(key.clone());
+    state
+        .storage
+        .put_file(&key, path, "application/octet-stream")
+        .await?;
+    Ok(key)
+}
+
+/// Turns the pending upload of a post into its image, releasing the image it
+/// replaces. Re-uploading the same image thus keeps its media referenced and
+/// stored throughout. Uploads that are no longer pending, because the post was
+/// deleted or got another image in the meantime, are discarded. The image is
+/// processed before the post is locked; returns the transaction holding the
+/// change and the files to remove once it commits.
+pub(crate) async fn process_post_image(
+    state: &AppState,
+    post_id: Uuid,
+    upload_key: &str,
+    cleanup_guard: &mut CleanupGuard,
+) -> Result<(Transaction<'static, Postgres>, Vec<String>), CreatePostError> {
+    let discarded = || vec![upload_key.to_string()];
+
+    let post = get_post_by_id(&state.connection_pool, post_id).await?;
+    if post.and_then(|post| post.pending_image_key).as_deref() != Some(upload_key) {
+        return Ok((state.connection_pool.begin().await?, discarded()));
+    }
+
+    let upload = state
+        .storage
+        .get(upload_key)
+        .await?
+        .ok_or(CreatePostError::InternalError)?;
+    let image = render_post_image(state, upload.data).await?;
+
+    let mut tx = state.connection_pool.begin().await?;
+    let pending_image_key = get_pending_image_for_update(&mut tx, post_id).await?;
+    if pending_image_key.flatten().as_deref() != Some(upload_key) {
+        return Ok((tx, discarded()));
+    }
+
+    let media = store_post_image(state, &mut tx, image, cleanup_guard).await?;
+    let replaced_image = set_post_image(&mut tx, post_id, media.id).await?;
+
+    let mut stale_files = release_post_image(&mut tx, replaced_image).await?;
+    stale_files.extend(discarded());
+    Ok((tx, stale_files))
+}
+
+/// A post image with its variants, named after the hash of its normalized
+/// bytes.
+struct PostImage {
+    hash: String,
+    file_name: String,
+    files: Vec<RenderedFile>,
+}
+
+/// Normalizes an uploaded image and renders its variants.
+async fn render_post_image(state: &AppState, data: Vec<u8>) -> Result<PostImage, CreatePostError> {
+    let format = state.media.output_format;
+    let max_pixels = state.media.max_image_pixels;
+    let image = state
+        .blocking_pool
+        .run(move || normalize_image(format, max_pixels, &data))
+        .await??;
+    let hash = format!("{:x}", Sha256::digest(&image.encoded));
+    let file_name = format!("{}.{}", hash, format.extension());
+
+    let files = render_image(
+        state,
+        image,
+        &file_name,
+        Variants::Widths(state.media.image_widths.clone()),
+    )
+    .await?;
+
+    Ok(PostImage {
+        hash,
+        file_name,
+        files,
+    })
+}
+
+/// Stores a post image under the hash of its normalized bytes. Files are only
+/// written for the first post using an image, later ones share its media row.
+async fn store_post_image(
+    state: &AppState,
+    tx: &mut Transaction<'_, Postgres>,
+    image: PostImage,
+    cleanup_guard: &mut CleanupGuard,
+) -> Result<Media, CreatePostError> {
+    let (media, created) = acquire_media(tx, &image.hash, &image.file_name).await?;
+    if created {
+        let variants = save_image(state, image.files, cleanup_guard).await?;
+        save_image_variants(tx, &media.path, &variants).await?;
+    }
+
+    Ok(media)
+}
+
+/// An avatar downloaded and rendered, ready to be stored under `file_name`.
+pub(super) struct AvatarImage {
+    file_name: String,
+    files: Vec<RenderedFile>,
+}
+
+/// Downloads the avatar at `url` and renders its square crop.
+pub(super) async fn render_avatar(
+    state: &AppState,
+    url: &str,
+) -> Result<AvatarImage, CreatePostError> {
+    let format = state.media.output_format;
+    let max_pixels = state.media.max_image_pixels;
+    let file_name = format!("avatar_{}.{}", Uuid::new_v4(), format.extension());
+
+    let data = download_avatar(state, url).await?;
+    let image = state
+        .blocking_pool
+        .run(move || normalize_image(format, max_pixels, &data))
+        .await??;
+    let files = render_image(
+        state,
+        image,
+        &file_name,
+        Variants::Square(state.media.avatar_size),
+    )
+    .await?;
+
+    Ok(AvatarImage { file_name, files })
+}
+
+/// Stores a rendered avatar, returning its path.
+pub(super) async fn store_avatar(
+    state: &AppState,
+    tx: &mut Transaction<'_, Postgres>,
+    avatar: AvatarImage,
+    cleanup_guard: &mut CleanupGuard,
+) -> Result<String, CreatePostError> {
+    let variants = save_image(state, avatar.files, cleanup_guard).await?;
+    save_image_variants(tx, &avatar.file_name, &variants).await?;
+
+    Ok(avatar.file_name)
+}
+
+/// Forgets the variants of an image that is no longer referenced and returns
+/// every file to remove once the transaction commits.
+pub(super) async fn release_image(
+    tx: &mut Transaction<'_, Postgres>,
+    image_path: Option<String>,
+) -> Result<Vec<String>, CreatePostError> {
+    let Some(image_path) = image_path else {
+        return Ok(Vec::new());
+    };
+
+    let mut files = delete_image_variants(tx, &image_path).await?;
+    files.push(image_path);
+    Ok(files)
+}
+
+/// Drops the post's reference to its image and returns the files to remove
+/// once the transaction commits, if no other post uses the image.
+async fn release_post_image(
+    tx: &mut Transaction<'_, Postgres>,
+    image_id: Option<Uuid>,
+) -> Result<Vec<String>, CreatePostError> {
+    let Some(image_id) = image_id else {
+        return Ok(Vec::new());
+    };
+
+    let image_path = release_media(tx, image_id).await?;
+    release_image(tx, image_path).await
+}
+
+/// Lists posts, newest first.
+#[utoipa::path(
+    get,
+    path = "/posts",
+    tag = "posts",
+    params(PageQuery),
+    responses(
+        (status = 200, description = "A page of posts", body = Page<BlogPost>),
+        GetPostError,
+    )
+)]
+#[tracing::instrument(name = "Listing posts", skip(state))]
+pub async fn list_posts(
+    State(state): State<Arc<AppState>>,
+    Query(page_query): Query<PageQuery>,
+) -> Result<Json<Page<BlogPost>>, GetPostError> {
+    let page = get_posts_page(&state.connection_pool, &page_query, &PostFilter::default()).await?;
+    Ok(Json(page))
+}
+
+/// A single post, as JSON when requested with `Accept: application/json` and
+/// as a page otherwise.
+#[utoipa::path(
+    get,
+    path = "/posts/{id}",
+    tag = "posts",
+    params(("id" = Uuid, Path, description = "Id of the post")),
+    responses(
+        (status = 200, description = "The post", content(
+            (BlogPost = "application/json"),
+            (String = "text/html"),
+        )),
+        GetPostError,
+    )
+)]
+#[tracing::instrument(name = "Getting a post", skip(state, headers))]
+pub async fn get_post(
+    State(state): State<Arc<AppState>>,
+    UrlPath(id): UrlPath<Uuid>,
+    caller: Option<Caller>,
+    headers: HeaderMap,
+) -> Result<Response, GetPostError> {
+    let post = get_post_by_id(&state.connection_pool, id)
+        .await?
+        .ok_or(GetPostError::NotFound)?;
+
+    if accepts_json(&headers) {
+        return Ok(Json(post).into_response());
+    }
+
+    let template = PostTemplate {
+        is_owner: caller.is_some_and(|caller| post.user_id == Some(caller.user_id)),
+        post,
+        upload_path: UPLOADS_ROUTE.to_string(),
+        base_url: state.base_url.clone(),
+        accepted_image_types: state.media.accept_attribute(),
+    };
+
+    Ok(template.into_response())
+}
+
+pub(super) fn accepts_json(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::ACCEPT)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|accept| accept.contains("application/json"))
+}
+
+/// Resized copies written next to a stored image.
+#[derive(Debug, Clone)]
+enum Variants {
+    /// Scaled down to each width, keeping the aspect ratio.
+    Widths(Vec<u32>),
+    /// Cropped to a centred square of the given size.
+    Square(u32),
+}
+
+/// An upload decoded and re-encoded in the configured output format. Only the
+/// pixels survive, so EXIF, XMP, ICC profiles, comments and any other metadata
+/// of the upload are never stored or served.
+struct NormalizedImage {
+    image: DynamicImage,
+    encoded: Vec<u8>,
+}
+
+/// Decodes `data` and encodes it as `format`. The EXIF orientation is applied
+/// to the pixels, as it is dropped with the rest of the metadata. Only the
+/// first frame of an animated GIF is kept. Images with more than `max_pixels`
+/// pixels are refused before decoding. CPU-bound, so it runs on the blocking
+/// pool.
+#[tracing::instrument(name = "Normalizing image", skip(data))]
+fn normalize_image(
+    format: MediaFormat,
+    max_pixels: u64,
+    data: &[u8],
+) -> Result<NormalizedImage, CreatePostError> {
+    let mut decoder = ImageReader::new(Cursor::new(data))
+        .with_guessed_format()?
+        .into_decoder()?;
+    check_dimensions(max_pixels, decoder.dimensions())?;
+    let orientation = decoder.orientation()?;
+    let mut img = DynamicImage::from_decoder(decoder)?;
+    img.apply_orientation(orientation);
+
+    // The encoders only accept a subset of the decoded pixel layouts.
+    let image = match format {
+        MediaFormat::Png => img,
+        MediaFormat::Jpeg => DynamicImage::ImageRgb8(img.to_rgb8()),
+        MediaFormat::Webp | MediaFormat::Gif => DynamicImage::ImageRgba8(img.to_rgba8()),
+    };
+
+    let mut buffer = Cursor::new(Vec::new());
+    image.write_to(&mut buffer, format.image_format())?;
+
+    Ok(NormalizedImage {
+        image,
+        encoded: buffer.into_inner(),
+    })
+}
+
+/// Fails with a limit error for images with more than `max_pixels` pixels.
+fn check_dimensions(max_pixels: u64, (width, height): (u32, u32)) -> Result<(), ImageError> {
+    if u64::from(width) * u64::from(height) > max_pixels {
+        return Err(ImageError::Limits(LimitError::from_kind(
+            LimitErrorKind::DimensionError,
+        )));
+    }
+    Ok(())
+}
+
+/// An encoded rendition of an image, ready to be stored.
+struct RenderedFile {
+    variant: ImageVariant,
+    data: Vec<u8>,
+    /// Whether the rendition is recorded as a variant. An original as wide as
+    /// one of its renditions is stored but not recorded, as the rendition
+    /// takes its width.
+    recorded: bool,
+}
+
+/// Renders the resized variants of the normalized image to be stored as
+/// `file_name`, encoded from the same metadata-free pixels. Images are never
+/// scaled up, so small images may have no variants besides the original,
+/// which always comes first.
+#[tracing::instrument(name = "Rendering image", skip(state, normalized))]
+async fn render_image(
+    state: &AppState,
+    normalized: NormalizedImage,
+    file_name: &str,
+    variants: Variants,
+) -> Result<Vec<RenderedFile>, CreatePostError> {
+    let format = state.media.output_format;
+    let NormalizedImage { image, encoded } = normalized;
+
+    let width = image.width();
+    let mut files = vec![RenderedFile {
+        variant: ImageVariant {
+            width: width as i32,
+            path: file_name.to_string(),
+        },
+        data: encoded,
+        recorded: true,
+    }];
+
+    let stem = file_name
+        .rsplit_once('.')
+        .map_or(file_name, |(stem, _)| stem)
+        .to_string();
+    let renditions = state
+        .blocking_pool
+        .run(move || render_variants(&image, &stem, variants, format))
+        .await??;
+
+    files[0].recorded = renditions
+        .iter()
+        .all(|(rendition_width, _, _)| *rendition_width != width);
+    files.extend(
+        renditions
+            .into_iter()
+            .map(|(width, path, data)| RenderedFile {
+                variant: ImageVariant {
+                    width: width as i32,
+                    path,
+                },
+                data,
+                recorded: true,
+            }),
+    );
+
+    Ok(files)
+}
+
+/// Writes the rendered files of an image, returning the variants to record.
+#[tracing::instrument(name = "Saving image", skip_all)]
+async fn save_image(
+    state: &AppState,
+    files: Vec<RenderedFile>,
+    cleanup_guard: &mut CleanupGuard,
+) -> Result<Vec<ImageVariant>, CreatePostError> {
+    let mime_type = state.media.output_format.mime_type();
+
+    let mut saved = Vec::with_capacity(files.len());
+    for RenderedFile {
+        variant,
+        data,
+        recorded,
+    } in files
+    {
+        cleanup_guard.add(variant.path.clone());
+        state.storage.put(&variant.path, data, mime_type).await?;
+        if recorded {
+            saved.push(variant);
+        }
+    }
+
+    Ok(saved)
+}
+
+/// Resizes and encodes the variants of `img`, returning their width, file
+/// name and encoded bytes. CPU-bound, so it runs on the blocking pool.
+fn render_variants(
+    img: &DynamicImage,
+    stem: &str,
+    variants: Variants,
+    format: MediaFormat,
+) -> Result<Vec<(u32, String, Vec<u8>)>, CreatePostError> {
+    let mut renditions = Vec::new();
+    match variants {
+        Variants::Widths(widths) => {
+            for width in widths.into_iter().filter(|&width| width < img.width()) {
+                renditions.push((
+                    width,
+                    format!("{}_{}.{}", stem, width, format.extension()),
+                    img.resize(width, u32::MAX, FilterType::Lanczos3),
+                ));
+            }
+        }
+        // Only cropped when both sides can be, and not when already square.
+        Variants::Square(size)
+            if size <= img.width()
+                && size <= img.height()
+                && (size, size) != (img.width(), img.height()) =>
+        {
+            renditions.push((
+                size,
+                format!("{}_{}x{}.{}", stem, size, size, format.extension()),
+                img.resize_to_fill(size, size, FilterType::Lanczos3),
+            ));
+        }
+        Variants::Square(_) => {}
+    }
+
+    renditions
+        .into_iter()
+        .map(|(width, path, rendition)| {
+            let mut buffer = Cursor::new(Vec::new());
+            rendition.write_to(&mut buffer, format.image_format())?;
+            Ok((width, path, buffer.into_inner()))
+        })
+        .collect()
+}
+
+/// Downloads the avatar at `url`, giving up once the fetcher's overall
+/// timeout has passed, however steadily the host keeps sending.
+#[tracing::instrument(name = "Downloading avatar", skip(state))]
+async fn download_avatar(state: &AppState, url: &str) -> Result<Vec<u8>, CreatePostError> {
+    let timeout = state.fetcher.timeout();
+    tokio::time::timeout(timeout, fetch_avatar(state, url))
         .await
-        .map_err(|e| CreatePostError::AvatarDownloadError(e.to_string()))?;
+        .map_err(|_| {
+            CreatePostError::AvatarDownloadError(format!("Timed out after {:?}", timeout))
+        })?
+}
+
+async fn fetch_avatar(state: &AppState, url: &str) -> Result<Vec<u8>, CreatePostError> {
+    let mut response = state.fetcher.get(url).await.map_err(|e| match e {
+        FetchError::Blocked(reason) => CreatePostError::AvatarUrlBlocked(reason),
+        // Retrying cannot fix the URL itself, only the network.
+        e @ (FetchError::InvalidUrl(_) | FetchError::TooManyRedirects(_)) => {
+            CreatePostError::invalid_field("avatar_url", "url", e.to_string())
+        }
+        e => CreatePostError::AvatarDownloadError(e.to_string()),
+    })?;
 
     if !response.status().is_success() {
         return Err(CreatePostError::AvatarDownloadError(format!(
-            "Failed to download avatar: HTTP {}",
+            "HTTP {}",
             response.status()
         )));
     }
 
-    let bytes = response
-        .bytes()
+    let limit = state.media.max_avatar_size;
+    if response
+        .content_length()
+        .is_some_and(|length| length > limit as u64)
+    {
+        return Err(CreatePostError::FileTooLarge(limit));
+    }
+
+    // The declared length is only a hint, so the limit is enforced again while reading.
+    let mut bytes = Vec::new();
+    while let Some(chunk) = response
+        .chunk()
         .await
-        .map_err(|e| CreatePostError::AvatarDownloadError(e.to_string()))?;
+        .map_err(|e| CreatePostError::AvatarDownloadError(e.to_string()))?
+    {
+        if bytes.len() + chunk.len() > limit {
+            return Err(CreatePostError::FileTooLarge(limit));
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+
+    check_image_format(&state.media, &bytes)?;
+
+    Ok(bytes)
+}
+
+/// Reads the image header on the blocking pool, so uploads that cannot be
+/// decoded or have too many pixels are rejected before they are queued.
+/// Answers with `503` instead of waiting while the pool is saturated.
+async fn probe_image(state: &AppState, path: &Path) -> Result<(), CreatePostError> {
+    let path = path.to_path_buf();
+    let max_pixels = state.media.max_image_pixels;
+    let (width, height) = state
+        .blocking_pool
+        .try_run(move || {
+            ImageReader::open(path)?
+                .with_guessed_format()?
+                .into_dimensions()
+                .map_err(|e| {
+                    CreatePostError::invalid_field(
+                        "image",
+                        "image",
+                        format!("Invalid image: {}", e),
+                    )
+                })
+        })
+        .await??;
+
+    check_dimensions(max_pixels, (width, height)).map_err(|_| {
+        CreatePostError::invalid_field(
+            "image",
+            "image",
+            format!(
+                "Image is too large: {}x{} pixels, at most {} pixels are allowed",
+                width, height, max_pixels
+            ),
+        )
+    })
+}
 
-    if guess_format(&bytes).ok() != Some(ALLOWED_IMAGE_TYPE) {
-        return Err(CreatePostError::InvalidFileType);
+/// Fields of a post as submitted, before they are validated.
+#[derive(Debug, Default)]
+pub(super) struct PostForm {
+    pub(super) text: Option<String>,
+    pub(super) tags: Option<Vec<String>>,
+    pub(super) image: Option<TempPath>,
+}
+
+/// Reports an invalid tag as a violation of the `tags` field.
+pub(super) fn invalid_tags(message: String) -> CreatePostError {
+    CreatePostError::invalid_field("tags", "tag", message)
+}
+
+/// Maps a multipart error, reporting an exceeded body limit as such.
+fn multipart_error(
+    state: &AppState,
+    error: MultipartError,
+    otherwise: impl FnOnce(MultipartError) -> CreatePostError,
+) -> CreatePostError {
+    if error.status() == StatusCode::PAYLOAD_TOO_LARGE {
+        CreatePostError::RequestTooLarge(state.max_request_size)
+    } else {
+        otherwise(error)
     }
+}
 
-    save_image(&bytes, path).await?;
-    Ok(())
+/// Streams the image part into a temporary file chunk by chunk, giving up as
+/// soon as it exceeds the limit or its first bytes are not an allowed format.
+/// Returns `None` for an empty part.
+async fn read_image_field(
+    state: &AppState,
+    mut field: Field<'_>,
+) -> Result<Option<TempPath>, CreatePostError> {
+    let limit = state.media.max_image_size;
+    let (file, path) = NamedTempFile::new()?.into_parts();
+    let mut file = tokio::fs::File::from_std(file);
+
+    let mut header = Vec::with_capacity(SNIFF_LENGTH);
+    let mut size = 0;
+    while let Some(chunk) = field.chunk().await.map_err(|e| {
+        multipart_error(state, e, |e| {
+            CreatePostError::MalformedRequest(format!("Failed to read image data: {}", e))
+        })
+    })? {
+        if header.len() < SNIFF_LENGTH {
+            let missing = SNIFF_LENGTH - header.len();
+            header.extend_from_slice(&chunk[..missing.min(chunk.len())]);
+            if header.len() == SNIFF_LENGTH {
+                check_image_format(&state.media, &header)?;
+            }
+        }
+
+        if size + chunk.len() > limit {
+            return Err(CreatePostError::FileTooLarge(limit));
+        }
+
+        file.write_all(&chunk).await?;
+        size += chunk.len();
+    }
+
+    // Browsers send an empty part when no file was selected.
+    if size == 0 {
+        return Ok(None);
+    }
+    if header.len() < SNIFF_LENGTH {
+        check_image_format(&state.media, &header)?;
+    }
+
+    file.flush().await?;
+    Ok(Some(path))
 }
 
 async fn process_multipart_fields(
-    multipart: &mut Multipart,
-) -> Result<
-    (
-        Option<String>,
-        Option<String>,
-        Option<String>,
-        Option<Vec<u8>>,
-    ),
-    CreatePostError,
-> {
-    let mut text = None;
-    let mut username = None;
-    let mut user_avatar_url = None;
-    let mut image_data = None;
-
-    while let Some(field) = multipart
-        .next_field()
-        .await
-        .map_err(|_| CreatePostError::InternalError)?
-    {
+    state: &AppState,
+    multipart: &mut axum::extract::Multipart,
+) -> Result<PostForm, CreatePostError> {
+    let mut form = PostForm::default();
+    while let Some(field) = multipart.next_field().await.map_err(|e| {
+        multipart_error(state, e, |e| {
+            if e.status().is_client_error() {
+                CreatePostError::MalformedRequest(format!("Invalid multipart body: {}", e))
+            } else {
+                CreatePostError::InternalError
+            }
+        })
+    })? {
         let name = field
             .name()
-            .ok_or_else(|| CreatePostError::ValidationError("Missing field name".to_string()))?
+            .ok_or_else(|| CreatePostError::MalformedRequest("Missing field name".to_string()))?
             .to_string();
 
         match name.as_str() {
             "text" => {
-                text = Some(field.text().await.map_err(|e| {
-                    CreatePostError::ValidationError(format!("Invalid text field: {}", e))
-                })?);
-                if text.clone().unwrap().is_empty() {
-                    return Err(CreatePostError::ValidationError(
-                        "Text is required".to_string(),
-                    ));
-                }
-            }
-            "username" => {
-                username = Some(field.text().await.map_err(|e| {
-                    CreatePostError::ValidationError(format!("Invalid username field: {}", e))
-                })?);
-                if username.clone().unwrap().is_empty() {
-                    return Err(CreatePostError::ValidationError(
-                        "Username is required".to_string(),
+                let text = field.text().await.map_err(|e| {
+                    multipart_error(state, e, |e| {
+                        CreatePostError::MalformedRequest(format!("Invalid text field: {}", e))
+                    })
+                })?;
+                if text.is_empty() {
+                    return Err(CreatePostError::invalid_field(
+                        "text",
+                        "required",
+                        "Text is required",
                     ));
                 }
+                form.text = Some(text);
             }
-            "user_avatar_url" => {
-                let url = field.text().await.map_err(|e| {
-                    CreatePostError::ValidationError(format!(
-                        "Invalid user_avatar_url field: {}",
-                        e
-                    ))
+            "tags" => {
+                let tags = field.text().await.map_err(|e| {
+                    multipart_error(state, e, |e| {
+                        CreatePostError::MalformedRequest(format!("Invalid tags field: {}", e))
+                    })
                 })?;
-                if !url.is_empty() {
-                    user_avatar_url = Some(url);
-                }
+                form.tags = Some(parse_tags(&tags).map_err(invalid_tags)?);
             }
             "image" => {
-                let data = field.bytes().await.map_err(|e| {
-                    CreatePostError::ValidationError(format!("Failed to read image data: {}", e))
-                })?;
-                if guess_format(&data).ok() != Some(ALLOWED_IMAGE_TYPE) {
-                    return Err(CreatePostError::InvalidFileType);
-                }
+                let Some(image) = read_image_field(state, field).await? else {
+                    continue;
+                };
 
-                if !data.is_empty() {
-                    image_data = Some(data.to_vec());
-                }
+                probe_image(state, &image).await?;
+
+                form.image = Some(image);
             }
             _ => {
                 warn!("Unknown field received: {}", name);
@@ -277,5 +1188,5 @@ async fn process_multipart_fields(
         }
     }
 
-    Ok((text, username, user_avatar_url, image_data))
+    Ok(form)
 }
@@ -0,0 +1,176 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::State,
+    response::{IntoResponse, Redirect},
+};
+use serde::Deserialize;
+use sqlx::{Postgres, Transaction};
+use utoipa::ToSchema;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    domain::users::{get_avatar, get_avatar_for_update, request_avatar, set_avatar, Avatar},
+    fetcher::FetchError,
+    jobs::{enqueue, enqueue_file_removal, Job},
+    startup::{AppState, UPLOADS_ROUTE},
+    templates::ProfileTemplate,
+};
+
+use super::{
+    caller::Caller,
+    errors::{field_violations, CreatePostError},
+    extract::Form,
+    posts::{release_image, render_avatar, store_avatar, CleanupGuard},
+};
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct AvatarForm {
+    /// URL of a PNG, JPEG, WebP or GIF image, empty to remove the avatar.
+    avatar_url: String,
+}
+
+#[derive(Debug, Validate)]
+struct NewAvatarData<'a> {
+    #[validate(url(message = "Avatar URL must be a valid URL"))]
+    avatar_url: &'a str,
+}
+
+/// Profile page of the caller, showing their avatar and its pending download.
+#[utoipa::path(
+    get,
+    path = "/profile",
+    tag = "profile",
+    responses(
+        (status = 200, description = "The profile page", body = String, content_type = "text/html"),
+        CreatePostError,
+    ),
+    security(("session" = []))
+)]
+#[tracing::instrument(name = "Showing profile", skip(state))]
+pub async fn profile(
+    State(state): State<Arc<AppState>>,
+    caller: Caller,
+) -> Result<ProfileTemplate, CreatePostError> {
+    let avatar = get_avatar(&state.connection_pool, caller.user_id).await?;
+
+    Ok(ProfileTemplate {
+        username: caller.username,
+        avatar_path: avatar.path,
+        avatar_url: avatar.pending_url.clone().or(avatar.url),
+        avatar_pending: avatar.pending_url.is_some(),
+        avatar_error: avatar.error,
+        upload_path: UPLOADS_ROUTE.to_string(),
+    })
+}
+
+/// Replaces the avatar shown on all of the caller's posts. The new avatar is
+/// downloaded in the background, an empty URL removes the avatar right away.
+#[utoipa::path(
+    post,
+    path = "/profile/avatar",
+    tag = "profile",
+    request_body(content = AvatarForm, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 303, description = "Avatar requested, redirects to `/profile`"),
+        CreatePostError,
+    ),
+    security(("session" = []))
+)]
+#[tracing::instrument(name = "Updating avatar", skip(state))]
+pub async fn update_avatar(
+    State(state): State<Arc<AppState>>,
+    caller: Caller,
+    Form(form): Form<AvatarForm>,
+) -> Result<impl IntoResponse, CreatePostError> {
+    let avatar_url = Some(form.avatar_url.trim()).filter(|url| !url.is_empty());
+
+    if let Some(avatar_url) = avatar_url {
+        NewAvatarData { avatar_url }
+            .validate()
+            .map_err(|e| CreatePostError::ValidationError(field_violations(&e)))?;
+
+        // Obviously blocked URLs are rejected now rather than failing in the job.
+        state.fetcher.check(avatar_url).await.map_err(|e| match e {
+            FetchError::Blocked(reason) => CreatePostError::AvatarUrlBlocked(reason),
+            e => CreatePostError::AvatarDownloadError(e.to_string()),
+        })?;
+    }
+
+    let mut tx = state
+        .connection_pool
+        .begin()
+        .await
+        .map_err(CreatePostError::DatabaseError)?;
+
+    let current = get_avatar_for_update(&mut tx, caller.user_id).await?;
+
+    let Some(url) = avatar_url else {
+        set_avatar(&mut tx, caller.user_id, &Avatar::default()).await?;
+        let stale_files = release_image(&mut tx, current.path).await?;
+        enqueue_file_removal(&mut tx, stale_files).await?;
+
+        tx.commit().await.map_err(CreatePostError::DatabaseError)?;
+
+        return Ok(Redirect::to("/profile"));
+    };
+
+    // The stored file is reused as long as the avatar URL does not change.
+    let unchanged = match current.pending_url.as_deref() {
+        Some(pending_url) => pending_url == url,
+        None => current.url.as_deref() == Some(url),
+    };
+    if unchanged {
+        return Ok(Redirect::to("/profile"));
+    }
+
+    request_avatar(&mut tx, caller.user_id, url).await?;
+    enqueue(
+        &mut tx,
+        &Job::DownloadAvatar {
+            user_id: caller.user_id,
+            url: url.to_string(),
+        },
+    )
+    .await?;
+
+    tx.commit().await.map_err(CreatePostError::DatabaseError)?;
+
+    Ok(Redirect::to("/profile"))
+}
+
+/// Downloads the avatar requested by the user, unless another one was requested
+/// since. The avatar is downloaded before the user is locked; returns the
+/// transaction holding the change and the files of the replaced avatar to
+/// remove once it commits.
+pub(crate) async fn download_requested_avatar(
+    state: &AppState,
+    user_id: Uuid,
+    url: &str,
+    cleanup_guard: &mut CleanupGuard,
+) -> Result<(Transaction<'static, Postgres>, Vec<String>), CreatePostError> {
+    let requested = get_avatar(&state.connection_pool, user_id).await?;
+    if requested.pending_url.as_deref() != Some(url) {
+        return Ok((state.connection_pool.begin().await?, Vec::new()));
+    }
+
+    let image = render_avatar(state, url).await?;
+
+    let mut tx = state.connection_pool.begin().await?;
+    let current = get_avatar_for_update(&mut tx, user_id).await?;
+    if current.pending_url.as_deref() != Some(url) {
+        return Ok((tx, Vec::new()));
+    }
+
+    let path = store_avatar(state, &mut tx, image, cleanup_guard).await?;
+    let avatar = Avatar {
+        path: Some(path),
+        url: Some(url.to_string()),
+        ..Avatar::default()
+    };
+    set_avatar(&mut tx, user_id, &avatar).await?;
+
+    let stale_files = release_image(&mut tx, current.path).await?;
+    Ok((tx, stale_files))
+}
@@ -0,0 +1,156 @@
+use std::{fmt::Display, str::FromStr, sync::Arc};
+
+use askama_axum::{IntoResponse, Response};
+use axum::{extract::State, Json};
+use chrono::NaiveDate;
+use hyper::HeaderMap;
+use serde::{de, Deserialize, Deserializer};
+use url::form_urlencoded;
+use utoipa::IntoParams;
+
+use crate::{
+    domain::search::{search_posts, SearchCriteria, SearchResults, MAX_QUERY_LENGTH},
+    startup::{AppState, UPLOADS_ROUTE},
+    templates::SearchTemplate,
+};
+
+use super::{errors::SearchError, extract::Query, posts::accepts_json};
+
+/// Query string of `/search`. Empty fields, as sent by the search form, are
+/// treated as missing.
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SearchParams {
+    /// Words, `"quoted phrases"`, `or` and `-excluded` words, at most 200 characters.
+    #[serde(default)]
+    q: String,
+    /// Only posts by this user.
+    #[serde(default, deserialize_with = "empty_as_none")]
+    username: Option<String>,
+    /// Only posts published on this day or later.
+    #[serde(default, deserialize_with = "empty_as_none")]
+    from: Option<NaiveDate>,
+    /// Only posts published on this day or earlier.
+    #[serde(default, deserialize_with = "empty_as_none")]
+    to: Option<NaiveDate>,
+    /// Number of results, 20 by default and at most 100.
+    limit: Option<i64>,
+    /// Number of results to skip.
+    offset: Option<i64>,
+}
+
+fn empty_as_none<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    match Option::<String>::deserialize(deserializer)?
+        .as_deref()
+        .map(str::trim)
+    {
+        None | Some("") => Ok(None),
+        Some(value) => value.parse().map(Some).map_err(de::Error::custom),
+    }
+}
+
+impl SearchParams {
+    fn into_criteria(self) -> Result<SearchCriteria, SearchError> {
+        let query = self.q.trim().to_string();
+        if query.chars().count() > MAX_QUERY_LENGTH {
+            return Err(SearchError::InvalidQuery(format!(
+                "Search query must be at most {} characters",
+                MAX_QUERY_LENGTH
+            )));
+        }
+        if matches!((self.from, self.to), (Some(from), Some(to)) if from > to) {
+            return Err(SearchError::InvalidQuery(
+                "Start date must not be after the end date".to_string(),
+            ));
+        }
+
+        Ok(SearchCriteria {
+            query,
+            username: self.username.map(|username| username.trim().to_string()),
+            from: self.from,
+            to: self.to,
+            limit: self.limit,
+            offset: self.offset,
+        })
+    }
+}
+
+/// Query string of the page of results following `offset`.
+fn next_page_query(criteria: &SearchCriteria, offset: i64) -> String {
+    let mut query = form_urlencoded::Serializer::new(String::new());
+    query.append_pair("q", &criteria.query);
+    if let Some(username) = &criteria.username {
+        query.append_pair("username", username);
+    }
+    if let Some(from) = criteria.from {
+        query.append_pair("from", &from.to_string());
+    }
+    if let Some(to) = criteria.to {
+        query.append_pair("to", &to.to_string());
+    }
+    query
+        .append_pair("limit", &criteria.limit().to_string())
+        .append_pair("offset", &offset.to_string())
+        .finish()
+}
+
+/// Full-text search over post texts, best matches first. Answers with JSON
+/// when requested with `Accept: application/json` and with a page otherwise.
+#[utoipa::path(
+    get,
+    path = "/search",
+    tag = "posts",
+    params(SearchParams),
+    responses(
+        (status = 200, description = "Matching posts", content(
+            (SearchResults = "application/json"),
+            (String = "text/html"),
+        )),
+        SearchError,
+    )
+)]
+#[tracing::instrument(name = "Searching posts", skip(state, headers))]
+pub async fn search(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<SearchParams>,
+    headers: HeaderMap,
+) -> Result<Response, SearchError> {
+    let criteria = params.into_criteria()?;
+
+    // An empty query, e.g. when the page is first opened, matches nothing.
+    let found = if criteria.query.is_empty() {
+        SearchResults {
+            results: Vec::new(),
+            next_offset: None,
+        }
+    } else {
+        search_posts(&state.connection_pool, &criteria).await?
+    };
+
+    if accepts_json(&headers) {
+        return Ok(Json(found).into_response());
+    }
+
+    let template = SearchTemplate {
+        next_query: found
+            .next_offset
+            .map(|offset| next_page_query(&criteria, offset)),
+        searched: !criteria.query.is_empty(),
+        username: criteria.username.unwrap_or_default(),
+        from: criteria
+            .from
+            .map(|from| from.to_string())
+            .unwrap_or_default(),
+        to: criteria.to.map(|to| to.to_string()).unwrap_or_default(),
+        query: criteria.query,
+        results: found.results,
+        upload_path: UPLOADS_ROUTE.to_string(),
+    };
+
+    Ok(template.into_response())
+}
@@ -0,0 +1,62 @@
+use std::sync::Arc;
+
+use askama_axum::{IntoResponse, Response};
+use axum::{extract::State, Json};
+use hyper::HeaderMap;
+
+use crate::{
+    domain::{get_posts_page, tags::normalize_tag, BlogPost, PostFilter},
+    pagination::{Page, PageQuery},
+    startup::{AppState, UPLOADS_ROUTE},
+    templates::TagTemplate,
+};
+
+use super::{
+    errors::GetPostError,
+    extract::{Path, Query},
+    posts::accepts_json,
+};
+
+/// Lists the posts carrying a tag, newest first. Answers with JSON when
+/// requested with `Accept: application/json` and with a page otherwise.
+#[utoipa::path(
+    get,
+    path = "/tags/{tag}",
+    tag = "posts",
+    params(("tag" = String, Path, description = "Name of the tag"), PageQuery),
+    responses(
+        (status = 200, description = "A page of posts", content(
+            (Page<BlogPost> = "application/json"),
+            (String = "text/html"),
+        )),
+        GetPostError,
+    )
+)]
+#[tracing::instrument(name = "Listing posts by tag", skip(state, headers))]
+pub async fn posts_by_tag(
+    State(state): State<Arc<AppState>>,
+    Path(tag): Path<String>,
+    Query(page_query): Query<PageQuery>,
+    headers: HeaderMap,
+) -> Result<Response, GetPostError> {
+    let tag = normalize_tag(&tag).ok_or(GetPostError::TagNotFound)?;
+    let filter = PostFilter {
+        tag: Some(tag.clone()),
+        ..PostFilter::default()
+    };
+    let page = get_posts_page(&state.connection_pool, &page_query, &filter).await?;
+
+    if accepts_json(&headers) {
+        return Ok(Json(page).into_response());
+    }
+
+    let template = TagTemplate {
+        tag,
+        older_query: page.older_query(page_query.limit()),
+        newer_query: page.newer_query(page_query.limit()),
+        posts: page.items,
+        upload_path: UPLOADS_ROUTE.to_string(),
+    };
+
+    Ok(template.into_response())
+}
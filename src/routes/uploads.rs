@@ -0,0 +1,53 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::State,
+    response::{IntoResponse, Response},
+};
+use hyper::header;
+
+use crate::{startup::AppState, storage::is_public_key};
+
+use super::{errors::FileError, extract::Path};
+
+/// Serves a stored upload from the configured storage backend. Raw uploads
+/// waiting to be processed are not found.
+#[utoipa::path(
+    get,
+    path = "/uploads/{key}",
+    tag = "uploads",
+    params(("key" = String, Path, description = "Storage key of the file, as in `image_path`")),
+    responses(
+        (status = 200, description = "The stored file", body = Vec<u8>, content_type = "application/octet-stream"),
+        FileError,
+    )
+)]
+#[tracing::instrument(name = "Serving upload", skip(state))]
+pub async fn serve_upload(
+    State(state): State<Arc<AppState>>,
+    Path(key): Path<String>,
+) -> Result<Response, FileError> {
+    if !is_public_key(&key) {
+        return Err(FileError::NotFound);
+    }
+
+    let object = state
+        .storage
+        .get(&key)
+        .await
+        .map_err(|e| FileError::ReadFailed(format!("{}: {}", key, e)))?
+        .ok_or(FileError::NotFound)?;
+
+    // Uploads are stored under fresh names and never change afterwards.
+    Ok((
+        [
+            (header::CONTENT_TYPE, object.content_type),
+            (
+                header::CACHE_CONTROL,
+                "public, max-age=31536000, immutable".to_string(),
+            ),
+        ],
+        object.data,
+    )
+        .into_response())
+}
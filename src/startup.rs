@@ -1,19 +1,46 @@
-use crate::configuration::Settings;
+use crate::blocking::BlockingPool;
+use crate::configuration::{MediaSettings, Settings};
+use crate::fetcher::GuardedFetcher;
+use crate::jobs::start_workers;
+use crate::routes::api::{
+    api_create_post, api_delete_post, api_get_post, api_list_posts, api_patch_post,
+    api_replace_post, API_V1_ROUTE,
+};
+use crate::routes::auth::{login, login_form, logout, register, register_form};
+use crate::routes::errors::{method_not_allowed, route_not_found};
+use crate::routes::feeds::{feed, Atom, Json, Rss, FEED_ROUTES};
 use crate::routes::health_check::handle_get;
 use crate::routes::home::home;
-use crate::routes::posts::create_post;
+use crate::routes::openapi::{
+    docs_asset, docs_index, docs_redirect, openapi_json, DOCS_ROUTE, OPENAPI_ROUTE,
+};
+use crate::routes::posts::{
+    create_post, get_post, list_posts, patch_post, remove_post, replace_post, submit_post_delete,
+    submit_post_edit,
+};
+use crate::routes::profile::{profile, update_avatar};
+use crate::routes::search::search;
+use crate::routes::tags::posts_by_tag;
+use crate::routes::uploads::serve_upload;
+use crate::storage::{build_storage, Storage};
+use crate::sweeper::start_sweeper;
 use crate::telemetry::{
     trace_layer_make_span_with, trace_layer_on_request, trace_layer_on_response,
 };
-use axum::routing::{get, post};
+use axum::extract::DefaultBodyLimit;
+use axum::handler::Handler;
+use axum::http::Method;
+use axum::routing::{on, MethodFilter};
 use axum::{serve::Serve, Router};
-use reqwest::Client;
 use sqlx::postgres::PgPoolOptions;
 use sqlx::PgPool;
+use std::sync::Arc;
 use tokio::net::TcpListener;
-use tower_http::services::ServeDir;
 use tower_http::trace::TraceLayer;
 
+/// Public route under which stored uploads are served.
+pub const UPLOADS_ROUTE: &str = "/uploads";
+
 pub struct Appliaction {
     port: u16,
     server: Serve<Router, Router>,
@@ -22,8 +49,14 @@ pub struct Appliaction {
 #[derive(Clone)]
 pub struct AppState {
     pub connection_pool: PgPool,
-    pub upload_path: std::path::PathBuf,
-    pub http_client: Client,
+    pub storage: Arc<dyn Storage>,
+    pub fetcher: GuardedFetcher,
+    pub blocking_pool: BlockingPool,
+    pub media: MediaSettings,
+    pub max_request_size: usize,
+    pub secure_cookies: bool,
+    /// Public address of the blog, without a trailing slash.
+    pub base_url: String,
 }
 
 impl Appliaction {
@@ -36,13 +69,26 @@ impl Appliaction {
         let listener = TcpListener::bind(address).await?;
         let port = listener.local_addr().unwrap().port();
         let connection_pool = get_connection_pool(configuration);
-        let http_client = Client::new();
+        let fetcher = GuardedFetcher::new(configuration.application.fetch.clone());
+        let storage = build_storage(&configuration.application).map_err(std::io::Error::other)?;
 
-        let app_state = AppState {
+        let app_state = Arc::new(AppState {
             connection_pool,
-            upload_path: configuration.application.upload_path.clone(),
-            http_client,
-        };
+            storage,
+            fetcher,
+            blocking_pool: BlockingPool::new(configuration.application.media.max_concurrency),
+            media: configuration.application.media.clone(),
+            max_request_size: configuration.application.max_request_size,
+            secure_cookies: configuration.application.secure_cookies,
+            base_url: configuration
+                .application
+                .base_url
+                .trim_end_matches('/')
+                .to_string(),
+        });
+
+        start_workers(&app_state, &configuration.application.jobs);
+        start_sweeper(&app_state, &configuration.application.sweeper);
 
         let server = run(listener, app_state)?;
 
@@ -58,13 +104,95 @@ impl Appliaction {
     }
 }
 
-fn get_connection_pool(settings: &Settings) -> PgPool {
+pub fn get_connection_pool(settings: &Settings) -> PgPool {
     PgPoolOptions::new().connect_lazy_with(settings.database.with_db())
 }
 
+/// Builds the router while recording the method and path of every route it
+/// serves, so they can be checked against the API documentation.
+pub struct Routes {
+    router: Router<Arc<AppState>>,
+    served: Vec<(Method, String)>,
+}
+
+impl Routes {
+    fn new() -> Self {
+        Self {
+            router: Router::new(),
+            served: Vec::new(),
+        }
+    }
+
+    fn route<H, T>(mut self, method: Method, path: &str, handler: H) -> Self
+    where
+        H: Handler<T, Arc<AppState>>,
+        T: 'static,
+    {
+        let filter = MethodFilter::try_from(method.clone()).expect("Unsupported method");
+        self.router = self.router.route(path, on(filter, handler));
+        self.served.push((method, path.to_string()));
+        self
+    }
+
+    /// Method and path of every route, with parameters written as `:name`.
+    pub fn served(&self) -> &[(Method, String)] {
+        &self.served
+    }
+}
+
+/// Every route of the application.
+pub fn routes() -> Routes {
+    let api_posts = format!("{}/posts", API_V1_ROUTE);
+    let api_post = format!("{}/posts/:id", API_V1_ROUTE);
+
+    let mut routes = Routes::new()
+        .route(Method::GET, "/health_check", handle_get)
+        .route(Method::GET, "/home", home)
+        .route(Method::GET, "/register", register_form)
+        .route(Method::POST, "/register", register)
+        .route(Method::GET, "/login", login_form)
+        .route(Method::POST, "/login", login)
+        .route(Method::POST, "/logout", logout)
+        .route(Method::GET, "/profile", profile)
+        .route(Method::POST, "/profile/avatar", update_avatar)
+        .route(Method::GET, "/tags/:tag", posts_by_tag)
+        .route(Method::GET, "/search", search);
+    for feed_route in &FEED_ROUTES {
+        routes = routes
+            .route(Method::GET, &feed_route.path::<Rss>(), feed::<Rss>)
+            .route(Method::GET, &feed_route.path::<Atom>(), feed::<Atom>)
+            .route(Method::GET, &feed_route.path::<Json>(), feed::<Json>);
+    }
+
+    routes
+        .route(Method::GET, "/posts", list_posts)
+        .route(Method::POST, "/posts", create_post)
+        .route(Method::GET, "/posts/:id", get_post)
+        .route(Method::PUT, "/posts/:id", replace_post)
+        .route(Method::PATCH, "/posts/:id", patch_post)
+        .route(Method::DELETE, "/posts/:id", remove_post)
+        .route(Method::POST, "/posts/:id/edit", submit_post_edit)
+        .route(Method::POST, "/posts/:id/delete", submit_post_delete)
+        .route(Method::GET, &api_posts, api_list_posts)
+        .route(Method::POST, &api_posts, api_create_post)
+        .route(Method::GET, &api_post, api_get_post)
+        .route(Method::PUT, &api_post, api_replace_post)
+        .route(Method::PATCH, &api_post, api_patch_post)
+        .route(Method::DELETE, &api_post, api_delete_post)
+        .route(Method::GET, OPENAPI_ROUTE, openapi_json)
+        .route(Method::GET, DOCS_ROUTE, docs_redirect)
+        .route(Method::GET, &format!("{}/", DOCS_ROUTE), docs_index)
+        .route(Method::GET, &format!("{}/*path", DOCS_ROUTE), docs_asset)
+        .route(
+            Method::GET,
+            &format!("{}/:key", UPLOADS_ROUTE),
+            serve_upload,
+        )
+}
+
 pub fn run(
     listener: TcpListener,
-    app_state: AppState,
+    app_state: Arc<AppState>,
 ) -> Result<Serve<Router, Router>, std::io::Error> {
     let trace_layer = TraceLayer::new_for_http()
         .make_span_with(trace_layer_make_span_with)
@@ -75,12 +203,12 @@ pub fn run(
 
     let server = axum::serve(
         listener,
-        Router::new()
-            .route("/health_check", get(handle_get))
-            .route("/home", get(home))
-            .route("/posts", post(create_post))
-            .nest_service("/uploads", ServeDir::new(&app_state.upload_path.clone()))
-            .with_state(app_state.into())
+        routes()
+            .router
+            .fallback(route_not_found)
+            .method_not_allowed_fallback(method_not_allowed)
+            .layer(DefaultBodyLimit::max(app_state.max_request_size))
+            .with_state(app_state)
             .layer(trace_layer),
     );
 
@@ -0,0 +1,113 @@
+pub mod local;
+pub mod s3;
+
+use std::{path::Path, sync::Arc};
+
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+
+use crate::configuration::{ApplicationSettings, StorageSettings};
+use local::LocalStorage;
+use s3::S3Storage;
+
+#[derive(Debug, thiserror::Error)]
+pub enum StorageError {
+    #[error("Invalid storage key: {0}")]
+    InvalidKey(String),
+
+    #[error("IO error: {0}")]
+    IoError(#[from] std::io::Error),
+
+    #[error("S3 error: {0}")]
+    S3Error(#[from] ::s3::error::S3Error),
+
+    #[error("Invalid object metadata: {0}")]
+    InvalidMetadata(String),
+}
+
+/// An object read back from storage.
+#[derive(Debug)]
+pub struct StoredObject {
+    pub data: Vec<u8>,
+    pub content_type: String,
+}
+
+/// An object as seen when listing the storage.
+#[derive(Debug)]
+pub struct ObjectInfo {
+    pub key: String,
+    pub last_modified: DateTime<Utc>,
+}
+
+/// Prefix under which quarantined objects are kept. Keys cannot contain a
+/// `/`, so quarantined objects are never served or listed.
+pub const QUARANTINE_PREFIX: &str = "quarantine";
+
+/// Prefix of uploads stored as received, before their metadata is stripped.
+/// They are never served, see [`is_public_key`].
+pub const INCOMING_PREFIX: &str = "incoming_";
+
+/// Where uploaded images and their variants are kept. Keys are flat file
+/// names as produced by the upload handlers, see [`is_valid_key`].
+#[async_trait]
+pub trait Storage: Send + Sync {
+    /// Stores `data` under `key`, replacing any previous object.
+    async fn put(&self, key: &str, data: Vec<u8>, content_type: &str) -> Result<(), StorageError>;
+
+    /// Stores the file at `path` under `key` without reading it into memory
+    /// at once, replacing any previous object.
+    async fn put_file(
+        &self,
+        key: &str,
+        path: &Path,
+        content_type: &str,
+    ) -> Result<(), StorageError>;
+
+    /// Returns the object stored under `key`, or `None` if there is none.
+    async fn get(&self, key: &str) -> Result<Option<StoredObject>, StorageError>;
+
+    /// Removes the object stored under `key`. Removing a missing object is not
+    /// an error, so removals can be retried.
+    async fn delete(&self, key: &str) -> Result<(), StorageError>;
+
+    /// Lists every object stored under a valid key, in no particular order.
+    async fn list(&self) -> Result<Vec<ObjectInfo>, StorageError>;
+
+    /// Moves the object stored under `key` below [`QUARANTINE_PREFIX`], where
+    /// it is kept for inspection but no longer served.
+    async fn quarantine(&self, key: &str) -> Result<(), StorageError>;
+}
+
+/// Keys never contain path separators, so they cannot escape the upload
+/// directory or bucket.
+pub fn is_valid_key(key: &str) -> bool {
+    !key.is_empty()
+        && !key.starts_with('.')
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+}
+
+/// Fails with [`StorageError::InvalidKey`] unless `key` is valid, so every
+/// backend accepts the same keys.
+fn check_key(key: &str) -> Result<&str, StorageError> {
+    if !is_valid_key(key) {
+        return Err(StorageError::InvalidKey(key.to_string()));
+    }
+    Ok(key)
+}
+
+/// Whether the object under `key` may be served to anyone, which raw uploads
+/// may not: they still carry their EXIF data such as GPS positions.
+pub fn is_public_key(key: &str) -> bool {
+    is_valid_key(key) && !key.starts_with(INCOMING_PREFIX)
+}
+
+/// Creates the backend selected by `application.storage`.
+pub fn build_storage(settings: &ApplicationSettings) -> Result<Arc<dyn Storage>, StorageError> {
+    let storage: Arc<dyn Storage> = match &settings.storage {
+        StorageSettings::Local => Arc::new(LocalStorage::new(settings.upload_path.clone())),
+        StorageSettings::S3(s3_settings) => Arc::new(S3Storage::new(s3_settings)?),
+    };
+    Ok(storage)
+}
@@ -0,0 +1,111 @@
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use axum::async_trait;
+
+use crate::domain::MediaFormat;
+
+use super::{
+    check_key, is_valid_key, ObjectInfo, Storage, StorageError, StoredObject, QUARANTINE_PREFIX,
+};
+
+/// Keeps objects as files in a directory on the local disk.
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    fn path(&self, key: &str) -> Result<PathBuf, StorageError> {
+        Ok(self.root.join(check_key(key)?))
+    }
+}
+
+#[async_trait]
+impl Storage for LocalStorage {
+    #[tracing::instrument(name = "Writing file to local storage", skip(self, data))]
+    async fn put(&self, key: &str, data: Vec<u8>, content_type: &str) -> Result<(), StorageError> {
+        tokio::fs::write(self.path(key)?, data).await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Copying file to local storage", skip(self))]
+    async fn put_file(
+        &self,
+        key: &str,
+        path: &Path,
+        content_type: &str,
+    ) -> Result<(), StorageError> {
+        tokio::fs::copy(path, self.path(key)?).await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Reading file from local storage", skip(self))]
+    async fn get(&self, key: &str) -> Result<Option<StoredObject>, StorageError> {
+        let data = match tokio::fs::read(self.path(key)?).await {
+            Ok(data) => data,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        // Files carry no metadata, the type follows from the extension they were stored with.
+        let content_type = key
+            .rsplit_once('.')
+            .and_then(|(_, extension)| MediaFormat::from_extension(extension))
+            .map_or("application/octet-stream", MediaFormat::mime_type);
+
+        Ok(Some(StoredObject {
+            data,
+            content_type: content_type.to_string(),
+        }))
+    }
+
+    #[tracing::instrument(name = "Removing file from local storage", skip(self))]
+    async fn delete(&self, key: &str) -> Result<(), StorageError> {
+        match tokio::fs::remove_file(self.path(key)?).await {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    #[tracing::instrument(name = "Listing files in local storage", skip(self))]
+    async fn list(&self) -> Result<Vec<ObjectInfo>, StorageError> {
+        let mut entries = match tokio::fs::read_dir(&self.root).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        // Directories, such as the quarantine, and stray files are skipped.
+        let mut objects = Vec::new();
+        while let Some(entry) = entries.next_entry().await? {
+            let metadata = entry.metadata().await?;
+            let Some(key) = entry.file_name().to_str().map(str::to_string) else {
+                continue;
+            };
+            if !metadata.is_file() || !is_valid_key(&key) {
+                continue;
+            }
+            objects.push(ObjectInfo {
+                key,
+                last_modified: metadata.modified()?.into(),
+            });
+        }
+
+        Ok(objects)
+    }
+
+    #[tracing::instrument(name = "Quarantining file in local storage", skip(self))]
+    async fn quarantine(&self, key: &str) -> Result<(), StorageError> {
+        let path = self.path(key)?;
+        let quarantine = self.root.join(QUARANTINE_PREFIX);
+        tokio::fs::create_dir_all(&quarantine).await?;
+        tokio::fs::rename(path, quarantine.join(key)).await?;
+        Ok(())
+    }
+}
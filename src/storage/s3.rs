@@ -0,0 +1,127 @@
+use std::path::Path;
+
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use s3::{creds::Credentials, error::S3Error, Bucket, Region};
+
+use crate::configuration::S3Settings;
+
+use super::{
+    check_key, is_valid_key, ObjectInfo, Storage, StorageError, StoredObject, QUARANTINE_PREFIX,
+};
+
+/// Keeps objects in a bucket of an S3-compatible service such as MinIO.
+pub struct S3Storage {
+    bucket: Box<Bucket>,
+}
+
+impl S3Storage {
+    pub fn new(settings: &S3Settings) -> Result<Self, StorageError> {
+        let region = Region::Custom {
+            region: settings.region.clone(),
+            endpoint: settings.endpoint.clone(),
+        };
+        let credentials = Credentials::new(
+            Some(&settings.access_key),
+            Some(&settings.secret_key),
+            None,
+            None,
+            None,
+        )
+        .map_err(S3Error::from)?;
+
+        let mut bucket = Bucket::new(&settings.bucket, region, credentials)?;
+        if settings.path_style {
+            bucket = bucket.with_path_style();
+        }
+
+        Ok(Self { bucket })
+    }
+}
+
+#[async_trait]
+impl Storage for S3Storage {
+    #[tracing::instrument(name = "Uploading object to S3", skip(self, data))]
+    async fn put(&self, key: &str, data: Vec<u8>, content_type: &str) -> Result<(), StorageError> {
+        self.bucket
+            .put_object_with_content_type(check_key(key)?, &data, content_type)
+            .await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Streaming file to S3", skip(self))]
+    async fn put_file(
+        &self,
+        key: &str,
+        path: &Path,
+        content_type: &str,
+    ) -> Result<(), StorageError> {
+        let key = check_key(key)?;
+        // Large files are sent as a multipart upload, one chunk at a time.
+        let mut file = tokio::fs::File::open(path).await?;
+        self.bucket
+            .put_object_stream_with_content_type(&mut file, key, content_type)
+            .await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Downloading object from S3", skip(self))]
+    async fn get(&self, key: &str) -> Result<Option<StoredObject>, StorageError> {
+        let response = match self.bucket.get_object(check_key(key)?).await {
+            Ok(response) => response,
+            Err(S3Error::HttpFailWithBody(404, _)) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let content_type = response
+            .headers()
+            .get("content-type")
+            .cloned()
+            .unwrap_or_else(|| "application/octet-stream".to_string());
+
+        Ok(Some(StoredObject {
+            data: response.bytes().to_vec(),
+            content_type,
+        }))
+    }
+
+    #[tracing::instrument(name = "Deleting object from S3", skip(self))]
+    async fn delete(&self, key: &str) -> Result<(), StorageError> {
+        self.bucket.delete_object(check_key(key)?).await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Listing objects in S3", skip(self))]
+    async fn list(&self) -> Result<Vec<ObjectInfo>, StorageError> {
+        // The delimiter keeps quarantined objects out of the listing.
+        let pages = self
+            .bucket
+            .list(String::new(), Some("/".to_string()))
+            .await?;
+
+        let mut objects = Vec::new();
+        for object in pages.into_iter().flat_map(|page| page.contents) {
+            if !is_valid_key(&object.key) {
+                continue;
+            }
+            let last_modified = DateTime::parse_from_rfc3339(&object.last_modified)
+                .map_err(|e| StorageError::InvalidMetadata(e.to_string()))?;
+            objects.push(ObjectInfo {
+                key: object.key,
+                last_modified: last_modified.with_timezone(&Utc),
+            });
+        }
+
+        Ok(objects)
+    }
+
+    #[tracing::instrument(name = "Quarantining object in S3", skip(self))]
+    async fn quarantine(&self, key: &str) -> Result<(), StorageError> {
+        let key = check_key(key)?;
+        self.bucket
+            .copy_object_internal(key, format!("{}/{}", QUARANTINE_PREFIX, key))
+            .await?;
+        self.bucket.delete_object(key).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,123 @@
+use std::{collections::HashSet, sync::Arc};
+
+use chrono::Utc;
+use sqlx::PgPool;
+use tracing::{error, info, warn};
+
+use crate::{
+    configuration::{OrphanAction, Settings, SweeperSettings},
+    domain::media::lock_removable_file,
+    startup::{get_connection_pool, AppState},
+    storage::{build_storage, Storage, StorageError},
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum SweepError {
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] sqlx::Error),
+
+    #[error("Storage error: {0}")]
+    StorageError(#[from] StorageError),
+}
+
+/// Outcome of a sweep for orphaned files.
+#[derive(Debug, Default)]
+pub struct SweepReport {
+    /// Number of stored files looked at.
+    pub scanned: usize,
+    /// Files that were deleted or quarantined.
+    pub orphans: Vec<String>,
+}
+
+/// Deletes or quarantines stored files that no row references, such as files
+/// left behind by a crash between writing an image and committing its row.
+/// Files younger than the grace period are skipped.
+#[tracing::instrument(name = "Sweeping orphaned files", skip_all)]
+pub async fn sweep_orphans(
+    pool: &PgPool,
+    storage: &dyn Storage,
+    settings: &SweeperSettings,
+) -> Result<SweepReport, SweepError> {
+    let cutoff = Utc::now() - settings.grace_period();
+    // Listed before the references are read, so a file referenced in between
+    // is never mistaken for an orphan.
+    let objects = storage.list().await?;
+    let referenced = referenced_keys(pool).await?;
+
+    let mut report = SweepReport {
+        scanned: objects.len(),
+        ..SweepReport::default()
+    };
+    for object in objects {
+        if object.last_modified > cutoff || referenced.contains(&object.key) {
+            continue;
+        }
+
+        // Content-addressed files may be stored again since the references
+        // were read, so they are only removed under the lock of their media.
+        let mut tx = pool.begin().await?;
+        if !lock_removable_file(&mut tx, &object.key).await? {
+            continue;
+        }
+        match settings.action {
+            OrphanAction::Delete => storage.delete(&object.key).await?,
+            OrphanAction::Quarantine => storage.quarantine(&object.key).await?,
+        }
+        tx.commit().await?;
+        warn!("Swept orphaned file {} ({:?})", object.key, settings.action);
+        report.orphans.push(object.key);
+    }
+
+    info!(
+        "Swept {} orphaned files out of {}",
+        report.orphans.len(),
+        report.scanned
+    );
+    Ok(report)
+}
+
+/// Every storage key a row points to: images with their variants, avatars,
+/// including those of posts without an author, and uploads still waiting to
+/// be processed.
+async fn referenced_keys(pool: &PgPool) -> Result<HashSet<String>, sqlx::Error> {
+    let keys = sqlx::query_scalar!(
+        r#"
+        SELECT path AS "key!" FROM media
+        UNION SELECT variant_path FROM image_variants
+        UNION SELECT avatar_path FROM users WHERE avatar_path IS NOT NULL
+        UNION SELECT legacy_avatar_path FROM blog_posts WHERE legacy_avatar_path IS NOT NULL
+        UNION SELECT pending_image_key FROM blog_posts WHERE pending_image_key IS NOT NULL
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(keys.into_iter().collect())
+}
+
+/// Spawns the periodic sweep unless it is disabled.
+pub fn start_sweeper(state: &Arc<AppState>, settings: &SweeperSettings) {
+    let Some(interval) = settings.interval() else {
+        return;
+    };
+
+    let state = Arc::clone(state);
+    let settings = settings.clone();
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(interval).await;
+            if let Err(e) =
+                sweep_orphans(&state.connection_pool, state.storage.as_ref(), &settings).await
+            {
+                error!("Failed to sweep orphaned files: {}", e);
+            }
+        }
+    });
+}
+
+/// Runs a single sweep outside of the server, for the `sweep-orphans` command.
+pub async fn sweep_once(configuration: &Settings) -> Result<SweepReport, SweepError> {
+    let pool = get_connection_pool(configuration);
+    let storage = build_storage(&configuration.application)?;
+    sweep_orphans(&pool, storage.as_ref(), &configuration.application.sweeper).await
+}
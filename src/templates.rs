@@ -1,10 +1,71 @@
 use askama_axum::Template;
 
-use crate::domain::BlogPost;
+use crate::domain::{search::SearchResult, BlogPost};
 
 #[derive(Template)]
 #[template(path = "home.html")]
 pub struct HomeTemplate {
+    pub current_user: Option<String>,
     pub posts: Vec<BlogPost>,
     pub upload_path: String,
+    pub accepted_image_types: String,
+    pub older_query: Option<String>,
+    pub newer_query: Option<String>,
+}
+
+#[derive(Template)]
+#[template(path = "post.html")]
+pub struct PostTemplate {
+    pub post: BlogPost,
+    pub upload_path: String,
+    /// Prefix of the absolute URLs in the Open Graph tags.
+    pub base_url: String,
+    /// Whether the signed-in user wrote the post, who gets forms to edit and
+    /// delete it.
+    pub is_owner: bool,
+    pub accepted_image_types: String,
+}
+
+#[derive(Template)]
+#[template(path = "tag.html")]
+pub struct TagTemplate {
+    pub tag: String,
+    pub posts: Vec<BlogPost>,
+    pub upload_path: String,
+    pub older_query: Option<String>,
+    pub newer_query: Option<String>,
+}
+
+#[derive(Template)]
+#[template(path = "search.html")]
+pub struct SearchTemplate {
+    pub query: String,
+    pub username: String,
+    pub from: String,
+    pub to: String,
+    /// Whether a search was run, as opposed to the empty form.
+    pub searched: bool,
+    pub results: Vec<SearchResult>,
+    pub upload_path: String,
+    pub next_query: Option<String>,
+}
+
+#[derive(Template)]
+#[template(path = "register.html")]
+pub struct RegisterTemplate;
+
+#[derive(Template)]
+#[template(path = "login.html")]
+pub struct LoginTemplate;
+
+#[derive(Template)]
+#[template(path = "profile.html")]
+pub struct ProfileTemplate {
+    pub username: String,
+    pub avatar_path: Option<String>,
+    /// URL shown in the form, the requested one while it is being downloaded.
+    pub avatar_url: Option<String>,
+    pub avatar_pending: bool,
+    pub avatar_error: Option<String>,
+    pub upload_path: String,
 }
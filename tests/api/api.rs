@@ -0,0 +1,304 @@
+use crate::helpers::{cookie_client, spawn_app, TestApp, TEST_USERNAME};
+use reqwest::{header, StatusCode};
+use serde_json::{json, Value};
+
+fn posts_url(app: &TestApp) -> String {
+    format!("{}/api/v1/posts", &app.address)
+}
+
+async fn create_post(app: &TestApp, body: Value) -> reqwest::Response {
+    app.api_client
+        .post(posts_url(app))
+        .json(&body)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+/// Creates a post and returns its JSON representation.
+async fn create_valid_post(app: &TestApp, body: Value) -> Value {
+    let response = create_post(app, body).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    response.json().await.expect("Failed to parse post.")
+}
+
+async fn send(
+    app: &TestApp,
+    method: reqwest::Method,
+    id: &str,
+    body: Option<Value>,
+) -> reqwest::Response {
+    let mut request = app
+        .api_client
+        .request(method, format!("{}/{}", posts_url(app), id));
+    if let Some(body) = body {
+        request = request.json(&body);
+    }
+    request.send().await.expect("Failed to execute request.")
+}
+
+#[tokio::test]
+async fn create_post_returns_201_with_location_and_post() {
+    let app = spawn_app().await;
+
+    let response = create_post(
+        &app,
+        json!({ "text": "Hello from a **script** #rust", "tags": ["API"] }),
+    )
+    .await;
+
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let location = response.headers()[header::LOCATION]
+        .to_str()
+        .unwrap()
+        .to_string();
+    let post: Value = response.json().await.expect("Failed to parse post.");
+    let id = post["id"].as_str().expect("Post should have an id");
+    assert_eq!(location, format!("/api/v1/posts/{}", id));
+    assert_eq!(post["text"], "Hello from a **script** #rust");
+    assert!(post["text_html"]
+        .as_str()
+        .unwrap()
+        .contains("<strong>script</strong>"));
+    assert_eq!(post["tags"], json!(["api", "rust"]));
+    assert_eq!(post["username"], TEST_USERNAME);
+
+    let response = app
+        .api_client
+        .get(format!("{}{}", &app.address, location))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::OK);
+    let fetched: Value = response.json().await.expect("Failed to parse post.");
+    assert_eq!(fetched, post);
+}
+
+#[tokio::test]
+async fn create_post_shares_validation_with_the_form() {
+    let app = spawn_app().await;
+    let too_many_tags: Vec<String> = (0..11).map(|i| format!("tag{}", i)).collect();
+    let cases = [
+        (
+            json!({ "text": "too short" }),
+            "text",
+            "between 10 and 10,000",
+        ),
+        (
+            json!({ "text": "x".repeat(10_001) }),
+            "text",
+            "between 10 and 10,000",
+        ),
+        (
+            json!({ "text": "A valid post text", "tags": too_many_tags }),
+            "tags",
+            "At most 10 tags",
+        ),
+        (
+            json!({ "text": "A valid post text", "tags": ["not valid"] }),
+            "tags",
+            "Invalid tag: not valid",
+        ),
+    ];
+
+    for (body, field, message) in cases {
+        let response = create_post(&app, body.clone()).await;
+        assert_eq!(
+            response.status(),
+            StatusCode::BAD_REQUEST,
+            "Unexpected status for {}",
+            body
+        );
+        let problem: Value = response.json().await.expect("Failed to parse error.");
+        assert_eq!(problem["code"], "validation_failed");
+        assert_eq!(problem["errors"][0]["field"], field, "{}", problem);
+        assert!(
+            problem["errors"][0]["message"]
+                .as_str()
+                .unwrap()
+                .contains(message),
+            "Unexpected error for {}: {}",
+            body,
+            problem
+        );
+    }
+
+    for (body, message) in [
+        (json!({ "tags": ["rust"] }), "missing field `text`"),
+        (
+            json!({ "text": "A valid post text", "image": "x" }),
+            "unknown field `image`",
+        ),
+    ] {
+        let response = create_post(&app, body.clone()).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let problem: Value = response.json().await.expect("Failed to parse error.");
+        assert_eq!(problem["code"], "malformed_request");
+        assert!(
+            problem["detail"].as_str().unwrap().contains(message),
+            "Unexpected error for {}: {}",
+            body,
+            problem
+        );
+    }
+
+    let count = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM blog_posts"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to count posts.");
+    assert_eq!(count, 0);
+}
+
+#[tokio::test]
+async fn list_posts_returns_pages_of_posts() {
+    let app = spawn_app().await;
+    for i in 0..3 {
+        create_valid_post(&app, json!({ "text": format!("API post number {}", i) })).await;
+    }
+
+    let response = app
+        .api_client
+        .get(format!("{}?limit=2", posts_url(&app)))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::OK);
+    let page: Value = response.json().await.expect("Failed to parse page.");
+    let texts: Vec<&str> = page["items"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|post| post["text"].as_str().unwrap())
+        .collect();
+    assert_eq!(texts, vec!["API post number 2", "API post number 1"]);
+    let older = page["older"]
+        .as_str()
+        .expect("Page should link older posts");
+
+    let page: Value = app
+        .api_client
+        .get(format!("{}?limit=2&before={}", posts_url(&app), older))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .json()
+        .await
+        .expect("Failed to parse page.");
+    assert_eq!(page["items"].as_array().unwrap().len(), 1);
+    assert_eq!(page["items"][0]["text"], "API post number 0");
+}
+
+#[tokio::test]
+async fn put_and_patch_update_a_post() {
+    let app = spawn_app().await;
+    let post = create_valid_post(
+        &app,
+        json!({ "text": "The original text #first", "tags": ["kept"] }),
+    )
+    .await;
+    let id = post["id"].as_str().unwrap();
+
+    let response = send(
+        &app,
+        reqwest::Method::PATCH,
+        id,
+        Some(json!({ "text": "The patched text #second" })),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let post: Value = response.json().await.unwrap();
+    assert_eq!(post["text"], "The patched text #second");
+    assert_eq!(post["tags"], json!(["kept", "second"]));
+
+    let response = send(
+        &app,
+        reqwest::Method::PATCH,
+        id,
+        Some(json!({ "tags": ["other"] })),
+    )
+    .await;
+    let post: Value = response.json().await.unwrap();
+    assert_eq!(post["text"], "The patched text #second");
+    assert_eq!(post["tags"], json!(["other", "second"]));
+
+    let response = send(
+        &app,
+        reqwest::Method::PUT,
+        id,
+        Some(json!({ "text": "The replaced text" })),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let post: Value = response.json().await.unwrap();
+    assert_eq!(post["text"], "The replaced text");
+    assert_eq!(post["tags"], json!([]));
+
+    let response = send(&app, reqwest::Method::PUT, id, Some(json!({ "tags": [] }))).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let response = send(
+        &app,
+        reqwest::Method::PATCH,
+        id,
+        Some(json!({ "text": "short" })),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn delete_removes_a_post() {
+    let app = spawn_app().await;
+    let post = create_valid_post(&app, json!({ "text": "A post to delete" })).await;
+    let id = post["id"].as_str().unwrap();
+
+    let response = send(&app, reqwest::Method::DELETE, id, None).await;
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    let response = send(&app, reqwest::Method::GET, id, None).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let response = send(&app, reqwest::Method::DELETE, id, None).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn changes_require_the_author() {
+    let app = spawn_app().await;
+    let post = create_valid_post(&app, json!({ "text": "Only mine to change" })).await;
+    let id = post["id"].as_str().unwrap();
+    let url = format!("{}/{}", posts_url(&app), id);
+
+    let anonymous = cookie_client();
+    let response = anonymous
+        .post(posts_url(&app))
+        .json(&json!({ "text": "Written by nobody" }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = anonymous
+        .delete(&url)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let other = app.logged_in_client("other_user").await;
+    let response = other
+        .patch(&url)
+        .json(&json!({ "text": "Taken over by someone else" }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    let response = other
+        .delete(&url)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let response = send(&app, reqwest::Method::GET, id, None).await;
+    let post: Value = response.json().await.unwrap();
+    assert_eq!(post["text"], "Only mine to change");
+}
@@ -0,0 +1,168 @@
+use crate::helpers::{cookie_client, spawn_app, spawn_app_with, TEST_PASSWORD, TEST_USERNAME};
+
+#[tokio::test]
+async fn register_signs_user_in_and_stores_password_hash() {
+    let app = spawn_app().await;
+    let client = cookie_client();
+
+    let response = app.register(&client, "new_user").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let html = response
+        .text()
+        .await
+        .expect("Failed to read response text.");
+    assert!(html.contains("Signed in as <strong>new_user</strong>"));
+
+    let password_hash = sqlx::query_scalar!(
+        "SELECT password_hash FROM users WHERE username = $1",
+        "new_user"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to fetch user from database.");
+    assert!(password_hash.starts_with("$argon2id$"));
+    assert!(!password_hash.contains(TEST_PASSWORD));
+}
+
+#[tokio::test]
+async fn session_cookie_is_secure_unless_turned_off() {
+    for secure in [true, false] {
+        let app = spawn_app_with(|c| c.application.secure_cookies = secure).await;
+        let client = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .expect("Failed to build HTTP client");
+
+        let response = client
+            .post(format!("{}/register", &app.address))
+            .form(&[("username", "new_user"), ("password", TEST_PASSWORD)])
+            .send()
+            .await
+            .expect("Failed to execute request.");
+
+        assert_eq!(response.status().as_u16(), 303);
+        let cookie = response
+            .headers()
+            .get("set-cookie")
+            .and_then(|value| value.to_str().ok())
+            .expect("No session cookie set");
+        assert!(cookie.contains("HttpOnly"));
+        assert_eq!(cookie.contains("Secure"), secure, "{}", cookie);
+    }
+}
+
+#[tokio::test]
+async fn register_with_invalid_username_returns_400() {
+    let app = spawn_app().await;
+
+    let response = app.register(&cookie_client(), "a").await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    let error_message = response
+        .text()
+        .await
+        .expect("Failed to read response text.");
+    assert!(error_message.contains("Username must be between 2 and 50 characters"));
+}
+
+#[tokio::test]
+async fn register_with_short_password_returns_400() {
+    let app = spawn_app().await;
+
+    let response = cookie_client()
+        .post(format!("{}/register", &app.address))
+        .form(&[("username", "new_user"), ("password", "short")])
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 400);
+    let error_message = response
+        .text()
+        .await
+        .expect("Failed to read response text.");
+    assert!(error_message.contains("Password must be between 8 and 128 characters"));
+}
+
+#[tokio::test]
+async fn register_with_taken_username_returns_409() {
+    let app = spawn_app().await;
+
+    let response = app.register(&cookie_client(), TEST_USERNAME).await;
+
+    assert_eq!(response.status().as_u16(), 409);
+}
+
+#[tokio::test]
+async fn login_with_valid_credentials_signs_user_in() {
+    let app = spawn_app().await;
+    let client = cookie_client();
+
+    let response = client
+        .post(format!("{}/login", &app.address))
+        .form(&[("username", TEST_USERNAME), ("password", TEST_PASSWORD)])
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 200);
+    let html = response
+        .text()
+        .await
+        .expect("Failed to read response text.");
+    assert!(html.contains(&format!("Signed in as <strong>{}</strong>", TEST_USERNAME)));
+}
+
+#[tokio::test]
+async fn login_with_wrong_password_returns_401() {
+    let app = spawn_app().await;
+
+    let response = cookie_client()
+        .post(format!("{}/login", &app.address))
+        .form(&[("username", TEST_USERNAME), ("password", "wrong password")])
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn login_with_unknown_username_returns_401() {
+    let app = spawn_app().await;
+
+    let response = cookie_client()
+        .post(format!("{}/login", &app.address))
+        .form(&[("username", "unknown_user"), ("password", TEST_PASSWORD)])
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn logout_ends_session() {
+    let app = spawn_app().await;
+
+    let response = app
+        .api_client
+        .post(format!("{}/logout", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 200);
+    let html = response
+        .text()
+        .await
+        .expect("Failed to read response text.");
+    assert!(html.contains("to write posts"));
+
+    let sessions = sqlx::query_scalar!("SELECT COUNT(*) FROM sessions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to count sessions.");
+    assert_eq!(sessions, Some(0));
+}
@@ -0,0 +1,256 @@
+use crate::helpers::{cookie_client, spawn_app, TestApp};
+use reqwest::{header, multipart, StatusCode};
+use serde_json::{json, Value};
+
+/// Checks the members every problem has and returns the problem.
+async fn read_problem(response: reqwest::Response, status: StatusCode, code: &str) -> Value {
+    assert_eq!(response.status(), status);
+    assert_eq!(
+        response.headers()[header::CONTENT_TYPE],
+        "application/problem+json"
+    );
+    let problem: Value = response.json().await.expect("Failed to parse problem.");
+    assert_eq!(problem["type"], "about:blank");
+    assert_eq!(problem["title"], status.canonical_reason().unwrap());
+    assert_eq!(problem["status"], status.as_u16());
+    assert_eq!(problem["code"], code, "{}", problem);
+    assert!(problem["detail"].is_string());
+    problem
+}
+
+fn violations(problem: &Value) -> Vec<(String, String)> {
+    problem["errors"]
+        .as_array()
+        .expect("Problem should list the violations")
+        .iter()
+        .map(|violation| {
+            assert!(violation["message"].is_string());
+            (
+                violation["field"].as_str().unwrap().to_string(),
+                violation["code"].as_str().unwrap().to_string(),
+            )
+        })
+        .collect()
+}
+
+async fn get(app: &TestApp, path: &str) -> reqwest::Response {
+    app.api_client
+        .get(format!("{}{}", &app.address, path))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+#[tokio::test]
+async fn not_found_is_a_problem() {
+    let app = spawn_app().await;
+
+    let response = get(&app, &format!("/api/v1/posts/{}", uuid::Uuid::new_v4())).await;
+    let problem = read_problem(response, StatusCode::NOT_FOUND, "post_not_found").await;
+    assert_eq!(problem["detail"], "Post not found");
+    assert!(problem.get("errors").is_none());
+
+    let response = get(&app, "/users/nobody/feed.atom").await;
+    read_problem(response, StatusCode::NOT_FOUND, "user_not_found").await;
+
+    let response = get(&app, "/tags/not%20a%20tag").await;
+    read_problem(response, StatusCode::NOT_FOUND, "tag_not_found").await;
+
+    for path in ["/uploads/missing.png", "/api/docs/missing.js"] {
+        let response = get(&app, path).await;
+        read_problem(response, StatusCode::NOT_FOUND, "file_not_found").await;
+    }
+}
+
+#[tokio::test]
+async fn unknown_routes_and_methods_are_problems() {
+    let app = spawn_app().await;
+
+    let response = get(&app, "/no/such/page").await;
+    read_problem(response, StatusCode::NOT_FOUND, "route_not_found").await;
+
+    let response = app
+        .api_client
+        .delete(format!("{}/home", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.headers()[header::ALLOW], "GET,HEAD");
+    read_problem(
+        response,
+        StatusCode::METHOD_NOT_ALLOWED,
+        "method_not_allowed",
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn invalid_post_form_lists_every_field() {
+    let app = spawn_app().await;
+    let tags: Vec<String> = (0..11).map(|i| format!("tag{}", i)).collect();
+    let form = multipart::Form::new()
+        .text("text", "Short")
+        .text("tags", tags.join(","));
+
+    let response = app
+        .api_client
+        .post(format!("{}/posts", &app.address))
+        .multipart(form)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    let problem = read_problem(response, StatusCode::BAD_REQUEST, "validation_failed").await;
+    assert_eq!(
+        violations(&problem),
+        vec![
+            ("tags".to_string(), "length".to_string()),
+            ("text".to_string(), "length".to_string()),
+        ]
+    );
+    assert_eq!(
+        problem["errors"][1]["message"],
+        "Text must be between 10 and 10,000 characters"
+    );
+}
+
+#[tokio::test]
+async fn invalid_tag_names_the_tags_field() {
+    let app = spawn_app().await;
+
+    let response = app
+        .api_client
+        .post(format!("{}/api/v1/posts", &app.address))
+        .json(&json!({ "text": "A valid post text", "tags": ["no spaces"] }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    let problem = read_problem(response, StatusCode::BAD_REQUEST, "validation_failed").await;
+    assert_eq!(
+        violations(&problem),
+        vec![("tags".to_string(), "tag".to_string())]
+    );
+    assert_eq!(problem["errors"][0]["message"], "Invalid tag: no spaces");
+}
+
+#[tokio::test]
+async fn invalid_registration_lists_every_field() {
+    let app = spawn_app().await;
+
+    let response = cookie_client()
+        .post(format!("{}/register", &app.address))
+        .form(&[("username", "a!"), ("password", "short")])
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    let problem = read_problem(response, StatusCode::BAD_REQUEST, "validation_failed").await;
+    let mut violations = violations(&problem);
+    violations.sort();
+    assert_eq!(
+        violations,
+        vec![
+            ("password".to_string(), "length".to_string()),
+            ("username".to_string(), "regex".to_string()),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn other_errors_have_their_own_codes() {
+    let app = spawn_app().await;
+
+    let response = cookie_client()
+        .post(format!("{}/api/v1/posts", &app.address))
+        .json(&json!({ "text": "Written by nobody" }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    read_problem(response, StatusCode::UNAUTHORIZED, "unauthorized").await;
+
+    let response = app
+        .api_client
+        .post(format!("{}/api/v1/posts", &app.address))
+        .header(header::CONTENT_TYPE, "application/json")
+        .body("{ not json")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    read_problem(response, StatusCode::BAD_REQUEST, "malformed_request").await;
+
+    let response = get(&app, "/search?q=rust&from=2024-02-01&to=2024-01-01").await;
+    read_problem(response, StatusCode::BAD_REQUEST, "invalid_search").await;
+
+    let response = cookie_client()
+        .post(format!("{}/login", &app.address))
+        .form(&[("username", "nobody"), ("password", "wrong password")])
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    read_problem(response, StatusCode::UNAUTHORIZED, "invalid_credentials").await;
+
+    let response = cookie_client()
+        .post(format!("{}/register", &app.address))
+        .form(&[("username", "valid_user"), ("password", "another password")])
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    read_problem(response, StatusCode::CONFLICT, "username_taken").await;
+}
+
+#[tokio::test]
+async fn unreadable_requests_are_problems() {
+    let app = spawn_app().await;
+
+    for path in ["/api/v1/posts/not-an-id", "/posts/not-an-id"] {
+        let response = get(&app, path).await;
+        read_problem(response, StatusCode::BAD_REQUEST, "malformed_request").await;
+    }
+
+    let response = get(&app, "/api/v1/posts?limit=many").await;
+    read_problem(response, StatusCode::BAD_REQUEST, "malformed_request").await;
+
+    let response = app
+        .api_client
+        .post(format!("{}/posts", &app.address))
+        .header(header::CONTENT_TYPE, "multipart/form-data")
+        .body("text=no boundary")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    read_problem(response, StatusCode::BAD_REQUEST, "malformed_request").await;
+
+    let response = cookie_client()
+        .post(format!("{}/login", &app.address))
+        .header(header::CONTENT_TYPE, "text/plain")
+        .body("username=valid_user")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    read_problem(
+        response,
+        StatusCode::UNSUPPORTED_MEDIA_TYPE,
+        "malformed_request",
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn server_errors_do_not_reveal_their_cause() {
+    let app = spawn_app().await;
+    sqlx::query("DROP VIEW post_details")
+        .execute(&app.db_pool)
+        .await
+        .expect("Failed to drop view.");
+
+    let response = get(&app, &format!("/api/v1/posts/{}", uuid::Uuid::new_v4())).await;
+
+    let problem = read_problem(
+        response,
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "database_error",
+    )
+    .await;
+    assert_eq!(problem["detail"], "Internal server error");
+}
@@ -0,0 +1,341 @@
+use crate::helpers::{
+    get_image_asset, serve_image_asset, spawn_app, TestApp, TEST_BASE_URL, TEST_USERNAME,
+};
+use reqwest::{header, multipart, StatusCode};
+use serde_json::Value;
+
+async fn get_feed(app: &TestApp, path: &str) -> reqwest::Response {
+    app.api_client
+        .get(format!("{}{}", &app.address, path))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn read_rss(app: &TestApp, path: &str) -> rss::Channel {
+    let response = get_feed(app, path).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()[header::CONTENT_TYPE],
+        "application/rss+xml; charset=utf-8"
+    );
+    let body = response
+        .bytes()
+        .await
+        .expect("Failed to read response body.");
+    rss::Channel::read_from(&body[..]).expect("Failed to parse RSS feed.")
+}
+
+async fn read_atom(app: &TestApp, path: &str) -> atom_syndication::Feed {
+    let response = get_feed(app, path).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()[header::CONTENT_TYPE],
+        "application/atom+xml; charset=utf-8"
+    );
+    let body = response
+        .bytes()
+        .await
+        .expect("Failed to read response body.");
+    atom_syndication::Feed::read_from(&body[..]).expect("Failed to parse Atom feed.")
+}
+
+#[tokio::test]
+async fn rss_feed_lists_posts_with_titles_authors_and_enclosures() {
+    let app = spawn_app().await;
+    let image_name = "jetbrains-logo.png";
+    let form = multipart::Form::new()
+        .text(
+            "text",
+            "# Hello **feed** readers\n\nThe body of the post. #rust",
+        )
+        .part(
+            "image",
+            multipart::Part::bytes(get_image_asset(image_name)).file_name(image_name),
+        );
+    let response = app
+        .api_client
+        .post(format!("{}/posts", &app.address))
+        .multipart(form)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert!(response.status().is_success());
+    app.wait_for_jobs().await;
+
+    let channel = read_rss(&app, "/feed.rss").await;
+
+    assert_eq!(channel.title(), "Blog Posts");
+    assert_eq!(channel.items().len(), 1);
+    let item = &channel.items()[0];
+    assert_eq!(item.title(), Some("Hello feed readers"));
+    assert!(item
+        .description()
+        .is_some_and(|html| html.contains("<strong>feed</strong>")));
+    assert_eq!(item.categories()[0].name(), "rust");
+    let creators = item.dublin_core_ext().map(|dc| dc.creators().to_vec());
+    assert_eq!(creators, Some(vec![TEST_USERNAME.to_string()]));
+
+    let link = item.link().expect("Item should link to the post");
+    assert!(link.starts_with(&format!("{}/posts/", TEST_BASE_URL)));
+
+    let enclosure = item.enclosure().expect("Item should have an enclosure");
+    let path = enclosure
+        .url()
+        .strip_prefix(TEST_BASE_URL)
+        .expect("Enclosure URL should be absolute");
+    assert!(path.starts_with("/uploads/"));
+    assert!(enclosure.mime_type().starts_with("image/"));
+    let image = get_feed(&app, path).await;
+    assert_eq!(image.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn atom_feed_lists_posts() {
+    let app = spawn_app().await;
+    app.create_text_post("First post").await;
+    app.create_text_post("Second post\n\nwith more text").await;
+
+    let feed = read_atom(&app, "/feed.atom").await;
+
+    assert_eq!(feed.title().as_str(), "Blog Posts");
+    let titles: Vec<&str> = feed
+        .entries()
+        .iter()
+        .map(|entry| entry.title().as_str())
+        .collect();
+    assert_eq!(titles, vec!["Second post", "First post"]);
+
+    let entry = &feed.entries()[0];
+    assert!(entry.id().starts_with("urn:uuid:"));
+    assert_eq!(entry.authors()[0].name(), TEST_USERNAME);
+    assert!(entry
+        .content()
+        .and_then(|content| content.value())
+        .is_some_and(|html| html.contains("<p>with more text</p>")));
+    assert!(entry.links().iter().any(|link| link.rel() == "alternate"));
+    assert!(feed
+        .links()
+        .iter()
+        .any(|link| link.rel() == "self" && link.href().ends_with("/feed.atom")));
+}
+
+#[tokio::test]
+async fn user_feeds_only_list_posts_of_that_user() {
+    let app = spawn_app().await;
+    app.create_text_post("Written by the test user").await;
+    let other = app.logged_in_client("other_user").await;
+    let form = multipart::Form::new().text("text", "Written by someone else");
+    let response = other
+        .post(format!("{}/posts", &app.address))
+        .multipart(form)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert!(response.status().is_success());
+
+    let channel = read_rss(&app, "/users/other_user/feed.rss").await;
+    assert_eq!(channel.title(), "Posts by other_user");
+    let titles: Vec<_> = channel.items().iter().map(|item| item.title()).collect();
+    assert_eq!(titles, vec![Some("Written by someone else")]);
+
+    let feed = read_atom(&app, &format!("/users/{}/feed.atom", TEST_USERNAME)).await;
+    assert_eq!(feed.entries().len(), 1);
+    assert_eq!(
+        feed.entries()[0].title().as_str(),
+        "Written by the test user"
+    );
+
+    let response = get_feed(&app, "/users/nobody/feed.rss").await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn user_feeds_leave_out_posts_without_author_of_the_same_name() {
+    let app = spawn_app().await;
+    app.create_text_post("Written by the test user").await;
+    sqlx::query!(
+        "INSERT INTO blog_posts (id, text, text_html, username) VALUES ($1, $2, $3, $4)",
+        uuid::Uuid::new_v4(),
+        "This post predates user accounts.",
+        "<p>This post predates user accounts.</p>",
+        TEST_USERNAME
+    )
+    .execute(&app.db_pool)
+    .await
+    .expect("Failed to insert post.");
+
+    let channel = read_rss(&app, &format!("/users/{}/feed.rss", TEST_USERNAME)).await;
+
+    let titles: Vec<_> = channel.items().iter().map(|item| item.title()).collect();
+    assert_eq!(titles, vec![Some("Written by the test user")]);
+}
+
+#[tokio::test]
+async fn tag_feeds_only_list_posts_with_that_tag() {
+    let app = spawn_app().await;
+    app.create_text_post("Writing about #rust").await;
+    app.create_text_post("Writing about #go").await;
+
+    let channel = read_rss(&app, "/tags/Rust/feed.rss").await;
+    assert_eq!(channel.title(), "Posts tagged #rust");
+    let titles: Vec<_> = channel.items().iter().map(|item| item.title()).collect();
+    assert_eq!(titles, vec![Some("Writing about #rust")]);
+
+    let feed = read_atom(&app, "/tags/go/feed.atom").await;
+    assert_eq!(feed.entries().len(), 1);
+    assert_eq!(feed.entries()[0].title().as_str(), "Writing about #go");
+
+    let feed = read_atom(&app, "/tags/python/feed.atom").await;
+    assert!(feed.entries().is_empty());
+
+    let response = get_feed(&app, "/tags/not%20a%20tag/feed.rss").await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn feeds_answer_conditional_requests() {
+    let app = spawn_app().await;
+    app.create_text_post("A post to cache").await;
+
+    let response = get_feed(&app, "/feed.atom").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let etag = response.headers()[header::ETAG].clone();
+    let last_modified = response.headers()[header::LAST_MODIFIED].clone();
+    assert!(last_modified.to_str().unwrap().ends_with(" GMT"));
+
+    let response = app
+        .api_client
+        .get(format!("{}/feed.atom", &app.address))
+        .header(header::IF_NONE_MATCH, etag.clone())
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(response.headers()[header::ETAG], etag);
+    assert!(response.text().await.unwrap().is_empty());
+
+    let response = app
+        .api_client
+        .get(format!("{}/feed.atom", &app.address))
+        .header(header::IF_MODIFIED_SINCE, last_modified.clone())
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+    let response = app
+        .api_client
+        .get(format!("{}/feed.atom", &app.address))
+        .header(header::IF_MODIFIED_SINCE, "Mon, 01 Jan 2001 00:00:00 GMT")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::OK);
+
+    app.create_text_post("A newer post").await;
+    let response = app
+        .api_client
+        .get(format!("{}/feed.atom", &app.address))
+        .header(header::IF_NONE_MATCH, etag.clone())
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_ne!(response.headers()[header::ETAG], etag);
+}
+
+async fn read_json_feed(app: &TestApp, path: &str) -> Value {
+    let response = get_feed(app, path).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()[header::CONTENT_TYPE],
+        "application/feed+json; charset=utf-8"
+    );
+    response.json().await.expect("Failed to parse JSON Feed.")
+}
+
+#[tokio::test]
+async fn json_feed_lists_posts_with_absolute_urls() {
+    let app = spawn_app().await;
+    let (_avatar_server, avatar_url) = serve_image_asset("jetbrains-logo.png").await;
+    app.set_avatar(&app.api_client, &avatar_url).await;
+    app.wait_for_jobs().await;
+    let image_name = "jetbrains-logo.png";
+    let form = multipart::Form::new()
+        .text("text", "Hello *JSON* feed\n\nSecond paragraph #rust")
+        .part(
+            "image",
+            multipart::Part::bytes(get_image_asset(image_name)).file_name(image_name),
+        );
+    let response = app
+        .api_client
+        .post(format!("{}/posts", &app.address))
+        .multipart(form)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert!(response.status().is_success());
+    app.wait_for_jobs().await;
+
+    let feed = read_json_feed(&app, "/feed.json").await;
+
+    assert_eq!(feed["version"], "https://jsonfeed.org/version/1.1");
+    assert_eq!(feed["title"], "Blog Posts");
+    assert_eq!(feed["home_page_url"], format!("{}/home", TEST_BASE_URL));
+    assert_eq!(feed["feed_url"], format!("{}/feed.json", TEST_BASE_URL));
+
+    let items = feed["items"].as_array().expect("Feed should have items");
+    assert_eq!(items.len(), 1);
+    let item = &items[0];
+    assert!(item["id"].as_str().unwrap().starts_with("urn:uuid:"));
+    assert!(item["url"]
+        .as_str()
+        .unwrap()
+        .starts_with(&format!("{}/posts/", TEST_BASE_URL)));
+    assert_eq!(item["title"], "Hello JSON feed");
+    assert!(item["content_html"]
+        .as_str()
+        .unwrap()
+        .contains("<em>JSON</em>"));
+    assert_eq!(item["tags"], serde_json::json!(["rust"]));
+    assert!(item["date_published"].is_string());
+    assert!(item["date_modified"].is_string());
+
+    let uploads = format!("{}/uploads/", TEST_BASE_URL);
+    let image = item["image"].as_str().expect("Item should have an image");
+    assert!(image.starts_with(&uploads));
+    assert_eq!(item["attachments"][0]["url"], image);
+    assert!(item["attachments"][0]["mime_type"]
+        .as_str()
+        .unwrap()
+        .starts_with("image/"));
+
+    let author = &item["authors"][0];
+    assert_eq!(author["name"], TEST_USERNAME);
+    let avatar = author["avatar"]
+        .as_str()
+        .expect("Author should have an avatar");
+    assert!(avatar.starts_with(&uploads));
+    let response = get_feed(&app, avatar.strip_prefix(TEST_BASE_URL).unwrap()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn json_feed_leaves_out_what_a_post_does_not_have() {
+    let app = spawn_app().await;
+    app.create_text_post("Just some #text here").await;
+
+    let feed = read_json_feed(&app, "/feed.json").await;
+    let item = &feed["items"][0];
+    assert!(item.get("image").is_none());
+    assert!(item.get("attachments").is_none());
+    assert!(item["authors"][0].get("avatar").is_none());
+
+    let feed = read_json_feed(&app, "/tags/text/feed.json").await;
+    assert_eq!(feed["title"], "Posts tagged #text");
+    assert_eq!(feed["items"].as_array().unwrap().len(), 1);
+
+    let feed = read_json_feed(&app, &format!("/users/{}/feed.json", TEST_USERNAME)).await;
+    assert_eq!(feed["items"].as_array().unwrap().len(), 1);
+}
@@ -2,16 +2,23 @@ use std::{
     fs::{self, File},
     io::Read,
     path::PathBuf,
+    time::Duration,
 };
 
 use jetbrains_web_app::{
-    configuration::{get_configuration, DatabaseSettings},
+    configuration::{get_configuration, DatabaseSettings, Settings},
     startup::Appliaction,
     telemetry::initialize_tracing,
 };
 use once_cell::sync::Lazy;
+use reqwest::multipart;
 use sqlx::{Connection, Executor, PgConnection, PgPool};
 use tracing::error;
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, MockServer, ResponseTemplate,
+};
 
 static TRACING: Lazy<()> = Lazy::new(|| {
     // Lower level tracing results in mess output as this is not yet fixed
@@ -19,10 +26,17 @@ static TRACING: Lazy<()> = Lazy::new(|| {
     let _ = initialize_tracing("error");
 });
 
+pub const TEST_USERNAME: &str = "valid_user";
+pub const TEST_PASSWORD: &str = "correct horse battery staple";
+/// Public address configured for the test app, which absolute links start with.
+pub const TEST_BASE_URL: &str = "https://blog.example.com";
+
 pub struct TestApp {
     pub address: String,
     pub upload_path: PathBuf,
     pub db_pool: PgPool,
+    /// Client signed in as [`TEST_USERNAME`].
+    pub api_client: reqwest::Client,
 }
 
 impl Drop for TestApp {
@@ -39,14 +53,140 @@ impl Drop for TestApp {
     }
 }
 
+impl TestApp {
+    pub async fn register(&self, client: &reqwest::Client, username: &str) -> reqwest::Response {
+        client
+            .post(format!("{}/register", &self.address))
+            .form(&[("username", username), ("password", TEST_PASSWORD)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Registers `username` and returns a client holding their session.
+    pub async fn logged_in_client(&self, username: &str) -> reqwest::Client {
+        let client = cookie_client();
+        let response = self.register(&client, username).await;
+        assert!(response.status().is_success());
+        client
+    }
+
+    pub async fn set_avatar(
+        &self,
+        client: &reqwest::Client,
+        avatar_url: &str,
+    ) -> reqwest::Response {
+        client
+            .post(format!("{}/profile/avatar", &self.address))
+            .form(&[("avatar_url", avatar_url)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Waits until the background workers have run every queued job, either to
+    /// completion or until it was marked as failed.
+    pub async fn wait_for_jobs(&self) {
+        for _ in 0..1500 {
+            let pending = sqlx::query_scalar!(
+                r#"SELECT COUNT(*) AS "count!" FROM jobs WHERE failed_at IS NULL"#
+            )
+            .fetch_one(&self.db_pool)
+            .await
+            .expect("Failed to count jobs");
+            if pending == 0 {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("Background jobs did not finish in time");
+    }
+
+    /// Submits the post form as [`TEST_USERNAME`], with `tags` if given.
+    pub async fn create_post(&self, text: &str, tags: Option<&str>) -> reqwest::Response {
+        let mut form = multipart::Form::new().text("text", text.to_string());
+        if let Some(tags) = tags {
+            form = form.text("tags", tags.to_string());
+        }
+
+        self.post_form(form).await
+    }
+
+    pub async fn create_text_post(&self, text: &str) {
+        let response = self.create_post(text, None).await;
+        assert!(response.status().is_success());
+    }
+
+    /// Submits a post with an image and returns its id, without waiting for the
+    /// image to be processed.
+    pub async fn submit_image_post(&self) -> Uuid {
+        let image_name = "jetbrains-logo.png";
+        let form = multipart::Form::new()
+            .text("text", "This is a sample post text.")
+            .part(
+                "image",
+                multipart::Part::bytes(get_image_asset(image_name)).file_name(image_name),
+            );
+
+        let response = self.post_form(form).await;
+        assert!(response.status().is_success());
+
+        sqlx::query_scalar!("SELECT id FROM blog_posts ORDER BY published_at DESC, id DESC LIMIT 1")
+            .fetch_one(&self.db_pool)
+            .await
+            .expect("Failed to fetch post id from database.")
+    }
+
+    /// Creates a post with an image and returns its id and the storage key of
+    /// the processed image.
+    pub async fn create_image_post(&self) -> (Uuid, String) {
+        let id = self.submit_image_post().await;
+        self.wait_for_jobs().await;
+
+        let image_path = sqlx::query_scalar!(
+            "SELECT media.path FROM blog_posts JOIN media ON media.id = blog_posts.image_id WHERE blog_posts.id = $1",
+            id
+        )
+        .fetch_one(&self.db_pool)
+        .await
+        .expect("Failed to fetch image path from database.");
+
+        (id, image_path)
+    }
+
+    async fn post_form(&self, form: multipart::Form) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/posts", &self.address))
+            .multipart(form)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+}
+
 pub async fn spawn_app() -> TestApp {
+    spawn_app_with(|_| {}).await
+}
+
+/// Spawns the application after `configure` has adjusted the test settings.
+pub async fn spawn_app_with(configure: impl FnOnce(&mut Settings)) -> TestApp {
     Lazy::force(&TRACING);
 
     let configuration = {
         let mut c = get_configuration().expect("Failed to read configuration");
-        c.database.db_name = uuid::Uuid::new_v4().to_string();
+        c.database.db_name = Uuid::new_v4().to_string();
         c.application.port = 0;
+        c.application.base_url = format!("{}/", TEST_BASE_URL);
         c.application.upload_path = create_temp_image_dir();
+        // The tests talk plain HTTP, over which a `Secure` cookie is not sent.
+        c.application.secure_cookies = false;
+        // Avatars are served by local mock servers.
+        c.application.fetch.allowed_networks = vec!["127.0.0.1/32".parse().unwrap()];
+        c.application.jobs.poll_interval_ms = 10;
+        c.application.jobs.retry_base_delay_ms = 10;
+        // Tests that need the periodic sweep turn it back on.
+        c.application.sweeper.interval_secs = 0;
+        configure(&mut c);
         c
     };
 
@@ -57,16 +197,38 @@ pub async fn spawn_app() -> TestApp {
         .expect("Failed to build application");
 
     let application_port = application.port();
-    let _ = tokio::spawn(application.run_until_stopped());
+    tokio::spawn(application.run_until_stopped());
 
-    TestApp {
+    let mut app = TestApp {
         address: format!("http://localhost:{}", application_port),
         upload_path: configuration.application.upload_path,
         db_pool,
-    }
+        api_client: cookie_client(),
+    };
+    app.api_client = app.logged_in_client(TEST_USERNAME).await;
+    app
+}
+
+pub fn cookie_client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .cookie_store(true)
+        .build()
+        .expect("Failed to build HTTP client")
 }
 
 async fn configure_database_for_tests(config: &DatabaseSettings) -> PgPool {
+    let connection_pool = create_database(config).await;
+
+    sqlx::migrate!("./migrations")
+        .run(&connection_pool)
+        .await
+        .expect("Failed to run migrations");
+
+    connection_pool
+}
+
+/// Creates the database named by `config`, leaving it without any tables.
+pub async fn create_database(config: &DatabaseSettings) -> PgPool {
     let mut connection = PgConnection::connect_with(&config.without_db())
         .await
         .expect("Failed to connect to Postgres");
@@ -76,22 +238,16 @@ async fn configure_database_for_tests(config: &DatabaseSettings) -> PgPool {
         .await
         .expect("Failed to create database");
 
-    let connection_pool = PgPool::connect_with(config.with_db())
-        .await
-        .expect("Failed to connect to Postgres");
-
-    sqlx::migrate!("./migrations")
-        .run(&connection_pool)
+    PgPool::connect_with(config.with_db())
         .await
-        .expect("Failed to run migrations");
-
-    connection_pool
+        .expect("Failed to connect to Postgres")
 }
 
 pub fn get_image_asset(name: &str) -> Vec<u8> {
     let mut image_data = Vec::new();
     let d = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
-        .join("tests\\resources")
+        .join("tests")
+        .join("resources")
         .join(name);
 
     let mut file = File::open(d).expect("Failed to open image file");
@@ -101,7 +257,22 @@ pub fn get_image_asset(name: &str) -> Vec<u8> {
 }
 
 pub fn create_temp_image_dir() -> PathBuf {
-    let dir = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+    let dir = std::env::temp_dir().join(Uuid::new_v4().to_string());
     fs::create_dir(&dir).expect("Failed to create temp dir");
     dir
 }
+
+/// Serves the named asset from `tests/resources` so avatar downloads do not
+/// depend on external hosts. The returned server must be kept alive for the
+/// duration of the test.
+pub async fn serve_image_asset(name: &str) -> (MockServer, String) {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path(format!("/{}", name)))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(get_image_asset(name)))
+        .mount(&server)
+        .await;
+
+    let url = format!("{}/{}", server.uri(), name);
+    (server, url)
+}
@@ -0,0 +1,47 @@
+use crate::helpers::spawn_app;
+
+#[tokio::test]
+async fn home_shows_limited_posts_with_older_link() {
+    let app = spawn_app().await;
+    for i in 1..=3 {
+        app.create_text_post(&format!("This is sample post number {}.", i))
+            .await;
+    }
+    let client = reqwest::Client::new();
+
+    let html = client
+        .get(format!("{}/home?limit=2", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .text()
+        .await
+        .expect("Failed to read response text.");
+
+    assert!(html.contains("This is sample post number 3."));
+    assert!(html.contains("This is sample post number 2."));
+    assert!(!html.contains("This is sample post number 1."));
+    assert!(!html.contains("Newer posts"));
+
+    let older_href = html
+        .split(r#"<a href=""#)
+        .skip(1)
+        .filter_map(|rest| rest.split('"').next())
+        .find(|href| href.starts_with("/home?before="))
+        .expect("Page should link to older posts")
+        .replace("&amp;", "&");
+
+    let html = client
+        .get(format!("{}{}", &app.address, older_href))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .text()
+        .await
+        .expect("Failed to read response text.");
+
+    assert!(html.contains("This is sample post number 1."));
+    assert!(!html.contains("This is sample post number 2."));
+    assert!(html.contains("Newer posts"));
+    assert!(!html.contains("Older posts"));
+}
@@ -1,3 +1,16 @@
+mod api;
+mod auth;
+mod errors;
+mod feeds;
 mod health_check;
 mod helpers;
+mod home;
+mod markdown;
+mod migrations;
+mod openapi;
 mod posts;
+mod profile;
+mod search;
+mod sweeper;
+mod tags;
+mod uploads;
@@ -0,0 +1,141 @@
+use crate::helpers::{spawn_app, TestApp};
+use jetbrains_web_app::domain::{BlogPost, MAX_TEXT_LENGTH};
+
+async fn get_only_post(app: &TestApp) -> BlogPost {
+    let id = sqlx::query_scalar!("SELECT id FROM blog_posts")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch post id from database.");
+
+    reqwest::Client::new()
+        .get(format!("{}/posts/{}", &app.address, id))
+        .header("Accept", "application/json")
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .json()
+        .await
+        .expect("Failed to parse post JSON.")
+}
+
+#[tokio::test]
+async fn post_text_is_rendered_from_markdown() {
+    let app = spawn_app().await;
+    let text = "Some **bold** and ~~struck~~ text\nwith a [link](https://example.com).\n\n\
+                | Language | Year |\n|----------|------|\n| Rust     | 2015 |\n\n\
+                See https://www.rust-lang.org";
+    assert!(app.create_post(text, None).await.status().is_success());
+
+    let post = get_only_post(&app).await;
+
+    assert_eq!(post.text, text);
+    assert!(post.text_html.contains("<strong>bold</strong>"));
+    assert!(post.text_html.contains("<del>struck</del>"));
+    assert!(post.text_html.contains("text<br>"));
+    assert!(post.text_html.contains("<table>"));
+    assert!(post.text_html.contains("<td>Rust</td>"));
+    assert!(post
+        .text_html
+        .contains(r#"<a href="https://example.com" rel="noopener noreferrer nofollow">link</a>"#));
+    assert!(post
+        .text_html
+        .contains(r#"<a href="https://www.rust-lang.org""#));
+
+    let html = reqwest::Client::new()
+        .get(format!("{}/home", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .text()
+        .await
+        .expect("Failed to read response text.");
+    assert!(html.contains("<strong>bold</strong>"));
+}
+
+#[tokio::test]
+async fn rendered_post_text_is_sanitized() {
+    let app = spawn_app().await;
+    let text = "Harmless <em>emphasis</em> <script>alert(1)</script>\n\n\
+                [click](javascript:alert(1)) <img src=\"x.png\" onerror=\"alert(1)\">\n\n\
+                <iframe src=\"https://example.com\"></iframe>";
+    assert!(app.create_post(text, None).await.status().is_success());
+
+    let post = get_only_post(&app).await;
+
+    assert!(post.text_html.contains("<em>emphasis</em>"));
+    for forbidden in ["<script", "alert(1)</", "javascript:", "onerror", "<iframe"] {
+        assert!(
+            !post.text_html.contains(forbidden),
+            "{} in {}",
+            forbidden,
+            post.text_html
+        );
+    }
+}
+
+#[tokio::test]
+async fn text_length_limit_applies_to_markdown_source() {
+    let app = spawn_app().await;
+    // Every `<` is escaped, so the rendered HTML is far longer than the limit.
+    let text = "a<".repeat(MAX_TEXT_LENGTH as usize / 2);
+
+    let response = app.create_post(&text, None).await;
+
+    assert!(response.status().is_success());
+    let post = get_only_post(&app).await;
+    assert_eq!(post.text, text);
+    assert!(post.text_html.len() > MAX_TEXT_LENGTH as usize);
+
+    let response = app.create_post(&format!("{}!", text), None).await;
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn fenced_code_blocks_are_highlighted_with_classes() {
+    let app = spawn_app().await;
+    let text = "Example:\n\n```rust\nfn main() {\n    let answer = 42; // the answer\n}\n```";
+    assert!(app.create_post(text, None).await.status().is_success());
+
+    let post = get_only_post(&app).await;
+
+    assert!(post
+        .text_html
+        .contains(r#"<pre><code class="language-rust">"#));
+    assert!(post
+        .text_html
+        .contains(r#"<span class="hl-storage hl-type hl-rust">let</span>"#));
+    assert!(post.text_html.contains(
+        r#"<span class="hl-constant hl-numeric hl-integer hl-decimal hl-rust">42</span>"#
+    ));
+    assert!(post.text_html.contains(r#"<span class="hl-comment "#));
+    // Indentation and line breaks of the code are kept.
+    assert!(post.text_html.contains("{</span>\n    <span"));
+    assert!(!post.text_html.contains("style="));
+}
+
+#[tokio::test]
+async fn code_without_a_known_language_is_escaped_and_not_highlighted() {
+    let app = spawn_app().await;
+    let text = "```nosuchlanguage\n<b>bold?</b>\n```\n\n```\nplain <i>text</i>\n```";
+    assert!(app.create_post(text, None).await.status().is_success());
+
+    let post = get_only_post(&app).await;
+
+    assert!(post.text_html.contains("&lt;b&gt;bold?&lt;/b&gt;"));
+    assert!(post.text_html.contains("plain &lt;i&gt;text&lt;/i&gt;"));
+    assert!(!post.text_html.contains("hl-"));
+}
+
+#[tokio::test]
+async fn only_highlighting_classes_are_kept_in_post_html() {
+    let app = spawn_app().await;
+    let text = r#"Styled <span class="post-text hl-keyword">text</span> <span class="image-placeholder">here</span>"#;
+    assert!(app.create_post(text, None).await.status().is_success());
+
+    let post = get_only_post(&app).await;
+
+    assert!(post
+        .text_html
+        .contains(r#"<span class="hl-keyword">text</span>"#));
+    assert!(post.text_html.contains("<span>here</span>"));
+}
@@ -0,0 +1,109 @@
+use std::fs;
+
+use crate::helpers::{create_database, create_temp_image_dir, get_image_asset};
+use jetbrains_web_app::{
+    configuration::{get_configuration, OrphanAction, SweeperSettings},
+    domain::get_post_by_id,
+    storage::local::LocalStorage,
+    sweeper::sweep_orphans,
+};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Version of the migration moving avatars from posts to user profiles.
+const USER_AVATARS_MIGRATION: i64 = 20241201000000;
+
+/// A new database with the migrations before `version` applied.
+async fn database_before(version: i64) -> PgPool {
+    let mut config = get_configuration()
+        .expect("Failed to read configuration")
+        .database;
+    config.db_name = Uuid::new_v4().to_string();
+    let pool = create_database(&config).await;
+
+    let mut migrator = sqlx::migrate!("./migrations");
+    migrator.migrations = migrator
+        .iter()
+        .filter(|migration| migration.version < version)
+        .cloned()
+        .collect::<Vec<_>>()
+        .into();
+    migrator
+        .run(&pool)
+        .await
+        .expect("Failed to run earlier migrations");
+
+    pool
+}
+
+async fn insert_post(pool: &PgPool, username: &str, user_id: Option<Uuid>, avatar: &str) -> Uuid {
+    let id = Uuid::new_v4();
+    sqlx::query(
+        "INSERT INTO blog_posts (id, text, username, user_id, user_avatar_path)
+        VALUES ($1, 'This is a sample post text.', $2, $3, $4)",
+    )
+    .bind(id)
+    .bind(username)
+    .bind(user_id)
+    .bind(avatar)
+    .execute(pool)
+    .await
+    .expect("Failed to insert post.");
+    id
+}
+
+#[tokio::test]
+async fn user_avatars_migration_keeps_avatars_of_posts_without_author() {
+    let pool = database_before(USER_AVATARS_MIGRATION).await;
+
+    let legacy_post = insert_post(&pool, "legacy_user", None, "legacy-avatar.png").await;
+    let user_id = Uuid::new_v4();
+    sqlx::query("INSERT INTO users (id, username, password_hash) VALUES ($1, 'author', 'hash')")
+        .bind(user_id)
+        .execute(&pool)
+        .await
+        .expect("Failed to insert user.");
+    let author_post = insert_post(&pool, "author", Some(user_id), "author-avatar.png").await;
+
+    sqlx::migrate!("./migrations")
+        .run(&pool)
+        .await
+        .expect("Failed to run remaining migrations");
+
+    let legacy_post = get_post_by_id(&pool, legacy_post)
+        .await
+        .expect("Failed to fetch post.")
+        .expect("Post should exist");
+    assert_eq!(
+        legacy_post.user_avatar_path.as_deref(),
+        Some("legacy-avatar.png")
+    );
+    let author_post = get_post_by_id(&pool, author_post)
+        .await
+        .expect("Failed to fetch post.")
+        .expect("Post should exist");
+    assert_eq!(
+        author_post.user_avatar_path.as_deref(),
+        Some("author-avatar.png")
+    );
+
+    // Both avatar files are still referenced, so the sweeper keeps them.
+    let upload_path = create_temp_image_dir();
+    for avatar in ["legacy-avatar.png", "author-avatar.png"] {
+        fs::write(
+            upload_path.join(avatar),
+            get_image_asset("jetbrains-logo.png"),
+        )
+        .expect("Failed to write avatar file.");
+    }
+    let settings = SweeperSettings {
+        interval_secs: 0,
+        grace_period_secs: 0,
+        action: OrphanAction::Delete,
+    };
+    let report = sweep_orphans(&pool, &LocalStorage::new(upload_path.clone()), &settings)
+        .await
+        .expect("Failed to sweep orphaned files.");
+    fs::remove_dir_all(&upload_path).expect("Failed to remove upload directory.");
+    assert!(report.orphans.is_empty());
+}
@@ -0,0 +1,187 @@
+use crate::helpers::{spawn_app, TestApp};
+use jetbrains_web_app::{
+    routes::openapi::{DOCS_ROUTE, OPENAPI_ROUTE},
+    startup::routes,
+};
+use reqwest::{header, StatusCode};
+use serde_json::Value;
+
+async fn get(app: &TestApp, path: &str) -> reqwest::Response {
+    app.api_client
+        .get(format!("{}{}", &app.address, path))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn get_document(app: &TestApp) -> Value {
+    let response = get(app, "/api/openapi.json").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    response
+        .json()
+        .await
+        .expect("Failed to parse OpenAPI document.")
+}
+
+#[tokio::test]
+async fn openapi_document_describes_the_api() {
+    let app = spawn_app().await;
+
+    let document = get_document(&app).await;
+
+    assert!(document["openapi"].as_str().unwrap().starts_with("3.1"));
+    assert!(document["info"].get("license").is_none());
+    for path in [
+        "/posts",
+        "/posts/{id}",
+        "/api/v1/posts",
+        "/api/v1/posts/{id}",
+        "/search",
+        "/tags/{tag}",
+        "/login",
+    ] {
+        assert!(document["paths"].get(path).is_some(), "{} is missing", path);
+    }
+
+    let create = &document["paths"]["/posts"]["post"];
+    let form = &create["requestBody"]["content"]["multipart/form-data"]["schema"];
+    assert_eq!(form["$ref"], "#/components/schemas/PostUpload");
+    let upload = &document["components"]["schemas"]["PostUpload"];
+    assert_eq!(upload["required"], serde_json::json!(["text"]));
+    assert!(upload["properties"]["image"].is_object());
+    assert!(create["responses"].get("303").is_some());
+    assert_eq!(
+        create["responses"]["400"]["content"]["application/problem+json"]["schema"]["$ref"],
+        "#/components/schemas/ProblemDetails"
+    );
+    assert_eq!(create["security"][0]["session"], serde_json::json!([]));
+
+    let created = &document["paths"]["/api/v1/posts"]["post"]["responses"]["201"];
+    assert!(created["headers"].get("Location").is_some());
+
+    let schemas = &document["components"]["schemas"];
+    for schema in [
+        "BlogPost",
+        "ImageVariant",
+        "ProblemDetails",
+        "FieldViolation",
+        "PostRequest",
+    ] {
+        assert!(schemas.get(schema).is_some(), "{} is missing", schema);
+    }
+    let post = &schemas["BlogPost"]["properties"];
+    assert!(post.get("text_html").is_some());
+    assert!(post.get("pending_image_key").is_none());
+    assert_eq!(
+        document["components"]["securitySchemes"]["session"],
+        serde_json::json!({ "type": "apiKey", "in": "cookie", "name": "session_id" })
+    );
+}
+
+#[tokio::test]
+async fn every_documented_operation_is_routed() {
+    let app = spawn_app().await;
+    let (_, image_path) = app.create_image_post().await;
+    let document = get_document(&app).await;
+    let client = reqwest::Client::new();
+
+    for (path, operations) in document["paths"].as_object().unwrap() {
+        let url = format!(
+            "{}{}",
+            &app.address,
+            path.replace("{id}", &uuid::Uuid::new_v4().to_string())
+                .replace("{tag}", "rust")
+                .replace("{username}", "valid_user")
+                .replace("{key}", &image_path)
+        );
+        for method in operations.as_object().unwrap().keys() {
+            let method: reqwest::Method = method.to_uppercase().parse().unwrap();
+            let response = client
+                .request(method.clone(), &url)
+                .send()
+                .await
+                .expect("Failed to execute request.");
+            let status = response.status();
+            let body = response.text().await.unwrap();
+            // The router answers unknown routes with an empty 404 and
+            // unknown methods with 405, the handlers never do.
+            assert_ne!(
+                status,
+                StatusCode::METHOD_NOT_ALLOWED,
+                "{} {}",
+                method,
+                path
+            );
+            assert!(
+                status != StatusCode::NOT_FOUND || !body.is_empty(),
+                "{} {} is not routed",
+                method,
+                path
+            );
+        }
+    }
+}
+
+#[tokio::test]
+async fn every_route_is_documented() {
+    let app = spawn_app().await;
+    let document = get_document(&app).await;
+
+    for (method, path) in routes().served() {
+        // The document and the page showing it are not part of the API.
+        if path == OPENAPI_ROUTE || path.starts_with(DOCS_ROUTE) {
+            continue;
+        }
+
+        let documented_path = path
+            .split('/')
+            .map(|segment| match segment.strip_prefix(':') {
+                Some(name) => format!("{{{}}}", name),
+                None => segment.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("/");
+        let operation =
+            &document["paths"][documented_path.as_str()][method.as_str().to_lowercase()];
+        assert!(
+            operation.is_object(),
+            "{} {} is not documented",
+            method,
+            path
+        );
+    }
+}
+
+#[tokio::test]
+async fn docs_page_is_served_without_other_hosts() {
+    let app = spawn_app().await;
+
+    let response = app
+        .api_client
+        .get(format!("{}/api/docs", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.url().path(), "/api/docs/");
+    let html = response.text().await.unwrap();
+    assert!(html.contains("swagger-ui"));
+    assert!(
+        !html.contains("https://"),
+        "Docs page loads from other hosts"
+    );
+
+    let response = get(&app, "/api/docs/swagger-initializer.js").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.text().await.unwrap().contains("/api/openapi.json"));
+
+    let response = get(&app, "/api/docs/swagger-ui-bundle.js").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers()[header::CONTENT_TYPE]
+        .to_str()
+        .unwrap()
+        .contains("javascript"));
+
+    let response = get(&app, "/api/docs/missing.js").await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
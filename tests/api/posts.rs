@@ -1,27 +1,28 @@
-use crate::helpers::{get_image_asset, spawn_app};
-use jetbrains_web_app::domain::BlogPost;
+use crate::helpers::{
+    get_image_asset, serve_image_asset, spawn_app, spawn_app_with, TestApp, TEST_BASE_URL,
+};
+use image::ImageDecoder;
+use jetbrains_web_app::domain::{get_post_by_id, BlogPost, MediaFormat};
 use reqwest::multipart;
-
-const JETBRAINS_PNG_LOGO_URL: &str = "https://w7.pngwing.com/pngs/101/125/png-transparent-intellij-idea-integrated-development-environment-computer-software-source-code-jetbrains-php-logo-angle-text-logo-thumbnail.png";
-const JETBRAINS_JPG_LOGO_URL: &str =
-    "https://upload.wikimedia.org/wikipedia/commons/e/e6/JetBrains_logo.jpg";
+use serde::Deserialize;
+use uuid::Uuid;
 
 #[tokio::test]
 async fn create_post_success_redirects_home_and_exists_in_database() {
     let app = spawn_app().await;
-    let client = reqwest::Client::new();
+    let client = &app.api_client;
     let image_name = "jetbrains-logo.png";
 
-    let image = get_image_asset(&image_name);
+    let image = get_image_asset(image_name);
+    let (_avatar_server, avatar_url) = serve_image_asset(image_name).await;
+    app.set_avatar(client, &avatar_url).await;
 
     let form = multipart::Form::new()
         .text("text", "This is a sample post text.")
-        .text("username", "valid_user")
-        .text("user_avatar_url", JETBRAINS_PNG_LOGO_URL)
         .part("image", multipart::Part::bytes(image).file_name(image_name));
 
     let response = client
-        .post(&format!("{}/posts", &app.address))
+        .post(format!("{}/posts", &app.address))
         .multipart(form)
         .send()
         .await
@@ -29,12 +30,12 @@ async fn create_post_success_redirects_home_and_exists_in_database() {
 
     assert!(response.status().is_success());
     assert_eq!(response.status().as_u16(), 200);
+    app.wait_for_jobs().await;
 
     let db_pool = &app.db_pool;
-    let post = sqlx::query_as!(
-        BlogPost,
+    let id = sqlx::query_scalar!(
         r#"
-        SELECT *
+        SELECT id
         FROM blog_posts
         WHERE username = $1
         "#,
@@ -43,6 +44,10 @@ async fn create_post_success_redirects_home_and_exists_in_database() {
     .fetch_one(db_pool)
     .await
     .expect("Failed to fetch post from database.");
+    let post = get_post_by_id(db_pool, id)
+        .await
+        .expect("Failed to fetch post from database.")
+        .expect("Post should exist");
 
     assert_eq!(post.text, "This is a sample post text.");
     assert_eq!(post.username, "valid_user");
@@ -50,15 +55,63 @@ async fn create_post_success_redirects_home_and_exists_in_database() {
     assert!(post.user_avatar_path.is_some());
 }
 
+#[tokio::test]
+async fn create_post_shows_placeholder_until_image_is_processed() {
+    let app = spawn_app_with(|c| c.application.jobs.workers = 0).await;
+    let id = app.submit_image_post().await;
+
+    let post = get_stored_post(&app, id).await.expect("Post should exist");
+    assert!(post.image_pending);
+    assert!(post.image_path.is_none());
+
+    let html = app
+        .api_client
+        .get(format!("{}/posts/{}", &app.address, id))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .text()
+        .await
+        .expect("Failed to read response text.");
+    assert!(html.contains("Processing image"));
+
+    let queued = sqlx::query_scalar!(r#"SELECT payload->>'kind' AS "kind!" FROM jobs"#)
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to fetch jobs from database.");
+    assert_eq!(queued, vec!["process_post_image"]);
+}
+
+#[tokio::test]
+async fn create_post_without_session_returns_401() {
+    let app = spawn_app().await;
+
+    let form = multipart::Form::new().text("text", "This is a sample post text.");
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/posts", &app.address))
+        .multipart(form)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 401);
+    let count = sqlx::query_scalar!("SELECT COUNT(*) FROM blog_posts")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to count posts.");
+    assert_eq!(count, Some(0));
+}
+
 #[tokio::test]
 async fn create_post_missing_text_returns_400() {
     let app = spawn_app().await;
-    let client = reqwest::Client::new();
+    let client = &app.api_client;
 
-    let form = multipart::Form::new().text("username", "valid_user");
+    let form = multipart::Form::new();
 
     let response = client
-        .post(&format!("{}/posts", &app.address))
+        .post(format!("{}/posts", &app.address))
         .multipart(form)
         .send()
         .await
@@ -68,16 +121,14 @@ async fn create_post_missing_text_returns_400() {
 }
 
 #[tokio::test]
-async fn create_post_invalid_username_length_returns_400() {
+async fn create_post_invalid_text_length_returns_400() {
     let app = spawn_app().await;
-    let client = reqwest::Client::new();
+    let client = &app.api_client;
 
-    let form = multipart::Form::new()
-        .text("text", "This is a sample post text.")
-        .text("username", "a");
+    let form = multipart::Form::new().text("text", "Short");
 
     let response = client
-        .post(&format!("{}/posts", &app.address))
+        .post(format!("{}/posts", &app.address))
         .multipart(form)
         .send()
         .await
@@ -88,20 +139,24 @@ async fn create_post_invalid_username_length_returns_400() {
         .text()
         .await
         .expect("Failed to read response text.");
-    assert!(error_message.contains("Username must be between 2 and 50 characters"));
+    assert!(error_message.contains("Text must be between 10 and 10,000 characters"));
 }
 
 #[tokio::test]
-async fn create_post_invalid_text_length_returns_400() {
+async fn create_post_invalid_image_type_returns_400() {
     let app = spawn_app().await;
-    let client = reqwest::Client::new();
+    let client = &app.api_client;
+
+    let image_name = "jetbrains-logo.bmp";
+
+    let image = get_image_asset(image_name);
 
     let form = multipart::Form::new()
-        .text("text", "Short")
-        .text("username", "valid_user");
+        .text("text", "This is a sample post text.")
+        .part("image", multipart::Part::bytes(image).file_name(image_name));
 
     let response = client
-        .post(&format!("{}/posts", &app.address))
+        .post(format!("{}/posts", &app.address))
         .multipart(form)
         .send()
         .await
@@ -112,25 +167,220 @@ async fn create_post_invalid_text_length_returns_400() {
         .text()
         .await
         .expect("Failed to read response text.");
-    assert!(error_message.contains("Text must be between 10 and 10,000 characters"));
+    assert!(error_message.contains("Supported types are PNG, JPEG, WebP, GIF"));
 }
 
 #[tokio::test]
-async fn create_post_invalid_image_type_returns_400() {
-    let app = spawn_app().await;
-    let client = reqwest::Client::new();
+async fn create_post_transcodes_accepted_formats_to_output_format() {
+    let app = spawn_app_with(|c| c.application.media.output_format = MediaFormat::Webp).await;
+
+    for image_name in [
+        "jetbrains-logo.png",
+        "jetbrains-logo.jpg",
+        "jetbrains-logo.webp",
+        "jetbrains-logo.gif",
+    ] {
+        // Misleading file names must not matter, only the contents are inspected.
+        let form = multipart::Form::new()
+            .text("text", "This is a sample post text.")
+            .part(
+                "image",
+                multipart::Part::bytes(get_image_asset(image_name)).file_name("image.png"),
+            );
+
+        let response = app
+            .api_client
+            .post(format!("{}/posts", &app.address))
+            .multipart(form)
+            .send()
+            .await
+            .expect("Failed to execute request.");
+
+        assert!(
+            response.status().is_success(),
+            "{} was rejected",
+            image_name
+        );
+    }
+    app.wait_for_jobs().await;
+
+    let image_paths = sqlx::query_scalar!(
+        "SELECT media.path FROM blog_posts JOIN media ON media.id = blog_posts.image_id"
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .expect("Failed to fetch image paths from database.");
+
+    assert_eq!(image_paths.len(), 4);
+    for image_path in image_paths {
+        assert!(image_path.ends_with(".webp"));
+
+        let stored =
+            std::fs::read(app.upload_path.join(&image_path)).expect("Stored image should exist");
+        assert_eq!(MediaFormat::detect(&stored), Some(MediaFormat::Webp));
+    }
+}
+
+/// Phone photos rotated by EXIF orientation 6, carrying a camera model and GPS position.
+const EXIF_FIXTURES: [&str; 3] = ["exif-rotated.jpg", "exif-rotated.png", "exif-rotated.webp"];
+
+async fn stored_image_files(app: &TestApp) -> Vec<String> {
+    sqlx::query_scalar!(
+        r#"SELECT variant_path AS "path!" FROM image_variants ORDER BY variant_path"#
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .expect("Failed to fetch image files from database.")
+}
+
+fn assert_metadata_stripped(path: &std::path::Path) {
+    let stored = std::fs::read(path).expect("Stored image should exist");
+    assert!(
+        !stored.windows(12).any(|window| window == b"Secret Phone"),
+        "{} contains EXIF data",
+        path.display()
+    );
+
+    let mut decoder = image::ImageReader::open(path)
+        .and_then(|reader| reader.with_guessed_format())
+        .expect("Failed to open stored image")
+        .into_decoder()
+        .expect("Failed to decode stored image");
+    assert_eq!(decoder.exif_metadata().unwrap(), None);
+    assert_eq!(decoder.icc_profile().unwrap(), None);
+}
 
-    let image_name = "jetbrains-logo-wrong-format.jpg";
+#[tokio::test]
+async fn create_post_applies_exif_orientation_and_strips_metadata() {
+    let app = spawn_app_with(|c| c.application.media.image_widths = vec![100]).await;
+
+    for image_name in EXIF_FIXTURES {
+        let upload = get_image_asset(image_name);
+        let form = multipart::Form::new()
+            .text("text", "This is a sample post text.")
+            .part(
+                "image",
+                multipart::Part::bytes(upload.clone()).file_name(image_name),
+            );
+        let response = app
+            .api_client
+            .post(format!("{}/posts", &app.address))
+            .multipart(form)
+            .send()
+            .await
+            .expect("Failed to execute request.");
+        assert!(
+            response.status().is_success(),
+            "{} was rejected",
+            image_name
+        );
+        app.wait_for_jobs().await;
+
+        let stored_path = sqlx::query_scalar!(
+            "SELECT media.path FROM blog_posts JOIN media ON media.id = blog_posts.image_id ORDER BY published_at DESC LIMIT 1"
+        )
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch image path from database.");
+
+        // Decoding ignores the orientation, the stored image is turned by 90 degrees.
+        let raw = image::load_from_memory(&upload).expect("Fixture should decode");
+        let stored =
+            image::open(app.upload_path.join(&stored_path)).expect("Image should be stored");
+        assert_eq!(
+            (stored.width(), stored.height()),
+            (raw.height(), raw.width()),
+            "{} was not rotated",
+            image_name
+        );
+    }
+
+    let files = stored_image_files(&app).await;
+    assert_eq!(files.len(), 2 * EXIF_FIXTURES.len());
+    for file in files {
+        assert_metadata_stripped(&app.upload_path.join(file));
+    }
+}
 
-    let image = get_image_asset(&image_name);
+#[tokio::test]
+async fn metadata_is_stripped_for_every_output_format() {
+    // PNG is covered above and GIF has no place for EXIF data.
+    for format in [MediaFormat::Jpeg, MediaFormat::Webp] {
+        let app = spawn_app_with(|c| {
+            c.application.media.output_format = format;
+            c.application.media.image_widths = vec![100];
+        })
+        .await;
+        let form = multipart::Form::new()
+            .text("text", "This is a sample post text.")
+            .part(
+                "image",
+                multipart::Part::bytes(get_image_asset(EXIF_FIXTURES[0])).file_name("photo.jpg"),
+            );
+        let response = app
+            .api_client
+            .post(format!("{}/posts", &app.address))
+            .multipart(form)
+            .send()
+            .await
+            .expect("Failed to execute request.");
+        assert!(response.status().is_success());
+        app.wait_for_jobs().await;
+
+        let files = stored_image_files(&app).await;
+        assert!(!files.is_empty());
+        for file in files {
+            assert_metadata_stripped(&app.upload_path.join(file));
+        }
+    }
+}
+
+#[tokio::test]
+async fn create_post_with_format_outside_allow_list_returns_400() {
+    let app =
+        spawn_app_with(|c| c.application.media.allowed_formats = vec![MediaFormat::Png]).await;
+    let image_name = "jetbrains-logo.jpg";
 
     let form = multipart::Form::new()
         .text("text", "This is a sample post text.")
-        .text("username", "valid_user")
-        .part("image", multipart::Part::bytes(image).file_name(image_name));
+        .part(
+            "image",
+            multipart::Part::bytes(get_image_asset(image_name)).file_name(image_name),
+        );
 
-    let response = client
-        .post(&format!("{}/posts", &app.address))
+    let response = app
+        .api_client
+        .post(format!("{}/posts", &app.address))
+        .multipart(form)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 400);
+    let count = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM blog_posts"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to count posts.");
+    assert_eq!(count, 0);
+}
+
+#[tokio::test]
+async fn create_post_rejects_unsupported_format_from_first_bytes() {
+    let app = spawn_app_with(|c| c.application.media.max_image_size = 1024).await;
+    // Over the size limit, but refused for its format before the limit is reached.
+    let mut image = get_image_asset("jetbrains-logo.bmp");
+    image.resize(4096, 0);
+
+    let form = multipart::Form::new()
+        .text("text", "This is a sample post text.")
+        .part(
+            "image",
+            multipart::Part::bytes(image).file_name("image.png"),
+        );
+
+    let response = app
+        .api_client
+        .post(format!("{}/posts", &app.address))
         .multipart(form)
         .send()
         .await
@@ -140,26 +390,840 @@ async fn create_post_invalid_image_type_returns_400() {
 }
 
 #[tokio::test]
-async fn create_post_invalid_avatar_type_returns_400() {
+async fn create_post_with_undecodable_image_returns_400() {
     let app = spawn_app().await;
-    let client = reqwest::Client::new();
+    // A PNG signature followed by a truncated header.
+    let mut image = get_image_asset("jetbrains-logo.png");
+    image.truncate(12);
 
+    let form = multipart::Form::new()
+        .text("text", "This is a sample post text.")
+        .part(
+            "image",
+            multipart::Part::bytes(image).file_name("broken.png"),
+        );
+
+    let response = app
+        .api_client
+        .post(format!("{}/posts", &app.address))
+        .multipart(form)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 400);
+    let error_message = response
+        .text()
+        .await
+        .expect("Failed to read response text.");
+    assert!(error_message.contains("Invalid image"));
+}
+
+#[tokio::test]
+async fn create_post_with_too_many_pixels_returns_400() {
+    let app = spawn_app_with(|c| c.application.media.max_image_pixels = 10_000).await;
+
+    let form = multipart::Form::new()
+        .text("text", "This is a sample post text.")
+        .part(
+            "image",
+            multipart::Part::bytes(get_image_asset("jetbrains-logo.png"))
+                .file_name("jetbrains-logo.png"),
+        );
+
+    let response = app
+        .api_client
+        .post(format!("{}/posts", &app.address))
+        .multipart(form)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 400);
+    let error_message = response
+        .text()
+        .await
+        .expect("Failed to read response text.");
+    assert!(error_message.contains("Image is too large: 300x250 pixels"));
+}
+
+#[tokio::test]
+async fn create_post_while_media_pool_is_saturated_returns_503() {
+    let app = spawn_app_with(|c| c.application.media.max_concurrency = 0).await;
     let image_name = "jetbrains-logo.png";
 
-    let image = get_image_asset(&image_name);
+    let form = multipart::Form::new()
+        .text("text", "This is a sample post text.")
+        .part(
+            "image",
+            multipart::Part::bytes(get_image_asset(image_name)).file_name(image_name),
+        );
+
+    let response = app
+        .api_client
+        .post(format!("{}/posts", &app.address))
+        .multipart(form)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 503);
+    let count = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM blog_posts"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to count posts.");
+    assert_eq!(count, 0);
+}
+
+#[tokio::test]
+async fn create_post_with_image_over_size_limit_returns_413() {
+    let app = spawn_app_with(|c| c.application.media.max_image_size = 1024).await;
+    let image_name = "jetbrains-logo.png";
 
     let form = multipart::Form::new()
         .text("text", "This is a sample post text.")
-        .text("username", "valid_user")
-        .text("user_avatar_url", JETBRAINS_JPG_LOGO_URL)
-        .part("image", multipart::Part::bytes(image).file_name(image_name));
+        .part(
+            "image",
+            multipart::Part::bytes(get_image_asset(image_name)).file_name(image_name),
+        );
 
-    let response = client
-        .post(&format!("{}/posts", &app.address))
+    let response = app
+        .api_client
+        .post(format!("{}/posts", &app.address))
+        .multipart(form)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 413);
+    let error_message = response
+        .text()
+        .await
+        .expect("Failed to read response text.");
+    assert!(error_message.contains("Maximum size is 1KB"));
+    assert_eq!(
+        std::fs::read_dir(&app.upload_path)
+            .expect("Failed to read upload dir")
+            .count(),
+        0
+    );
+}
+
+#[tokio::test]
+async fn create_post_with_body_over_request_limit_returns_413() {
+    let app = spawn_app_with(|c| c.application.max_request_size = 2048).await;
+    let image_name = "jetbrains-logo.png";
+
+    let form = multipart::Form::new()
+        .text("text", "This is a sample post text.")
+        .part(
+            "image",
+            multipart::Part::bytes(get_image_asset(image_name)).file_name(image_name),
+        );
+
+    let response = app
+        .api_client
+        .post(format!("{}/posts", &app.address))
+        .multipart(form)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 413);
+    let count = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM blog_posts"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to count posts.");
+    assert_eq!(count, 0);
+}
+
+#[tokio::test]
+async fn get_post_returns_html_page_with_open_graph_tags() {
+    let app = spawn_app().await;
+    app.create_text_post("Permalink **post** text.\nSecond line.")
+        .await;
+
+    let id = sqlx::query_scalar!("SELECT id FROM blog_posts")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch post id from database.");
+
+    let response = reqwest::Client::new()
+        .get(format!("{}/posts/{}", &app.address, id))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 200);
+    let html = response
+        .text()
+        .await
+        .expect("Failed to read response text.");
+    assert!(html.contains(r#"<meta property="og:title" content="Permalink post text.">"#));
+    assert!(html.contains(
+        r#"<meta property="og:description" content="Permalink post text. Second line.">"#
+    ));
+    assert!(html.contains(&format!(
+        r#"<meta property="og:url" content="{}/posts/{}">"#,
+        TEST_BASE_URL, id
+    )));
+    assert!(html.contains("valid_user"));
+}
+
+#[tokio::test]
+async fn get_post_returns_json_when_requested() {
+    let app = spawn_app().await;
+    app.create_text_post("This is a sample post text.").await;
+
+    let id = sqlx::query_scalar!("SELECT id FROM blog_posts")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch post id from database.");
+
+    let response = reqwest::Client::new()
+        .get(format!("{}/posts/{}", &app.address, id))
+        .header("Accept", "application/json")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 200);
+    let post: BlogPost = response.json().await.expect("Failed to parse post JSON.");
+    assert_eq!(post.id, id);
+    assert_eq!(post.text, "This is a sample post text.");
+    assert_eq!(post.username, "valid_user");
+}
+
+#[tokio::test]
+async fn create_post_stores_resized_variants_and_emits_srcset() {
+    let app = spawn_app_with(|c| c.application.media.image_widths = vec![100, 200, 640]).await;
+    let (id, image_path) = app.create_image_post().await;
+
+    let post: BlogPost = reqwest::Client::new()
+        .get(format!("{}/posts/{}", &app.address, id))
+        .header("Accept", "application/json")
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .json()
+        .await
+        .expect("Failed to parse post JSON.");
+
+    // The 300 px wide original is not scaled up to 640 px.
+    let widths: Vec<i32> = post.image_variants.iter().map(|v| v.width).collect();
+    assert_eq!(widths, vec![100, 200, 300]);
+    for variant in post.image_variants.iter() {
+        let stored =
+            image::open(app.upload_path.join(&variant.path)).expect("Variant should be stored");
+        assert_eq!(stored.width() as i32, variant.width);
+    }
+
+    let html = reqwest::Client::new()
+        .get(format!("{}/posts/{}", &app.address, id))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .text()
+        .await
+        .expect("Failed to read response text.");
+    let small_variant = image_path.replace(".png", "_100.png");
+    assert!(html.contains(&format!(r#"srcset="/uploads/{} 100w"#, small_variant)));
+    assert!(html.contains(&format!("/uploads/{} 300w", image_path)));
+    assert!(html.contains(&format!(
+        r#"<meta property="og:image" content="{}/uploads/{}">"#,
+        TEST_BASE_URL, image_path
+    )));
+}
+
+#[tokio::test]
+async fn delete_post_removes_image_variants() {
+    let app = spawn_app_with(|c| c.application.media.image_widths = vec![100, 200]).await;
+    let (id, _) = app.create_image_post().await;
+
+    let response = app
+        .api_client
+        .delete(format!("{}/posts/{}", &app.address, id))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 204);
+    app.wait_for_jobs().await;
+    assert_eq!(
+        std::fs::read_dir(&app.upload_path)
+            .expect("Failed to read upload dir")
+            .count(),
+        0
+    );
+    let variants = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM image_variants"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to count image variants.");
+    assert_eq!(variants, 0);
+}
+
+#[tokio::test]
+async fn get_post_unknown_id_returns_404() {
+    let app = spawn_app().await;
+
+    let response = reqwest::Client::new()
+        .get(format!("{}/posts/{}", &app.address, Uuid::new_v4()))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+async fn get_stored_post(app: &TestApp, id: Uuid) -> Option<BlogPost> {
+    get_post_by_id(&app.db_pool, id)
+        .await
+        .expect("Failed to fetch post from database.")
+}
+
+#[tokio::test]
+async fn put_post_by_author_replaces_text_and_removes_image() {
+    let app = spawn_app().await;
+    let (id, image_path) = app.create_image_post().await;
+
+    let form = multipart::Form::new().text("text", "This is the replaced post text.");
+    let response = app
+        .api_client
+        .put(format!("{}/posts/{}", &app.address, id))
+        .multipart(form)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 200);
+    let post = get_stored_post(&app, id).await.expect("Post should exist");
+    assert_eq!(post.text, "This is the replaced post text.");
+    assert!(post.image_path.is_none());
+    app.wait_for_jobs().await;
+    assert!(!app.upload_path.join(image_path).exists());
+}
+
+#[tokio::test]
+async fn patch_post_by_author_keeps_fields_not_in_form() {
+    let app = spawn_app().await;
+    let (id, image_path) = app.create_image_post().await;
+
+    let form = multipart::Form::new().text("text", "This is the patched post text.");
+    let response = app
+        .api_client
+        .patch(format!("{}/posts/{}", &app.address, id))
+        .multipart(form)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 200);
+    let post = get_stored_post(&app, id).await.expect("Post should exist");
+    assert_eq!(post.text, "This is the patched post text.");
+    assert_eq!(post.image_path.as_deref(), Some(image_path.as_str()));
+    assert!(app.upload_path.join(image_path).exists());
+}
+
+#[tokio::test]
+async fn patch_post_with_new_image_replaces_stored_file() {
+    let app = spawn_app().await;
+    let (id, old_image_path) = app.create_image_post().await;
+
+    let image_name = "jetbrains-logo.jpg";
+    let form = multipart::Form::new().part(
+        "image",
+        multipart::Part::bytes(get_image_asset(image_name)).file_name(image_name),
+    );
+    let response = app
+        .api_client
+        .patch(format!("{}/posts/{}", &app.address, id))
+        .multipart(form)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 200);
+    app.wait_for_jobs().await;
+    let post = get_stored_post(&app, id).await.expect("Post should exist");
+    let new_image_path = post.image_path.expect("Post should have an image");
+    assert_ne!(new_image_path, old_image_path);
+    assert!(app.upload_path.join(new_image_path).exists());
+    assert!(!app.upload_path.join(old_image_path).exists());
+}
+
+#[tokio::test]
+async fn patch_post_with_unprocessable_image_keeps_previous_image() {
+    let app = spawn_app().await;
+    let (id, image_path) = app.create_image_post().await;
+
+    // The header is intact, so the upload is accepted, but the pixels are cut off.
+    let mut truncated = get_image_asset("jetbrains-logo.png");
+    truncated.truncate(truncated.len() / 2);
+    let form = multipart::Form::new().part(
+        "image",
+        multipart::Part::bytes(truncated).file_name("truncated.png"),
+    );
+    let response = app
+        .api_client
+        .patch(format!("{}/posts/{}", &app.address, id))
+        .multipart(form)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 200);
+    app.wait_for_jobs().await;
+    let post = get_stored_post(&app, id).await.expect("Post should exist");
+    assert!(!post.image_pending);
+    assert_eq!(post.image_path.as_deref(), Some(image_path.as_str()));
+    assert!(app.upload_path.join(image_path).exists());
+}
+
+#[tokio::test]
+async fn patch_post_with_same_image_keeps_stored_file() {
+    let app = spawn_app().await;
+    let (id, image_path) = app.create_image_post().await;
+
+    let image_name = "jetbrains-logo.png";
+    let form = multipart::Form::new().part(
+        "image",
+        multipart::Part::bytes(get_image_asset(image_name)).file_name(image_name),
+    );
+    let response = app
+        .api_client
+        .patch(format!("{}/posts/{}", &app.address, id))
+        .multipart(form)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 200);
+    app.wait_for_jobs().await;
+    let post = get_stored_post(&app, id).await.expect("Post should exist");
+    assert_eq!(post.image_path.as_deref(), Some(image_path.as_str()));
+    assert!(app.upload_path.join(image_path).exists());
+}
+
+#[tokio::test]
+async fn identical_images_share_one_stored_file_until_last_post_is_deleted() {
+    let app = spawn_app().await;
+    let (first_id, image_path) = app.create_image_post().await;
+
+    let image_name = "jetbrains-logo.png";
+    let form = multipart::Form::new()
+        .text("text", "This post reuses the same image.")
+        .part(
+            "image",
+            multipart::Part::bytes(get_image_asset(image_name)).file_name("copy.png"),
+        );
+    let response = app
+        .api_client
+        .post(format!("{}/posts", &app.address))
+        .multipart(form)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert!(response.status().is_success());
+    app.wait_for_jobs().await;
+
+    let media = sqlx::query!("SELECT path, ref_count FROM media")
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to fetch media from database.");
+    assert_eq!(media.len(), 1);
+    assert_eq!(media[0].path, image_path);
+    assert_eq!(media[0].ref_count, 2);
+    assert_eq!(
+        std::fs::read_dir(&app.upload_path)
+            .expect("Failed to read upload dir")
+            .count(),
+        1
+    );
+
+    let delete_post = |id: Uuid| {
+        app.api_client
+            .delete(format!("{}/posts/{}", &app.address, id))
+            .send()
+    };
+    let second_id = sqlx::query_scalar!("SELECT id FROM blog_posts WHERE id <> $1", first_id)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch post id from database.");
+
+    delete_post(first_id)
+        .await
+        .expect("Failed to execute request.");
+    app.wait_for_jobs().await;
+    assert!(app.upload_path.join(&image_path).exists());
+
+    delete_post(second_id)
+        .await
+        .expect("Failed to execute request.");
+    app.wait_for_jobs().await;
+    assert!(!app.upload_path.join(&image_path).exists());
+    let remaining = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM media"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to count media.");
+    assert_eq!(remaining, 0);
+}
+
+#[tokio::test]
+async fn image_released_and_stored_again_at_the_same_time_stays_stored() {
+    let app = spawn_app().await;
+
+    for _ in 0..5 {
+        let (released_id, _) = app.create_image_post().await;
+
+        // The last reference is dropped while another post's job stores the
+        // same image again.
+        let (response, reacquired_id) = tokio::join!(
+            app.api_client
+                .delete(format!("{}/posts/{}", &app.address, released_id))
+                .send(),
+            app.submit_image_post(),
+        );
+        assert_eq!(
+            response
+                .expect("Failed to execute request.")
+                .status()
+                .as_u16(),
+            204
+        );
+        app.wait_for_jobs().await;
+
+        let post = get_stored_post(&app, reacquired_id)
+            .await
+            .expect("Post should exist");
+        let image_path = post.image_path.expect("Post should have an image");
+        assert!(app.upload_path.join(&image_path).exists());
+        for variant in post.image_variants.iter() {
+            assert!(app.upload_path.join(&variant.path).exists());
+        }
+
+        app.api_client
+            .delete(format!("{}/posts/{}", &app.address, reacquired_id))
+            .send()
+            .await
+            .expect("Failed to execute request.");
+        app.wait_for_jobs().await;
+    }
+}
+
+#[tokio::test]
+async fn update_post_with_invalid_text_returns_400() {
+    let app = spawn_app().await;
+    let (id, _) = app.create_image_post().await;
+
+    let form = multipart::Form::new().text("text", "Short");
+    let response = app
+        .api_client
+        .patch(format!("{}/posts/{}", &app.address, id))
+        .multipart(form)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 400);
+    let post = get_stored_post(&app, id).await.expect("Post should exist");
+    assert_eq!(post.text, "This is a sample post text.");
+}
+
+#[tokio::test]
+async fn update_post_by_other_user_returns_403() {
+    let app = spawn_app().await;
+    let (id, _) = app.create_image_post().await;
+
+    let form = multipart::Form::new().text("text", "This is a hijacked post text.");
+    let response = app
+        .logged_in_client("other_user")
+        .await
+        .put(format!("{}/posts/{}", &app.address, id))
         .multipart(form)
         .send()
         .await
         .expect("Failed to execute request.");
 
+    assert_eq!(response.status().as_u16(), 403);
+    let post = get_stored_post(&app, id).await.expect("Post should exist");
+    assert_eq!(post.text, "This is a sample post text.");
+}
+
+#[tokio::test]
+async fn update_post_by_other_user_with_image_leaves_no_upload() {
+    let app = spawn_app().await;
+    let (id, _) = app.create_image_post().await;
+    let stored_files = || {
+        std::fs::read_dir(&app.upload_path)
+            .expect("Failed to read upload dir")
+            .count()
+    };
+    let before = stored_files();
+
+    let form = multipart::Form::new()
+        .text("text", "This is a hijacked post text.")
+        .part(
+            "image",
+            multipart::Part::bytes(get_image_asset("jetbrains-logo.jpg"))
+                .file_name("jetbrains-logo.jpg"),
+        );
+    let response = app
+        .logged_in_client("other_user")
+        .await
+        .put(format!("{}/posts/{}", &app.address, id))
+        .multipart(form)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 403);
+    // The refused upload is removed in the background.
+    for _ in 0..50 {
+        if stored_files() == before {
+            return;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    panic!("Refused upload was not removed");
+}
+
+#[tokio::test]
+async fn update_post_without_session_returns_401() {
+    let app = spawn_app().await;
+    let (id, _) = app.create_image_post().await;
+
+    let form = multipart::Form::new().text("text", "This is a hijacked post text.");
+    let response = reqwest::Client::new()
+        .put(format!("{}/posts/{}", &app.address, id))
+        .multipart(form)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn update_post_without_author_account_returns_403() {
+    let app = spawn_app().await;
+    let id = Uuid::new_v4();
+    sqlx::query!(
+        "INSERT INTO blog_posts (id, text, text_html, username) VALUES ($1, $2, $3, $4)",
+        id,
+        "This post predates user accounts.",
+        "<p>This post predates user accounts.</p>",
+        "valid_user"
+    )
+    .execute(&app.db_pool)
+    .await
+    .expect("Failed to insert post.");
+
+    let form = multipart::Form::new().text("text", "This is a hijacked post text.");
+    let response = app
+        .api_client
+        .put(format!("{}/posts/{}", &app.address, id))
+        .multipart(form)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn delete_post_by_author_removes_post_and_files() {
+    let app = spawn_app().await;
+    let (id, image_path) = app.create_image_post().await;
+
+    let response = app
+        .api_client
+        .delete(format!("{}/posts/{}", &app.address, id))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 204);
+    assert!(get_stored_post(&app, id).await.is_none());
+    app.wait_for_jobs().await;
+    assert!(!app.upload_path.join(image_path).exists());
+}
+
+#[tokio::test]
+async fn delete_post_by_other_user_returns_403() {
+    let app = spawn_app().await;
+    let (id, image_path) = app.create_image_post().await;
+
+    let response = app
+        .logged_in_client("other_user")
+        .await
+        .delete(format!("{}/posts/{}", &app.address, id))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 403);
+    assert!(get_stored_post(&app, id).await.is_some());
+    assert!(app.upload_path.join(image_path).exists());
+}
+
+#[tokio::test]
+async fn delete_unknown_post_returns_404() {
+    let app = spawn_app().await;
+
+    let response = app
+        .api_client
+        .delete(format!("{}/posts/{}", &app.address, Uuid::new_v4()))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn post_page_shows_edit_and_delete_forms_only_to_author() {
+    let app = spawn_app().await;
+    let (id, _) = app.create_image_post().await;
+    let url = format!("{}/posts/{}", &app.address, id);
+    let edit_form = format!(r#"action="/posts/{}/edit""#, id);
+    let delete_form = format!(r#"action="/posts/{}/delete""#, id);
+
+    let html = app
+        .api_client
+        .get(&url)
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html.contains(&edit_form));
+    assert!(html.contains(&delete_form));
+
+    for client in [
+        app.logged_in_client("other_user").await,
+        reqwest::Client::new(),
+    ] {
+        let html = client.get(&url).send().await.unwrap().text().await.unwrap();
+        assert!(!html.contains(&edit_form));
+        assert!(!html.contains(&delete_form));
+    }
+}
+
+#[tokio::test]
+async fn edit_and_delete_forms_change_the_post() {
+    let app = spawn_app().await;
+    let (id, image_path) = app.create_image_post().await;
+
+    let form = multipart::Form::new()
+        .text("text", "This is the edited post text.")
+        .text("tags", "edited");
+    let response = app
+        .api_client
+        .post(format!("{}/posts/{}/edit", &app.address, id))
+        .multipart(form)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.url().path(), format!("/posts/{}", id));
+    let post = get_stored_post(&app, id).await.expect("Post should exist");
+    assert_eq!(post.text, "This is the edited post text.");
+    assert_eq!(post.tags, vec!["edited"]);
+    assert_eq!(post.image_path.as_deref(), Some(image_path.as_str()));
+
+    let response = app
+        .logged_in_client("other_user")
+        .await
+        .post(format!("{}/posts/{}/delete", &app.address, id))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 403);
+
+    let response = app
+        .api_client
+        .post(format!("{}/posts/{}/delete", &app.address, id))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.url().path(), "/home");
+    assert!(get_stored_post(&app, id).await.is_none());
+    app.wait_for_jobs().await;
+    assert!(!app.upload_path.join(image_path).exists());
+}
+
+#[derive(Debug, Deserialize)]
+struct PostsPage {
+    items: Vec<BlogPost>,
+    older: Option<String>,
+    newer: Option<String>,
+}
+
+async fn get_posts_page(app: &TestApp, query: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .get(format!("{}/posts?{}", &app.address, query))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+#[tokio::test]
+async fn list_posts_paginates_with_cursors() {
+    let app = spawn_app().await;
+    for i in 1..=3 {
+        app.create_text_post(&format!("This is sample post number {}.", i))
+            .await;
+    }
+
+    let first: PostsPage = get_posts_page(&app, "limit=2")
+        .await
+        .json()
+        .await
+        .expect("Failed to parse page JSON.");
+    let texts: Vec<_> = first.items.iter().map(|post| post.text.as_str()).collect();
+    assert_eq!(
+        texts,
+        [
+            "This is sample post number 3.",
+            "This is sample post number 2."
+        ]
+    );
+    assert!(first.newer.is_none());
+    let older = first.older.expect("First page should link to older posts");
+
+    let second: PostsPage = get_posts_page(&app, &format!("before={}&limit=2", older))
+        .await
+        .json()
+        .await
+        .expect("Failed to parse page JSON.");
+    let texts: Vec<_> = second.items.iter().map(|post| post.text.as_str()).collect();
+    assert_eq!(texts, ["This is sample post number 1."]);
+    assert!(second.older.is_none());
+    let newer = second
+        .newer
+        .expect("Second page should link to newer posts");
+
+    let back: PostsPage = get_posts_page(&app, &format!("after={}&limit=2", newer))
+        .await
+        .json()
+        .await
+        .expect("Failed to parse page JSON.");
+    let ids: Vec<_> = back.items.iter().map(|post| post.id).collect();
+    let first_ids: Vec<_> = first.items.iter().map(|post| post.id).collect();
+    assert_eq!(ids, first_ids);
+    assert!(back.newer.is_none());
+    assert!(back.older.is_some());
+}
+
+#[tokio::test]
+async fn list_posts_with_invalid_cursor_returns_400() {
+    let app = spawn_app().await;
+
+    let response = get_posts_page(&app, "before=not-a-cursor").await;
+
     assert_eq!(response.status().as_u16(), 400);
 }
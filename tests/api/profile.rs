@@ -0,0 +1,573 @@
+use std::time::Duration;
+
+use crate::helpers::{get_image_asset, serve_image_asset, spawn_app, spawn_app_with, TestApp};
+use jetbrains_web_app::domain::BlogPost;
+use serde::Deserialize;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use wiremock::{
+    matchers::{method, path},
+    Mock, MockServer, ResponseTemplate,
+};
+
+#[derive(Debug, Deserialize)]
+struct PostsPage {
+    items: Vec<BlogPost>,
+}
+
+async fn get_avatar(app: &TestApp) -> (Option<String>, Option<String>) {
+    let avatar = sqlx::query!(
+        "SELECT avatar_path, avatar_url FROM users WHERE username = $1",
+        "valid_user"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to fetch user from database.");
+
+    (avatar.avatar_path, avatar.avatar_url)
+}
+
+async fn get_avatar_error(app: &TestApp) -> String {
+    sqlx::query_scalar!(
+        "SELECT avatar_error FROM users WHERE username = $1",
+        "valid_user"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to fetch user from database.")
+    .expect("Avatar error should be recorded")
+}
+
+#[tokio::test]
+async fn set_avatar_is_shown_on_all_posts_of_user() {
+    let app = spawn_app().await;
+    let (_avatar_server, avatar_url) = serve_image_asset("jetbrains-logo.png").await;
+
+    let response = app.set_avatar(&app.api_client, &avatar_url).await;
+    assert_eq!(response.status().as_u16(), 200);
+    app.wait_for_jobs().await;
+
+    app.create_text_post("This is the first sample post.").await;
+    app.create_text_post("This is the second sample post.")
+        .await;
+
+    let (avatar_path, _) = get_avatar(&app).await;
+    let avatar_path = avatar_path.expect("User should have an avatar");
+    assert!(app.upload_path.join(&avatar_path).exists());
+
+    let page: PostsPage = app
+        .api_client
+        .get(format!("{}/posts", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .json()
+        .await
+        .expect("Failed to parse page JSON.");
+    // Posts show the square crop rather than the full-size avatar.
+    let avatar_crop = avatar_path.replace(".png", "_80x80.png");
+    assert!(app.upload_path.join(&avatar_crop).exists());
+    assert_eq!(page.items.len(), 2);
+    for post in page.items {
+        assert_eq!(post.user_avatar_path.as_deref(), Some(avatar_crop.as_str()));
+    }
+}
+
+#[tokio::test]
+async fn set_same_avatar_url_downloads_only_once() {
+    let app = spawn_app().await;
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/avatar.png"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_bytes(get_image_asset("jetbrains-logo.png")),
+        )
+        .expect(1)
+        .mount(&server)
+        .await;
+    let avatar_url = format!("{}/avatar.png", server.uri());
+
+    app.set_avatar(&app.api_client, &avatar_url).await;
+    app.wait_for_jobs().await;
+    let (first_path, _) = get_avatar(&app).await;
+    app.set_avatar(&app.api_client, &avatar_url).await;
+    app.wait_for_jobs().await;
+    let (second_path, _) = get_avatar(&app).await;
+
+    assert!(first_path.is_some());
+    assert_eq!(first_path, second_path);
+}
+
+#[tokio::test]
+async fn change_avatar_removes_previous_file() {
+    let app = spawn_app().await;
+    let (_first_server, first_url) = serve_image_asset("jetbrains-logo.png").await;
+    let (_second_server, second_url) = serve_image_asset("jetbrains-logo.png").await;
+
+    app.set_avatar(&app.api_client, &first_url).await;
+    app.wait_for_jobs().await;
+    let (first_path, _) = get_avatar(&app).await;
+    let first_path = first_path.expect("User should have an avatar");
+
+    app.set_avatar(&app.api_client, &second_url).await;
+    app.wait_for_jobs().await;
+    let (second_path, stored_url) = get_avatar(&app).await;
+    let second_path = second_path.expect("User should have an avatar");
+
+    assert_ne!(first_path, second_path);
+    assert_eq!(stored_url, Some(second_url));
+    assert!(!app.upload_path.join(first_path).exists());
+    assert!(app.upload_path.join(second_path).exists());
+}
+
+#[tokio::test]
+async fn empty_avatar_url_removes_avatar() {
+    let app = spawn_app().await;
+    let (_avatar_server, avatar_url) = serve_image_asset("jetbrains-logo.png").await;
+
+    app.set_avatar(&app.api_client, &avatar_url).await;
+    app.wait_for_jobs().await;
+    let (avatar_path, _) = get_avatar(&app).await;
+    let avatar_path = avatar_path.expect("User should have an avatar");
+
+    let response = app.set_avatar(&app.api_client, "").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(get_avatar(&app).await, (None, None));
+    app.wait_for_jobs().await;
+    assert!(!app.upload_path.join(avatar_path).exists());
+}
+
+#[tokio::test]
+async fn set_avatar_with_invalid_type_records_error() {
+    let app = spawn_app().await;
+    let (_avatar_server, avatar_url) = serve_image_asset("jetbrains-logo.bmp").await;
+
+    let response = app.set_avatar(&app.api_client, &avatar_url).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    app.wait_for_jobs().await;
+    assert_eq!(get_avatar(&app).await, (None, None));
+    assert!(get_avatar_error(&app).await.contains("Invalid file type"));
+}
+
+#[tokio::test]
+async fn set_avatar_detects_format_from_contents_not_url() {
+    let app = spawn_app().await;
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/avatar"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_bytes(get_image_asset("jetbrains-logo.jpg")),
+        )
+        .mount(&server)
+        .await;
+
+    let response = app
+        .set_avatar(&app.api_client, &format!("{}/avatar", server.uri()))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    app.wait_for_jobs().await;
+    let (avatar_path, _) = get_avatar(&app).await;
+    let avatar_path = avatar_path.expect("User should have an avatar");
+    assert!(avatar_path.ends_with(".png"));
+}
+
+#[tokio::test]
+async fn set_avatar_over_size_limit_records_error() {
+    let app = spawn_app_with(|c| c.application.media.max_avatar_size = 1024).await;
+    let (_avatar_server, avatar_url) = serve_image_asset("jetbrains-logo.png").await;
+
+    let response = app.set_avatar(&app.api_client, &avatar_url).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    app.wait_for_jobs().await;
+    assert_eq!(get_avatar(&app).await, (None, None));
+    assert!(get_avatar_error(&app)
+        .await
+        .contains("File too large. Maximum size is 1KB"));
+}
+
+#[tokio::test]
+async fn set_avatar_with_too_many_pixels_records_error() {
+    let app = spawn_app_with(|c| c.application.media.max_image_pixels = 10_000).await;
+    let (_avatar_server, avatar_url) = serve_image_asset("jetbrains-logo.png").await;
+
+    app.set_avatar(&app.api_client, &avatar_url).await;
+    app.wait_for_jobs().await;
+
+    assert_eq!(get_avatar(&app).await, (None, None));
+    assert!(get_avatar_error(&app)
+        .await
+        .contains("Image size exceeds limit"));
+}
+
+#[tokio::test]
+async fn set_avatar_stores_square_crop_and_removes_it_on_change() {
+    let app = spawn_app_with(|c| c.application.media.avatar_size = 32).await;
+    let (_first_server, first_url) = serve_image_asset("jetbrains-logo.png").await;
+    let (_second_server, second_url) = serve_image_asset("jetbrains-logo.png").await;
+
+    app.set_avatar(&app.api_client, &first_url).await;
+    app.wait_for_jobs().await;
+    let (avatar_path, _) = get_avatar(&app).await;
+    let avatar_crop = avatar_path
+        .expect("User should have an avatar")
+        .replace(".png", "_32x32.png");
+
+    let crop = image::open(app.upload_path.join(&avatar_crop)).expect("Crop should be stored");
+    assert_eq!((crop.width(), crop.height()), (32, 32));
+
+    app.set_avatar(&app.api_client, &second_url).await;
+    app.wait_for_jobs().await;
+
+    assert!(!app.upload_path.join(&avatar_crop).exists());
+}
+
+#[tokio::test]
+async fn set_avatar_as_wide_as_the_crop_shows_the_crop() {
+    let app = spawn_app_with(|c| c.application.media.avatar_size = 80).await;
+    let mut avatar = std::io::Cursor::new(Vec::new());
+    image::RgbImage::new(80, 120)
+        .write_to(&mut avatar, image::ImageFormat::Png)
+        .expect("Failed to encode avatar");
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/avatar.png"))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(avatar.into_inner()))
+        .mount(&server)
+        .await;
+
+    app.set_avatar(&app.api_client, &format!("{}/avatar.png", server.uri()))
+        .await;
+    app.wait_for_jobs().await;
+    app.create_text_post("This is a sample post.").await;
+
+    let (avatar_path, _) = get_avatar(&app).await;
+    let avatar_crop = avatar_path
+        .expect("User should have an avatar")
+        .replace(".png", "_80x80.png");
+    let crop = image::open(app.upload_path.join(&avatar_crop)).expect("Crop should be stored");
+    assert_eq!((crop.width(), crop.height()), (80, 80));
+    let page: PostsPage = app
+        .api_client
+        .get(format!("{}/posts", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .json()
+        .await
+        .expect("Failed to parse page JSON.");
+    assert_eq!(
+        page.items[0].user_avatar_path.as_deref(),
+        Some(avatar_crop.as_str())
+    );
+}
+
+async fn assert_avatar_url_blocked(app: &TestApp, avatar_url: &str) {
+    let response = app.set_avatar(&app.api_client, avatar_url).await;
+
+    assert_eq!(response.status().as_u16(), 400, "{}", avatar_url);
+    let error_message = response
+        .text()
+        .await
+        .expect("Failed to read response text.");
+    assert!(
+        error_message.contains("Avatar URL is not allowed"),
+        "{}",
+        error_message
+    );
+    assert_eq!(get_avatar(app).await, (None, None));
+}
+
+#[tokio::test]
+async fn set_avatar_from_internal_addresses_is_blocked() {
+    let app = spawn_app_with(|c| c.application.fetch.allowed_networks.clear()).await;
+    let (_avatar_server, avatar_url) = serve_image_asset("jetbrains-logo.png").await;
+
+    for url in [
+        avatar_url.as_str(),
+        "http://169.254.169.254/latest/meta-data/",
+        "http://10.0.0.1/avatar.png",
+        "http://[::1]/avatar.png",
+        "http://[::ffff:127.0.0.1]/avatar.png",
+        // IPv4-compatible address embedding 127.0.0.1
+        "http://[::7f00:1]/avatar.png",
+        // Discard prefix
+        "http://[100::1]/avatar.png",
+        // 6to4 address embedding 10.0.0.1
+        "http://[2002:a00:1::]/avatar.png",
+        // Teredo address of a client behind 10.0.0.1
+        "http://[2001:0:4136:e378:8000:63bf:f5ff:fffe]/avatar.png",
+        "http://localhost/avatar.png",
+    ] {
+        assert_avatar_url_blocked(&app, url).await;
+    }
+}
+
+#[tokio::test]
+async fn set_avatar_from_denied_network_is_blocked_even_if_allowed() {
+    let app = spawn_app_with(|c| {
+        c.application.fetch.denied_networks = vec!["127.0.0.0/8".parse().unwrap()];
+    })
+    .await;
+    let (_avatar_server, avatar_url) = serve_image_asset("jetbrains-logo.png").await;
+
+    assert_avatar_url_blocked(&app, &avatar_url).await;
+}
+
+#[tokio::test]
+async fn set_avatar_redirect_is_checked_on_every_hop() {
+    let app = spawn_app().await;
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/redirect"))
+        .respond_with(
+            ResponseTemplate::new(302)
+                .insert_header("location", "http://169.254.169.254/latest/meta-data/"),
+        )
+        .mount(&server)
+        .await;
+
+    // The first hop is allowed, so the redirect is only caught by the download job.
+    let response = app
+        .set_avatar(&app.api_client, &format!("{}/redirect", server.uri()))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    app.wait_for_jobs().await;
+    assert_eq!(get_avatar(&app).await, (None, None));
+    assert!(get_avatar_error(&app)
+        .await
+        .contains("Avatar URL is not allowed"));
+}
+
+#[tokio::test]
+async fn set_avatar_redirect_to_a_host_name_is_followed() {
+    let app = spawn_app_with(|c| {
+        c.application.fetch.denied_hosts.clear();
+        c.application.fetch.allowed_networks =
+            vec!["127.0.0.1/32".parse().unwrap(), "::1/128".parse().unwrap()];
+    })
+    .await;
+    let server = MockServer::start().await;
+    let port = server.address().port();
+    Mock::given(method("GET"))
+        .and(path("/redirect"))
+        .respond_with(ResponseTemplate::new(302).insert_header(
+            "location",
+            format!("http://localhost:{}/avatar.png", port).as_str(),
+        ))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/avatar.png"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_bytes(get_image_asset("jetbrains-logo.png")),
+        )
+        .mount(&server)
+        .await;
+
+    app.set_avatar(&app.api_client, &format!("{}/redirect", server.uri()))
+        .await;
+    app.wait_for_jobs().await;
+
+    assert!(get_avatar(&app).await.0.is_some());
+    assert_eq!(server.received_requests().await.unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn set_avatar_download_from_slow_host_times_out() {
+    let app = spawn_app_with(|c| {
+        c.application.fetch.read_timeout_ms = 100;
+        c.application.jobs.max_attempts = 1;
+    })
+    .await;
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/avatar.png"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(get_image_asset("jetbrains-logo.png"))
+                .set_delay(Duration::from_secs(5)),
+        )
+        .mount(&server)
+        .await;
+
+    app.set_avatar(&app.api_client, &format!("{}/avatar.png", server.uri()))
+        .await;
+    app.wait_for_jobs().await;
+
+    assert_eq!(get_avatar(&app).await, (None, None));
+    assert!(!get_avatar_error(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn set_avatar_download_of_trickled_body_times_out() {
+    let app = spawn_app_with(|c| {
+        c.application.fetch.read_timeout_ms = 1000;
+        c.application.fetch.timeout_ms = 500;
+        c.application.jobs.max_attempts = 1;
+    })
+    .await;
+    // Sends a byte of the body every 100ms, never waiting long enough for
+    // the read timeout to fire.
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("Failed to bind trickling server");
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut request = [0; 1024];
+        let _ = stream.read(&mut request).await;
+        let _ = stream
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 1000\r\n\r\n")
+            .await;
+        for _ in 0..1000 {
+            if stream.write_all(b"x").await.is_err() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    });
+
+    let started = std::time::Instant::now();
+    app.set_avatar(&app.api_client, &format!("http://{}/avatar.png", address))
+        .await;
+    app.wait_for_jobs().await;
+
+    assert!(started.elapsed() < Duration::from_secs(5));
+    assert_eq!(get_avatar(&app).await, (None, None));
+    assert!(get_avatar_error(&app).await.contains("Timed out"));
+}
+
+#[tokio::test]
+async fn avatar_can_be_changed_while_a_download_is_slow() {
+    let app = spawn_app().await;
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/avatar.png"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(get_image_asset("jetbrains-logo.png"))
+                .set_delay(Duration::from_secs(3)),
+        )
+        .mount(&server)
+        .await;
+
+    app.set_avatar(&app.api_client, &format!("{}/avatar.png", server.uri()))
+        .await;
+    while server.received_requests().await.unwrap().is_empty() {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    // The download does not keep the user locked.
+    let started = std::time::Instant::now();
+    let response = app.set_avatar(&app.api_client, "").await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(started.elapsed() < Duration::from_secs(1));
+
+    app.wait_for_jobs().await;
+    assert_eq!(get_avatar(&app).await, (None, None));
+}
+
+#[tokio::test]
+async fn set_avatar_follows_redirects_up_to_the_limit() {
+    let app = spawn_app_with(|c| c.application.fetch.max_redirects = 1).await;
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/once"))
+        .respond_with(ResponseTemplate::new(302).insert_header("location", "/avatar.png"))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/twice"))
+        .respond_with(ResponseTemplate::new(302).insert_header("location", "/once"))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/avatar.png"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_bytes(get_image_asset("jetbrains-logo.png")),
+        )
+        .mount(&server)
+        .await;
+
+    app.set_avatar(&app.api_client, &format!("{}/twice", server.uri()))
+        .await;
+    app.wait_for_jobs().await;
+    assert_eq!(get_avatar(&app).await, (None, None));
+    assert!(get_avatar_error(&app)
+        .await
+        .contains("Stopped after 1 redirects"));
+
+    app.set_avatar(&app.api_client, &format!("{}/once", server.uri()))
+        .await;
+    app.wait_for_jobs().await;
+    assert!(get_avatar(&app).await.0.is_some());
+}
+
+#[tokio::test]
+async fn set_avatar_download_is_retried_after_a_transient_failure() {
+    let app = spawn_app().await;
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/avatar.png"))
+        .respond_with(ResponseTemplate::new(503))
+        .up_to_n_times(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/avatar.png"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_bytes(get_image_asset("jetbrains-logo.png")),
+        )
+        .mount(&server)
+        .await;
+
+    app.set_avatar(&app.api_client, &format!("{}/avatar.png", server.uri()))
+        .await;
+    app.wait_for_jobs().await;
+
+    assert!(get_avatar(&app).await.0.is_some());
+    assert_eq!(server.received_requests().await.unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn set_avatar_download_gives_up_after_max_attempts() {
+    let app = spawn_app_with(|c| c.application.jobs.max_attempts = 2).await;
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/avatar.png"))
+        .respond_with(ResponseTemplate::new(503))
+        .expect(2)
+        .mount(&server)
+        .await;
+
+    app.set_avatar(&app.api_client, &format!("{}/avatar.png", server.uri()))
+        .await;
+    app.wait_for_jobs().await;
+
+    assert_eq!(get_avatar(&app).await, (None, None));
+    assert_eq!(
+        get_avatar_error(&app).await,
+        "Failed to download avatar: HTTP 503 Service Unavailable"
+    );
+    let job = sqlx::query!("SELECT attempts, failed_at FROM jobs")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch job from database.");
+    assert_eq!(job.attempts, 2);
+    assert!(job.failed_at.is_some());
+}
+
+#[tokio::test]
+async fn set_avatar_without_session_returns_401() {
+    let app = spawn_app().await;
+
+    let response = app
+        .set_avatar(&reqwest::Client::new(), "https://example.com/avatar.png")
+        .await;
+
+    assert_eq!(response.status().as_u16(), 401);
+}
@@ -0,0 +1,209 @@
+use crate::helpers::{spawn_app, TestApp};
+use reqwest::multipart;
+use serde::Deserialize;
+use uuid::Uuid;
+
+#[derive(Deserialize)]
+struct SearchResult {
+    id: Uuid,
+    text: String,
+    rank: f32,
+    snippet: String,
+}
+
+#[derive(Deserialize)]
+struct SearchResults {
+    results: Vec<SearchResult>,
+    next_offset: Option<i64>,
+}
+
+async fn search(app: &TestApp, query: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .get(format!("{}/search?{}", &app.address, query))
+        .header("Accept", "application/json")
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn search_json(app: &TestApp, query: &str) -> SearchResults {
+    let response = search(app, query).await;
+    assert_eq!(response.status().as_u16(), 200);
+    response.json().await.expect("Failed to parse search JSON.")
+}
+
+async fn post_id(app: &TestApp, text: &str) -> Uuid {
+    sqlx::query_scalar!("SELECT id FROM blog_posts WHERE text = $1", text)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch post id from database.")
+}
+
+#[tokio::test]
+async fn search_ranks_matches_and_highlights_snippets() {
+    let app = spawn_app().await;
+    app.create_text_post("A short note that mentions Rust once.")
+        .await;
+    app.create_text_post("Rust ownership explained: Rust moves values, Rust borrows them.")
+        .await;
+    app.create_text_post("Cooking pasta tonight, nothing else.")
+        .await;
+
+    let found = search_json(&app, "q=rust").await;
+
+    let texts: Vec<&str> = found.results.iter().map(|r| r.text.as_str()).collect();
+    assert_eq!(
+        texts,
+        vec![
+            "Rust ownership explained: Rust moves values, Rust borrows them.",
+            "A short note that mentions Rust once."
+        ]
+    );
+    assert!(found.results[0].rank > found.results[1].rank);
+    assert!(found.results[1]
+        .snippet
+        .contains("mentions <mark>Rust</mark> once"));
+    assert_eq!(found.next_offset, None);
+
+    // Stemming matches other forms of the word.
+    let found = search_json(&app, "q=borrowing").await;
+    assert_eq!(found.results.len(), 1);
+}
+
+#[tokio::test]
+async fn search_snippets_escape_post_text() {
+    let app = spawn_app().await;
+    app.create_text_post("Beware: fish & chips < 5 <script>alert('rust')</script> in posts.")
+        .await;
+
+    let found = search_json(&app, "q=beware").await;
+
+    assert_eq!(found.results.len(), 1);
+    let snippet = &found.results[0].snippet;
+    assert!(snippet.starts_with("<mark>Beware</mark>: fish &amp; chips &lt; 5"));
+    assert!(!snippet.contains("<script"));
+}
+
+#[tokio::test]
+async fn search_filters_by_username_and_date_range() {
+    let app = spawn_app().await;
+    app.create_text_post("Old thoughts about databases.").await;
+    app.create_text_post("New thoughts about databases.").await;
+    let other = app.logged_in_client("other_user").await;
+    let form = multipart::Form::new().text("text", "Other thoughts about databases.");
+    let response = other
+        .post(format!("{}/posts", &app.address))
+        .multipart(form)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert!(response.status().is_success());
+
+    sqlx::query!(
+        "UPDATE blog_posts SET published_at = '2020-06-15T12:00:00Z' WHERE text = $1",
+        "Old thoughts about databases."
+    )
+    .execute(&app.db_pool)
+    .await
+    .expect("Failed to backdate post.");
+
+    let texts = |found: SearchResults| -> Vec<String> {
+        let mut texts: Vec<String> = found.results.into_iter().map(|r| r.text).collect();
+        texts.sort();
+        texts
+    };
+
+    let found = search_json(&app, "q=databases&username=other_user").await;
+    assert_eq!(texts(found), vec!["Other thoughts about databases."]);
+
+    let found = search_json(&app, "q=databases&from=2020-06-15&to=2020-06-15").await;
+    assert_eq!(texts(found), vec!["Old thoughts about databases."]);
+
+    let found = search_json(&app, "q=databases&username=valid_user&from=2021-01-01&to=").await;
+    assert_eq!(texts(found), vec!["New thoughts about databases."]);
+}
+
+#[tokio::test]
+async fn search_by_username_leaves_out_posts_without_author_of_that_name() {
+    let app = spawn_app().await;
+    app.create_text_post("Thoughts about databases.").await;
+    sqlx::query!(
+        "INSERT INTO blog_posts (id, text, text_html, username) VALUES ($1, $2, $3, $4)",
+        Uuid::new_v4(),
+        "Legacy thoughts about databases.",
+        "<p>Legacy thoughts about databases.</p>",
+        "valid_user"
+    )
+    .execute(&app.db_pool)
+    .await
+    .expect("Failed to insert post.");
+
+    let found = search_json(&app, "q=databases&username=valid_user").await;
+    let texts: Vec<String> = found.results.into_iter().map(|r| r.text).collect();
+    assert_eq!(texts, vec!["Thoughts about databases."]);
+
+    let found = search_json(&app, "q=databases&username=nobody").await;
+    assert!(found.results.is_empty());
+}
+
+#[tokio::test]
+async fn search_index_follows_post_updates() {
+    let app = spawn_app().await;
+    let text = "Initially about gardening.";
+    app.create_text_post(text).await;
+    let id = post_id(&app, text).await;
+
+    let form = multipart::Form::new().text("text", "Now about astronomy instead.");
+    let response = app
+        .api_client
+        .patch(format!("{}/posts/{}", &app.address, id))
+        .multipart(form)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 200);
+
+    assert!(search_json(&app, "q=gardening").await.results.is_empty());
+    let found = search_json(&app, "q=astronomy").await;
+    assert_eq!(found.results.len(), 1);
+    assert_eq!(found.results[0].id, id);
+}
+
+#[tokio::test]
+async fn search_page_shows_results_and_next_page_link() {
+    let app = spawn_app().await;
+    for i in 0..3 {
+        app.create_text_post(&format!("Searchable post number {}.", i))
+            .await;
+    }
+
+    let html = reqwest::Client::new()
+        .get(format!("{}/search?q=searchable&limit=2", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .text()
+        .await
+        .expect("Failed to read response text.");
+
+    assert_eq!(html.matches("<mark>Searchable</mark>").count(), 2);
+    assert!(html.contains(r#"href="/search?q=searchable&amp;limit=2&amp;offset=2""#));
+
+    let found = search_json(&app, "q=searchable&limit=2&offset=2").await;
+    assert_eq!(found.results.len(), 1);
+    assert_eq!(found.next_offset, None);
+}
+
+#[tokio::test]
+async fn invalid_search_returns_400() {
+    let app = spawn_app().await;
+
+    for query in [
+        format!("q={}", "a".repeat(201)),
+        "q=rust&from=2024-02-01&to=2024-01-01".to_string(),
+        "q=rust&from=yesterday".to_string(),
+    ] {
+        let response = search(&app, &query).await;
+        assert_eq!(response.status().as_u16(), 400, "query {}", query);
+    }
+}
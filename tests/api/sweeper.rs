@@ -0,0 +1,132 @@
+use std::{collections::BTreeSet, fs, time::Duration};
+
+use crate::helpers::{get_image_asset, spawn_app, spawn_app_with, TestApp};
+use jetbrains_web_app::{
+    configuration::{OrphanAction, SweeperSettings},
+    storage::local::LocalStorage,
+    sweeper::sweep_orphans,
+};
+
+const ORPHAN: &str = "orphan.png";
+
+fn stored_files(app: &TestApp) -> BTreeSet<String> {
+    fs::read_dir(&app.upload_path)
+        .expect("Failed to read upload directory.")
+        .map(|entry| entry.unwrap())
+        .filter(|entry| entry.file_type().unwrap().is_file())
+        .map(|entry| entry.file_name().into_string().unwrap())
+        .collect()
+}
+
+fn write_orphan(app: &TestApp) {
+    fs::write(
+        app.upload_path.join(ORPHAN),
+        get_image_asset("jetbrains-logo.png"),
+    )
+    .expect("Failed to write orphaned file.");
+}
+
+async fn sweep(app: &TestApp, grace_period_secs: u64, action: OrphanAction) -> Vec<String> {
+    let settings = SweeperSettings {
+        interval_secs: 0,
+        grace_period_secs,
+        action,
+    };
+    let storage = LocalStorage::new(app.upload_path.clone());
+
+    sweep_orphans(&app.db_pool, &storage, &settings)
+        .await
+        .expect("Failed to sweep orphaned files.")
+        .orphans
+}
+
+#[tokio::test]
+async fn sweep_deletes_only_unreferenced_files() {
+    let app = spawn_app().await;
+    app.create_image_post().await;
+    let referenced = stored_files(&app);
+    assert!(!referenced.is_empty());
+    write_orphan(&app);
+
+    let orphans = sweep(&app, 0, OrphanAction::Delete).await;
+
+    assert_eq!(orphans, vec![ORPHAN.to_string()]);
+    assert_eq!(stored_files(&app), referenced);
+}
+
+#[tokio::test]
+async fn sweep_keeps_content_addressed_files_of_existing_media() {
+    let app = spawn_app().await;
+    let (_, image_path) = app.create_image_post().await;
+    // Not referenced by any row, but named after media that is still stored,
+    // as a file stored again after the references were read would be.
+    let unrecorded = image_path.replace(".png", "_999.png");
+    fs::write(
+        app.upload_path.join(&unrecorded),
+        get_image_asset("jetbrains-logo.png"),
+    )
+    .expect("Failed to write content-addressed file.");
+
+    let orphans = sweep(&app, 0, OrphanAction::Delete).await;
+
+    assert!(orphans.is_empty());
+    assert!(app.upload_path.join(&unrecorded).exists());
+}
+
+#[tokio::test]
+async fn sweep_keeps_uploads_waiting_to_be_processed() {
+    let app = spawn_app_with(|c| c.application.jobs.workers = 0).await;
+    app.submit_image_post().await;
+    let pending = stored_files(&app);
+    assert_eq!(pending.len(), 1);
+
+    let orphans = sweep(&app, 0, OrphanAction::Delete).await;
+
+    assert!(orphans.is_empty());
+    assert_eq!(stored_files(&app), pending);
+}
+
+#[tokio::test]
+async fn sweep_keeps_orphans_within_the_grace_period() {
+    let app = spawn_app().await;
+    write_orphan(&app);
+
+    let orphans = sweep(&app, 3600, OrphanAction::Delete).await;
+
+    assert!(orphans.is_empty());
+    assert!(app.upload_path.join(ORPHAN).exists());
+}
+
+#[tokio::test]
+async fn sweep_can_quarantine_orphans() {
+    let app = spawn_app().await;
+    write_orphan(&app);
+
+    let orphans = sweep(&app, 0, OrphanAction::Quarantine).await;
+
+    assert_eq!(orphans, vec![ORPHAN.to_string()]);
+    assert!(!app.upload_path.join(ORPHAN).exists());
+    assert!(app.upload_path.join("quarantine").join(ORPHAN).exists());
+
+    // Quarantined files are not listed again.
+    assert!(sweep(&app, 0, OrphanAction::Quarantine).await.is_empty());
+}
+
+#[tokio::test]
+async fn periodic_sweep_removes_orphans() {
+    let app = spawn_app_with(|c| {
+        c.application.sweeper.interval_secs = 1;
+        c.application.sweeper.grace_period_secs = 0;
+        c.application.sweeper.action = OrphanAction::Delete;
+    })
+    .await;
+    write_orphan(&app);
+
+    for _ in 0..50 {
+        if !app.upload_path.join(ORPHAN).exists() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("Orphaned file was not swept");
+}
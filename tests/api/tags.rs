@@ -0,0 +1,181 @@
+use crate::helpers::{spawn_app, TestApp};
+use jetbrains_web_app::domain::{get_post_by_id, BlogPost};
+use reqwest::multipart;
+use serde::Deserialize;
+use uuid::Uuid;
+
+async fn create_tagged_post(app: &TestApp, text: &str, tags: Option<&str>) -> Uuid {
+    assert!(app.create_post(text, tags).await.status().is_success());
+
+    sqlx::query_scalar!("SELECT id FROM blog_posts ORDER BY published_at DESC, id DESC LIMIT 1")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch post id from database.")
+}
+
+async fn get_tags(app: &TestApp, id: Uuid) -> Vec<String> {
+    get_post_by_id(&app.db_pool, id)
+        .await
+        .expect("Failed to fetch post from database.")
+        .expect("Post should exist")
+        .tags
+}
+
+async fn update_post(
+    app: &TestApp,
+    method: reqwest::Method,
+    id: Uuid,
+    form: multipart::Form,
+) -> reqwest::Response {
+    app.api_client
+        .request(method, format!("{}/posts/{}", &app.address, id))
+        .multipart(form)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+#[tokio::test]
+async fn hashtags_and_tags_field_are_saved_and_linked() {
+    let app = spawn_app().await;
+    let text = "Learning #Rust today, with #async_await!\n\n\
+                Not tags: `#include`, https://example.com/#anchor, issue #42 and C&#35;\n\n\
+                ```c\n#define ANSWER 42\n```";
+
+    let id = create_tagged_post(&app, text, Some("web, #Backend rust")).await;
+
+    assert_eq!(
+        get_tags(&app, id).await,
+        vec!["async_await", "backend", "rust", "web"]
+    );
+
+    let html = reqwest::Client::new()
+        .get(format!("{}/posts/{}", &app.address, id))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .text()
+        .await
+        .expect("Failed to read response text.");
+    assert!(html.contains(r##"<a href="/tags/rust" class="tag-link">#rust</a>"##));
+    assert!(html.contains(r##"<a href="/tags/backend" class="tag-link">#backend</a>"##));
+}
+
+#[tokio::test]
+async fn invalid_or_too_many_tags_return_400() {
+    let app = spawn_app().await;
+
+    for tags in ["not/valid", &"x".repeat(51), "a b c d e f g h i j k"] {
+        let response = app
+            .create_post("This is a sample post text.", Some(tags))
+            .await;
+        assert_eq!(response.status().as_u16(), 400, "tags {}", tags);
+    }
+
+    let count = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM blog_posts"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to count posts.");
+    assert_eq!(count, 0);
+}
+
+#[tokio::test]
+async fn hashtags_do_not_count_toward_the_tag_limit() {
+    let app = spawn_app().await;
+    let hashtags: Vec<String> = (0..15).map(|i| format!("#topic{}", i)).collect();
+    let text = format!("A long post about {}", hashtags.join(" "));
+    let explicit = "a b c d e f g h i j";
+
+    let id = create_tagged_post(&app, &text, Some(explicit)).await;
+
+    assert_eq!(get_tags(&app, id).await.len(), 25);
+}
+
+#[tokio::test]
+async fn duplicate_tags_count_once_toward_the_tag_limit() {
+    let app = spawn_app().await;
+    let explicit = "a A #a a a a a a a a a a b";
+
+    let id = create_tagged_post(&app, "This is a sample post text.", Some(explicit)).await;
+
+    assert_eq!(get_tags(&app, id).await, vec!["a", "b"]);
+}
+
+#[derive(Deserialize)]
+struct PostsPage {
+    items: Vec<BlogPost>,
+}
+
+#[tokio::test]
+async fn tag_page_lists_only_posts_with_the_tag() {
+    let app = spawn_app().await;
+    create_tagged_post(&app, "All about #rust programming.", None).await;
+    create_tagged_post(&app, "Something else entirely.", Some("cooking")).await;
+
+    let html = reqwest::Client::new()
+        .get(format!("{}/tags/Rust", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .text()
+        .await
+        .expect("Failed to read response text.");
+    assert!(html.contains("Posts tagged #rust"));
+    assert!(html.contains("All about"));
+    assert!(!html.contains("Something else entirely."));
+
+    let page: PostsPage = reqwest::Client::new()
+        .get(format!("{}/tags/cooking", &app.address))
+        .header("Accept", "application/json")
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .json()
+        .await
+        .expect("Failed to parse posts JSON.");
+    assert_eq!(page.items.len(), 1);
+    assert_eq!(page.items[0].text, "Something else entirely.");
+}
+
+#[tokio::test]
+async fn invalid_tag_in_path_returns_404() {
+    let app = spawn_app().await;
+
+    let response = reqwest::Client::new()
+        .get(format!("{}/tags/not%20a%20tag", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn patch_keeps_explicit_tags_and_follows_hashtags_of_new_text() {
+    let app = spawn_app().await;
+    let id = create_tagged_post(&app, "First version about #rust.", Some("web")).await;
+
+    let form = multipart::Form::new().text("text", "Second version about #async.");
+    let response = update_post(&app, reqwest::Method::PATCH, id, form).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(get_tags(&app, id).await, vec!["async", "web"]);
+
+    let form = multipart::Form::new().text("tags", "databases");
+    let response = update_post(&app, reqwest::Method::PATCH, id, form).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(get_tags(&app, id).await, vec!["async", "databases"]);
+}
+
+#[tokio::test]
+async fn put_without_tags_field_keeps_only_hashtags() {
+    let app = spawn_app().await;
+    let id = create_tagged_post(&app, "First version about #rust.", Some("web")).await;
+
+    let form = multipart::Form::new().text("text", "Replaced text about #postgres.");
+    let response = update_post(&app, reqwest::Method::PUT, id, form).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(get_tags(&app, id).await, vec!["postgres"]);
+}
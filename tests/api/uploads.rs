@@ -0,0 +1,187 @@
+use crate::helpers::{create_temp_image_dir, get_image_asset, spawn_app, spawn_app_with};
+use jetbrains_web_app::{
+    configuration::{S3Settings, StorageSettings},
+    storage::{local::LocalStorage, s3::S3Storage, Storage, StorageError},
+};
+use wiremock::{
+    matchers::{method, path, path_regex},
+    Mock, MockServer, ResponseTemplate,
+};
+
+#[tokio::test]
+async fn stored_upload_is_served_with_its_content_type() {
+    let app = spawn_app().await;
+    let (_, image_path) = app.create_image_post().await;
+
+    let response = reqwest::Client::new()
+        .get(format!("{}/uploads/{}", &app.address, image_path))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers().get("content-type").unwrap(), "image/png");
+    let body = response.bytes().await.expect("Failed to read body.");
+    assert_eq!(
+        body.as_ref(),
+        std::fs::read(app.upload_path.join(&image_path)).unwrap()
+    );
+}
+
+#[tokio::test]
+async fn raw_upload_waiting_to_be_processed_is_not_served() {
+    let app = spawn_app_with(|c| c.application.jobs.workers = 0).await;
+    let id = app.submit_image_post().await;
+    let upload_key = sqlx::query_scalar!(
+        r#"SELECT pending_image_key AS "key!" FROM blog_posts WHERE id = $1"#,
+        id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to fetch pending upload from database.");
+    assert!(app.upload_path.join(&upload_key).exists());
+
+    let response = reqwest::Client::new()
+        .get(format!("{}/uploads/{}", &app.address, upload_key))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn unknown_or_invalid_upload_keys_return_404() {
+    let app = spawn_app().await;
+
+    for key in ["missing.png", "..%2FCargo.toml", ".hidden"] {
+        let response = reqwest::Client::new()
+            .get(format!("{}/uploads/{}", &app.address, key))
+            .send()
+            .await
+            .expect("Failed to execute request.");
+
+        assert_eq!(response.status().as_u16(), 404, "{}", key);
+    }
+}
+
+#[tokio::test]
+async fn s3_backend_stores_and_serves_uploads_from_the_bucket() {
+    let s3_server = MockServer::start().await;
+    Mock::given(method("PUT"))
+        .and(path_regex(r"^/blog/[0-9a-f]{64}\.png$"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&s3_server)
+        .await;
+    // Raw uploads wait in the bucket until the image job has processed them.
+    Mock::given(method("PUT"))
+        .and(path_regex(r"^/blog/incoming_"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&s3_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/blog/incoming_"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_bytes(get_image_asset("jetbrains-logo.png")),
+        )
+        .expect(1)
+        .mount(&s3_server)
+        .await;
+    Mock::given(method("DELETE"))
+        .and(path_regex(r"^/blog/incoming_"))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&s3_server)
+        .await;
+
+    let endpoint = s3_server.uri();
+    let app = spawn_app_with(|c| {
+        c.application.storage = StorageSettings::S3(S3Settings {
+            endpoint,
+            region: "us-east-1".to_string(),
+            bucket: "blog".to_string(),
+            access_key: "minioadmin".to_string(),
+            secret_key: "minioadmin".to_string(),
+            path_style: true,
+        })
+    })
+    .await;
+
+    let (_, image_path) = app.create_image_post().await;
+
+    // Nothing is written to the local upload directory.
+    assert!(!app.upload_path.join(&image_path).exists());
+    let requests = s3_server.received_requests().await.unwrap();
+    let uploaded = requests
+        .iter()
+        .find(|request| {
+            request.method == wiremock::http::Method::PUT && request.url.path().ends_with(".png")
+        })
+        .expect("Image should be uploaded");
+    assert_eq!(uploaded.headers.get("content-type").unwrap(), "image/png");
+
+    Mock::given(method("GET"))
+        .and(path(format!("/blog/{}", image_path)))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("content-type", "image/png")
+                .set_body_bytes(uploaded.body.clone()),
+        )
+        .mount(&s3_server)
+        .await;
+
+    let response = reqwest::Client::new()
+        .get(format!("{}/uploads/{}", &app.address, image_path))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response
+            .bytes()
+            .await
+            .expect("Failed to read body.")
+            .as_ref(),
+        uploaded.body.as_slice()
+    );
+}
+
+#[tokio::test]
+async fn both_backends_reject_the_same_invalid_keys() {
+    let s3_server = MockServer::start().await;
+    let s3 = S3Storage::new(&S3Settings {
+        endpoint: s3_server.uri(),
+        region: "us-east-1".to_string(),
+        bucket: "blog".to_string(),
+        access_key: "minioadmin".to_string(),
+        secret_key: "minioadmin".to_string(),
+        path_style: true,
+    })
+    .expect("Failed to build S3 storage");
+    let local = LocalStorage::new(create_temp_image_dir());
+    let file = tempfile::NamedTempFile::new().expect("Failed to create temp file");
+
+    for storage in [&s3 as &dyn Storage, &local] {
+        for key in ["../escape.png", "/absolute.png", "control\u{7}.png", ""] {
+            let results = [
+                storage.put(key, Vec::new(), "image/png").await,
+                storage.put_file(key, file.path(), "image/png").await,
+                storage.get(key).await.map(|_| ()),
+                storage.delete(key).await,
+                storage.quarantine(key).await,
+            ];
+            for result in results {
+                assert!(
+                    matches!(result, Err(StorageError::InvalidKey(_))),
+                    "{:?}",
+                    key
+                );
+            }
+        }
+    }
+
+    assert!(s3_server.received_requests().await.unwrap().is_empty());
+}